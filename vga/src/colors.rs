@@ -1,5 +1,8 @@
 //! Common color structures used in vga programming.
 
+use super::Error;
+use core::convert::TryFrom;
+
 /// Represents the size of the vga palette in bytes.
 pub const PALETTE_SIZE: usize = 768;
 
@@ -47,6 +50,32 @@ impl From<Color16> for u8 {
     }
 }
 
+impl TryFrom<u8> for Color16 {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x0 => Ok(Color16::Black),
+            0x1 => Ok(Color16::Blue),
+            0x2 => Ok(Color16::Green),
+            0x3 => Ok(Color16::Cyan),
+            0x4 => Ok(Color16::Red),
+            0x5 => Ok(Color16::Magenta),
+            0x6 => Ok(Color16::Brown),
+            0x7 => Ok(Color16::LightGrey),
+            0x8 => Ok(Color16::DarkGrey),
+            0x9 => Ok(Color16::LightBlue),
+            0xA => Ok(Color16::LightGreen),
+            0xB => Ok(Color16::LightCyan),
+            0xC => Ok(Color16::LightRed),
+            0xD => Ok(Color16::Pink),
+            0xE => Ok(Color16::Yellow),
+            0xF => Ok(Color16::White),
+            _ => Err(Error::InvalidColor(value)),
+        }
+    }
+}
+
 /// Represents a color for vga text modes.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(transparent)]
@@ -70,6 +99,18 @@ impl TextModeColor {
     }
 }
 
+impl From<TextModeColor> for u8 {
+    fn from(value: TextModeColor) -> u8 {
+        value.0
+    }
+}
+
+impl From<u8> for TextModeColor {
+    fn from(value: u8) -> TextModeColor {
+        TextModeColor(value)
+    }
+}
+
 /// Represents the default vga 256 color palette.
 pub const DEFAULT_PALETTE: [u8; PALETTE_SIZE] = [
     0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x2a, 0x00, 0x00, 0x2a, 0x2a, 0x2a, 0x00, 0x00, 0x2a,
@@ -0,0 +1,146 @@
+use super::Point;
+
+/// Represents which pixels of a `Bitmap` are transparent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transparency<'a, Color> {
+    /// Every pixel is drawn.
+    None,
+    /// Pixels of the given color are skipped.
+    ColorKey(Color),
+    /// Pixels are only drawn where the corresponding bit of the mask is set.
+    ///
+    /// The mask holds one bit per pixel, most significant bit first, with
+    /// every row padded to a whole number of bytes.
+    Mask(&'a [u8]),
+}
+
+/// A rectangular image, with its pixels stored row by row in the color
+/// type of a graphics mode.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use vga::colors::Color16;
+/// use vga::drawing::Bitmap;
+///
+/// const X: Color16 = Color16::White;
+/// const O: Color16 = Color16::Black;
+/// static PIXELS: [Color16; 9] = [O, X, O, X, X, X, O, X, O];
+///
+/// let cross = Bitmap::new(3, 3, &PIXELS).with_color_key(O);
+/// assert_eq!(cross.get_pixel(1, 0), Some(X));
+/// assert_eq!(cross.get_pixel(0, 0), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bitmap<'a, Color> {
+    width: usize,
+    height: usize,
+    pixels: &'a [Color],
+    transparency: Transparency<'a, Color>,
+}
+
+impl<'a, Color: Copy + PartialEq> Bitmap<'a, Color> {
+    /// Creates a new opaque `Bitmap` of `width` by `height` pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` holds less than `width * height` pixels.
+    pub fn new(width: usize, height: usize, pixels: &'a [Color]) -> Bitmap<'a, Color> {
+        assert!(
+            pixels.len() >= width * height,
+            "Bitmap pixels are too short for its dimensions!"
+        );
+        Bitmap {
+            width,
+            height,
+            pixels,
+            transparency: Transparency::None,
+        }
+    }
+
+    /// Makes every pixel of this `Bitmap` with the color `key` transparent.
+    pub fn with_color_key(mut self, key: Color) -> Bitmap<'a, Color> {
+        self.transparency = Transparency::ColorKey(key);
+        self
+    }
+
+    /// Only draws the pixels of this `Bitmap` whose bit is set in `mask`.
+    /// See `Transparency::Mask` for its layout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mask` is too short for the dimensions of this `Bitmap`.
+    pub fn with_mask(mut self, mask: &'a [u8]) -> Bitmap<'a, Color> {
+        assert!(
+            mask.len() >= self.width.div_ceil(8) * self.height,
+            "Bitmap mask is too short for its dimensions!"
+        );
+        self.transparency = Transparency::Mask(mask);
+        self
+    }
+
+    /// Returns the width of this `Bitmap` in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of this `Bitmap` in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the `Transparency` of this `Bitmap`.
+    pub fn transparency(&self) -> Transparency<'a, Color> {
+        self.transparency
+    }
+
+    /// Returns the pixels of row `y`, ignoring transparency.
+    pub fn row(&self, y: usize) -> &'a [Color] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    /// Returns the color of the pixel at `(x, y)`, or `None` if
+    /// it's transparent.
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        let color = self.pixels[y * self.width + x];
+        let opaque = match self.transparency {
+            Transparency::None => true,
+            Transparency::ColorKey(key) => color != key,
+            Transparency::Mask(mask) => {
+                let stride = self.width.div_ceil(8);
+                mask[y * stride + x / 8] & (0x80 >> (x & 0x7)) != 0
+            }
+        };
+        if opaque {
+            Some(color)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the part of this `Bitmap` that is visible when drawn at
+    /// `position` on a screen of `width` by `height` pixels, as the
+    /// `(x, y)` of its first visible pixel, the matching screen position,
+    /// and the visible `(width, height)`, or `None` if nothing is visible.
+    pub(crate) fn clip(
+        &self,
+        position: Point<isize>,
+        width: usize,
+        height: usize,
+    ) -> Option<(Point<usize>, Point<usize>, Point<usize>)> {
+        let left = position.0.max(0);
+        let top = position.1.max(0);
+        let right = (position.0 + self.width as isize).min(width as isize);
+        let bottom = (position.1 + self.height as isize).min(height as isize);
+        if left >= right || top >= bottom {
+            return None;
+        }
+        Some((
+            ((left - position.0) as usize, (top - position.1) as usize),
+            (left as usize, top as usize),
+            ((right - left) as usize, (bottom - top) as usize),
+        ))
+    }
+}
@@ -28,6 +28,68 @@ impl<T: SignedNum> Bresenham<T> {
             error: delta_y - delta_x,
         }
     }
+
+    /// Creates a line from `start` to `end` that only yields the points
+    /// inside of the rectangle from `min` to `max` (inclusive).
+    ///
+    /// The yielded points are exactly the ones `new` would yield inside of
+    /// the rectangle, without stepping through the points outside of it.
+    #[inline]
+    pub fn clipped(start: Point<T>, end: Point<T>, min: Point<T>, max: Point<T>) -> Self {
+        let mut line = Self::new(start, end);
+        let start = line.point;
+
+        // The clipping rectangle, as seen from inside of the octant.
+        let (corner_a, corner_b) = (line.octant.to(min), line.octant.to(max));
+        let min = (corner_a.0.min(corner_b.0), corner_a.1.min(corner_b.1));
+        let max = (corner_a.0.max(corner_b.0), corner_a.1.max(corner_b.1));
+
+        // Inside of the octant, the point at step `k` is
+        // `(start.0 + k, start.1 + floor(k * delta_y / delta_x))`, and both
+        // coordinates only ever increase, so the steps inside of the
+        // rectangle form a single range.
+        let mut first = T::zero().max(min.0 - start.0);
+        let mut last = line.delta_x.min(max.0 - start.0);
+        if line.delta_y == T::zero() {
+            if start.1 < min.1 || start.1 > max.1 {
+                last = first - T::one();
+            }
+        } else {
+            first = first.max(div_ceil((min.1 - start.1) * line.delta_x, line.delta_y));
+            last = last.min(div_floor(
+                (max.1 - start.1 + T::one()) * line.delta_x - T::one(),
+                line.delta_y,
+            ));
+        }
+
+        let steps = if line.delta_x == T::zero() {
+            T::zero()
+        } else {
+            div_floor(first * line.delta_y, line.delta_x)
+        };
+        line.point = (start.0 + first, start.1 + steps);
+        line.error += first * line.delta_y - steps * line.delta_x;
+        line.end_x = start.0 + last;
+        line
+    }
+}
+
+fn div_floor<T: SignedNum>(numerator: T, denominator: T) -> T {
+    let quotient = numerator / denominator;
+    if numerator % denominator != T::zero() && numerator < T::zero() {
+        quotient - T::one()
+    } else {
+        quotient
+    }
+}
+
+fn div_ceil<T: SignedNum>(numerator: T, denominator: T) -> T {
+    let quotient = numerator / denominator;
+    if numerator % denominator != T::zero() && numerator > T::zero() {
+        quotient + T::one()
+    } else {
+        quotient
+    }
 }
 
 impl<T> Iterator for Bresenham<T>
@@ -55,3 +117,68 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MIN: Point<isize> = (0, 0);
+    const MAX: Point<isize> = (15, 11);
+
+    fn assert_clipped(start: Point<isize>, end: Point<isize>) {
+        let inside = |&(x, y): &Point<isize>| x >= MIN.0 && x <= MAX.0 && y >= MIN.1 && y <= MAX.1;
+        assert!(
+            Bresenham::new(start, end)
+                .filter(inside)
+                .eq(Bresenham::clipped(start, end, MIN, MAX)),
+            "{:?} -> {:?}",
+            start,
+            end
+        );
+    }
+
+    #[test]
+    fn test_clipped_octants() {
+        // Lines leaving the rectangle through every octant.
+        let center = (7, 5);
+        let ends = [
+            (40, 9),
+            (11, 40),
+            (3, 40),
+            (-40, 9),
+            (-40, 1),
+            (3, -40),
+            (11, -40),
+            (40, 1),
+        ];
+        for (octant, end) in ends.iter().enumerate() {
+            assert_eq!(Octant::new(center, *end).value, octant as u8);
+            assert_clipped(center, *end);
+            assert_clipped(*end, center);
+        }
+    }
+
+    #[test]
+    fn test_clipped_outside() {
+        // Lines starting and ending outside of the rectangle.
+        for &start in &[(-5, -3), (20, -3), (-5, 14), (20, 14), (7, -20), (-20, 5)] {
+            for end_x in -6..22 {
+                for end_y in -4..16 {
+                    assert_clipped(start, (end_x, end_y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_clipped_degenerate() {
+        assert_clipped((3, 3), (3, 3));
+        assert_clipped((-3, 3), (-3, 3));
+        assert_clipped((-10, 4), (30, 4));
+        assert_clipped((4, -10), (4, 30));
+        assert_clipped((4, 20), (4, 30));
+        assert!(Bresenham::clipped((-10, -10), (-20, 30), MIN, MAX)
+            .next()
+            .is_none());
+    }
+}
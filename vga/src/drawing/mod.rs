@@ -2,11 +2,23 @@
 //! Original implementation here https://github.com/expenses/line_drawing.
 use num_traits::{NumAssignOps, NumCast, Signed};
 
+mod bitmap;
 mod bresenham;
 mod octant;
+mod shapes;
+mod text;
+
+pub use bitmap::{Bitmap, Transparency};
 
 pub(crate) use bresenham::Bresenham;
 use octant::Octant;
+pub use shapes::{
+    draw_arc, draw_circle, draw_ellipse, draw_polygon, draw_polyline, draw_rect, fill_circle,
+    fill_ellipse, fill_polygon, fill_rect, flood_fill, FLOOD_FILL_STACK_SIZE,
+};
+pub use text::{
+    draw_text, draw_text_wrapped, Alignment, FontSource, TextStyle, GLYPH_WIDTH, MAX_GLYPH_HEIGHT,
+};
 
 /// A point in 2D space.
 pub type Point<T> = (T, T);
@@ -4,7 +4,7 @@ use num_traits::Zero;
 
 /// A simple octant struct for transforming line points.
 pub struct Octant {
-    value: u8,
+    pub(super) value: u8,
 }
 
 impl Octant {
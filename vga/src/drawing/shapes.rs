@@ -0,0 +1,648 @@
+use super::Point;
+use crate::writers::{DrawTarget, GraphicsWriter, Screen};
+
+/// The maximum number of pending spans `flood_fill` keeps track of.
+pub const FLOOD_FILL_STACK_SIZE: usize = 256;
+
+/// Draws the outline of the rectangle with the corners `start` and `end`
+/// (inclusive) with the specified `color`.
+pub fn draw_rect<W, Color>(writer: &W, start: Point<isize>, end: Point<isize>, color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    let ((left, top), (right, bottom)) = normalize(start, end);
+    writer.with_draw_target(&mut |target| {
+        target.fill_rect((left, top), (right, top), color);
+        target.fill_rect((left, bottom), (right, bottom), color);
+        if bottom - top > 1 {
+            target.fill_rect((left, top + 1), (left, bottom - 1), color);
+            target.fill_rect((right, top + 1), (right, bottom - 1), color);
+        }
+    });
+}
+
+/// Fills the rectangle with the corners `start` and `end` (inclusive)
+/// with the specified `color`.
+pub fn fill_rect<W, Color>(writer: &W, start: Point<isize>, end: Point<isize>, color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    writer.with_draw_target(&mut |target| target.fill_rect(start, end, color));
+}
+
+/// Draws the outline of the circle around `center` with the specified
+/// `radius` and `color`, using the midpoint circle algorithm.
+pub fn draw_circle<W, Color>(writer: &W, center: Point<isize>, radius: isize, color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    writer.with_draw_target(&mut |target| {
+        circle_points(radius, |x, y| {
+            for &(x, y) in &octant_points(x, y) {
+                draw_pixel::<W, _>(target, center.0 + x, center.1 + y, color);
+            }
+        });
+    });
+}
+
+/// Fills the circle around `center` with the specified `radius` and `color`.
+pub fn fill_circle<W, Color>(writer: &W, center: Point<isize>, radius: isize, color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    writer.with_draw_target(&mut |target| {
+        circle_points(radius, |x, y| {
+            draw_span(target, center.0 - x, center.0 + x, center.1 + y, color);
+            draw_span(target, center.0 - x, center.0 + x, center.1 - y, color);
+            draw_span(target, center.0 - y, center.0 + y, center.1 + x, color);
+            draw_span(target, center.0 - y, center.0 + y, center.1 - x, color);
+        });
+    });
+}
+
+/// Draws the part of the circle around `center` with the specified
+/// `radius` and `color` from `start_angle` to `end_angle`.
+///
+/// Angles are in degrees, measured counterclockwise (as seen on the
+/// screen) from the positive x axis. The arc is drawn counterclockwise from
+/// `start_angle`, and a full circle is drawn if the angles are at least
+/// 360 degrees apart.
+pub fn draw_arc<W, Color>(
+    writer: &W,
+    center: Point<isize>,
+    radius: isize,
+    start_angle: isize,
+    end_angle: isize,
+    color: Color,
+) where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    let span = end_angle - start_angle;
+    let full_circle = span >= 360 || span <= -360;
+    let span = span.rem_euclid(360);
+    let start = direction(start_angle);
+    let end = direction(end_angle);
+
+    writer.with_draw_target(&mut |target| {
+        circle_points(radius, |x, y| {
+            for &(x, y) in &octant_points(x, y) {
+                // Flip the y axis, so angles increase counterclockwise on the screen.
+                let point = (x, -y);
+                let inside = if full_circle {
+                    true
+                } else if span <= 180 {
+                    cross(start, point) >= 0 && cross(point, end) >= 0
+                } else {
+                    !(cross(end, point) > 0 && cross(point, start) > 0)
+                };
+                if inside {
+                    draw_pixel::<W, _>(target, center.0 + x, center.1 + y, color);
+                }
+            }
+        });
+    });
+}
+
+/// Draws the outline of the axis aligned ellipse around `center` with the
+/// specified `radii` along the x and y axis, and the specified `color`.
+pub fn draw_ellipse<W, Color>(writer: &W, center: Point<isize>, radii: Point<isize>, color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    writer.with_draw_target(&mut |target| {
+        ellipse_points(radii, |x, y| {
+            draw_pixel::<W, _>(target, center.0 + x, center.1 + y, color);
+            draw_pixel::<W, _>(target, center.0 - x, center.1 + y, color);
+            draw_pixel::<W, _>(target, center.0 + x, center.1 - y, color);
+            draw_pixel::<W, _>(target, center.0 - x, center.1 - y, color);
+        });
+    });
+}
+
+/// Fills the axis aligned ellipse around `center` with the specified
+/// `radii` along the x and y axis, and the specified `color`.
+pub fn fill_ellipse<W, Color>(writer: &W, center: Point<isize>, radii: Point<isize>, color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    writer.with_draw_target(&mut |target| {
+        ellipse_points(radii, |x, y| {
+            draw_span(target, center.0 - x, center.0 + x, center.1 + y, color);
+            draw_span(target, center.0 - x, center.0 + x, center.1 - y, color);
+        });
+    });
+}
+
+/// Draws lines between each pair of consecutive `points` with the specified `color`.
+pub fn draw_polyline<W, Color>(writer: &W, points: &[Point<isize>], color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    writer.with_draw_target(&mut |target| draw_lines::<W, _>(target, points, false, color));
+}
+
+/// Draws the outline of the polygon with the specified `points` and `color`,
+/// connecting the last point back to the first one.
+pub fn draw_polygon<W, Color>(writer: &W, points: &[Point<isize>], color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    writer.with_draw_target(&mut |target| draw_lines::<W, _>(target, points, true, color));
+}
+
+/// Fills the polygon with the specified `points` and `color`.
+///
+/// The polygon may be concave or self intersecting, in which case the
+/// even-odd rule decides which parts are inside. The outline of the
+/// polygon is always drawn as well.
+pub fn fill_polygon<W, Color>(writer: &W, points: &[Point<isize>], color: Color)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    let (top, bottom) = match (
+        points.iter().map(|point| point.1).min(),
+        points.iter().map(|point| point.1).max(),
+    ) {
+        (Some(top), Some(bottom)) => (top, bottom),
+        _ => return,
+    };
+
+    writer.with_draw_target(&mut |target| {
+        for y in top.max(0)..=bottom.min(W::HEIGHT as isize - 1) {
+            fill_scanline(target, points, y, color);
+        }
+        draw_lines::<W, _>(target, points, true, color);
+    });
+}
+
+/// Draws lines between each pair of consecutive `points`, and from the
+/// last point back to the first one if `closed` is set.
+fn draw_lines<W: Screen, Color: Copy>(
+    target: &mut dyn DrawTarget<Color>,
+    points: &[Point<isize>],
+    closed: bool,
+    color: Color,
+) {
+    for line in points.windows(2) {
+        target.draw_line(line[0], line[1], color);
+    }
+    if let [point] = points {
+        draw_pixel::<W, _>(target, point.0, point.1, color);
+    }
+    if let (true, Some(&first), Some(&last)) = (closed, points.first(), points.last()) {
+        target.draw_line(last, first, color);
+    }
+}
+
+/// Fills the parts of row `y` inside of the polygon with the specified
+/// `points`, using the even-odd rule.
+fn fill_scanline<Color: Copy>(
+    target: &mut dyn DrawTarget<Color>,
+    points: &[Point<isize>],
+    y: isize,
+    color: Color,
+) {
+    // Walk the crossings of this scanline from left to right, without
+    // having to store and sort them.
+    let mut previous = None;
+    let mut inside = false;
+    let mut span_start = 0;
+    loop {
+        let mut next = None;
+        let mut count = 0;
+        for (index, &start) in points.iter().enumerate() {
+            let end = points[(index + 1) % points.len()];
+            let x = match crossing(start, end, y) {
+                Some(x) if previous < Some(x) => x,
+                _ => continue,
+            };
+            match next {
+                Some(next_x) if x > next_x => (),
+                Some(next_x) if x == next_x => count += 1,
+                _ => {
+                    next = Some(x);
+                    count = 1;
+                }
+            }
+        }
+        let x = match next {
+            Some(x) => x,
+            None => break,
+        };
+        for _ in 0..count {
+            if inside {
+                draw_span(target, span_start, x, y, color);
+            }
+            span_start = x;
+            inside = !inside;
+        }
+        previous = next;
+    }
+}
+
+/// Fills the area of pixels connected to `seed` that share its color with
+/// the specified `color`.
+///
+/// At most `FLOOD_FILL_STACK_SIZE` spans are kept track of at once, so no
+/// memory is allocated. Returns `false` if the area was too complex to be
+/// filled completely.
+pub fn flood_fill<W, Color>(writer: &W, seed: Point<isize>, color: Color) -> bool
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy + PartialEq,
+{
+    if !contains::<W>(seed.0, seed.1) {
+        return true;
+    }
+    let mut complete = true;
+    writer.with_draw_target(&mut |target| {
+        complete = fill_area::<W, _>(target, (seed.0 as usize, seed.1 as usize), color);
+    });
+    complete
+}
+
+/// Fills the area of pixels connected to `seed` like `flood_fill`, which
+/// has already checked that `seed` is on the screen.
+fn fill_area<W: Screen, Color>(
+    draw_target: &mut dyn DrawTarget<Color>,
+    seed: Point<usize>,
+    color: Color,
+) -> bool
+where
+    Color: Copy + PartialEq,
+{
+    let target = draw_target.get_pixel(seed.0, seed.1);
+    if target == color {
+        return true;
+    }
+
+    let mut complete = true;
+    let mut stack = [(0, 0); FLOOD_FILL_STACK_SIZE];
+    let mut length = 1;
+    stack[0] = seed;
+
+    while length > 0 {
+        length -= 1;
+        let (x, y) = stack[length];
+        if draw_target.get_pixel(x, y) != target {
+            continue;
+        }
+
+        let mut left = x;
+        while left > 0 && draw_target.get_pixel(left - 1, y) == target {
+            left -= 1;
+        }
+        let mut right = x;
+        while right + 1 < W::WIDTH && draw_target.get_pixel(right + 1, y) == target {
+            right += 1;
+        }
+        draw_span(
+            draw_target,
+            left as isize,
+            right as isize,
+            y as isize,
+            color,
+        );
+
+        // Queue one seed for every run of matching pixels above and below the span.
+        let rows = [y.checked_sub(1), Some(y + 1).filter(|y| *y < W::HEIGHT)];
+        for row in rows.iter().flatten() {
+            let mut in_run = false;
+            for x in left..=right {
+                if draw_target.get_pixel(x, *row) != target {
+                    in_run = false;
+                } else if !in_run {
+                    in_run = true;
+                    if length < stack.len() {
+                        stack[length] = (x, *row);
+                        length += 1;
+                    } else {
+                        complete = false;
+                    }
+                }
+            }
+        }
+    }
+
+    complete
+}
+
+fn contains<W: Screen>(x: isize, y: isize) -> bool {
+    x >= 0 && y >= 0 && (x as usize) < W::WIDTH && (y as usize) < W::HEIGHT
+}
+
+fn draw_pixel<W: Screen, Color>(
+    target: &mut dyn DrawTarget<Color>,
+    x: isize,
+    y: isize,
+    color: Color,
+) {
+    if contains::<W>(x, y) {
+        target.set_pixel(x as usize, y as usize, color);
+    }
+}
+
+/// Draws the horizontal span from `start_x` to `end_x` (inclusive) on row
+/// `y`, clipped to the screen.
+fn draw_span<Color>(
+    target: &mut dyn DrawTarget<Color>,
+    start_x: isize,
+    end_x: isize,
+    y: isize,
+    color: Color,
+) {
+    target.fill_rect((start_x, y), (end_x, y), color);
+}
+
+fn normalize(start: Point<isize>, end: Point<isize>) -> (Point<isize>, Point<isize>) {
+    (
+        (start.0.min(end.0), start.1.min(end.1)),
+        (start.0.max(end.0), start.1.max(end.1)),
+    )
+}
+
+/// Calls `f` with the points of the first octant of a circle with the
+/// specified `radius`, relative to its center.
+fn circle_points<F: FnMut(isize, isize)>(radius: isize, mut f: F) {
+    let (mut x, mut y) = (radius, 0);
+    let mut error = 1 - radius;
+    while x >= y {
+        f(x, y);
+        y += 1;
+        if error < 0 {
+            error += 2 * y + 1;
+        } else {
+            x -= 1;
+            error += 2 * (y - x) + 1;
+        }
+    }
+}
+
+fn octant_points(x: isize, y: isize) -> [Point<isize>; 8] {
+    [
+        (x, y),
+        (y, x),
+        (-y, x),
+        (-x, y),
+        (-x, -y),
+        (-y, -x),
+        (y, -x),
+        (x, -y),
+    ]
+}
+
+/// Calls `f` with the points of the first quadrant of an ellipse with the
+/// specified `radii`, relative to its center.
+fn ellipse_points<F: FnMut(isize, isize)>(radii: Point<isize>, mut f: F) {
+    let (radius_x, radius_y) = radii;
+    if radius_x < 0 || radius_y < 0 {
+        return;
+    }
+    if radius_x == 0 || radius_y == 0 {
+        for x in 0..=radius_x {
+            f(x, 0);
+        }
+        for y in 0..=radius_y {
+            f(0, y);
+        }
+        return;
+    }
+
+    let (a2, b2) = (radius_x * radius_x, radius_y * radius_y);
+
+    // The first region, where the slope is less than -1.
+    let (mut x, mut y) = (radius_x, 0);
+    let (mut change_x, mut change_y) = (b2 * (1 - 2 * radius_x), a2);
+    let (mut stopping_x, mut stopping_y) = (2 * b2 * radius_x, 0);
+    let mut error = 0;
+    while stopping_x >= stopping_y {
+        f(x, y);
+        y += 1;
+        stopping_y += 2 * a2;
+        error += change_y;
+        change_y += 2 * a2;
+        if 2 * error + change_x > 0 {
+            x -= 1;
+            stopping_x -= 2 * b2;
+            error += change_x;
+            change_x += 2 * b2;
+        }
+    }
+
+    // The second region, where the slope is greater than -1.
+    let (mut x, mut y) = (0, radius_y);
+    let (mut change_x, mut change_y) = (b2, a2 * (1 - 2 * radius_y));
+    let (mut stopping_x, mut stopping_y) = (0, 2 * a2 * radius_y);
+    let mut error = 0;
+    while stopping_x <= stopping_y {
+        f(x, y);
+        x += 1;
+        stopping_x += 2 * b2;
+        error += change_x;
+        change_x += 2 * b2;
+        if 2 * error + change_y > 0 {
+            y -= 1;
+            stopping_y -= 2 * a2;
+            error += change_y;
+            change_y += 2 * a2;
+        }
+    }
+}
+
+/// Returns the x coordinate where the edge from `start` to `end` crosses
+/// row `y`, treating the edge as half open so shared vertices are only
+/// counted once.
+fn crossing(start: Point<isize>, end: Point<isize>, y: isize) -> Option<isize> {
+    let (start, end) = if start.1 <= end.1 {
+        (start, end)
+    } else {
+        (end, start)
+    };
+    if y < start.1 || y >= end.1 {
+        return None;
+    }
+    Some(start.0 + (y - start.1) * (end.0 - start.0) / (end.1 - start.1))
+}
+
+const ANGLE_SCALE: isize = 1 << 14;
+
+/// Returns the direction of `angle` (in degrees) scaled by `ANGLE_SCALE`,
+/// using Bhaskara's sine approximation.
+fn direction(angle: isize) -> Point<isize> {
+    (sine(angle + 90), sine(angle))
+}
+
+fn sine(angle: isize) -> isize {
+    let angle = angle.rem_euclid(360);
+    let (angle, sign) = if angle < 180 {
+        (angle, 1)
+    } else {
+        (angle - 180, -1)
+    };
+    let product = angle * (180 - angle);
+    sign * ANGLE_SCALE * 4 * product / (40500 - product)
+}
+
+fn cross(a: Point<isize>, b: Point<isize>) -> isize {
+    a.0 * b.1 - a.1 * b.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        emulator::VgaEmulator,
+        vga::Vga,
+        writers::{BackBuffer, Graphics320x200x256},
+    };
+    use spinning_top::Spinlock;
+
+    fn with_mode<F>(f: F)
+    where
+        F: FnOnce(&VgaEmulator, &Graphics320x200x256<&Spinlock<Vga<&VgaEmulator>>>),
+    {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics320x200x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(0);
+        f(&emulator, &mode);
+    }
+
+    fn count(emulator: &VgaEmulator, color: u8) -> usize {
+        (0..200)
+            .flat_map(|y| (0..320).map(move |x| (x, y)))
+            .filter(|&(x, y)| emulator.read_pixel(x, y) == color)
+            .count()
+    }
+
+    #[test]
+    fn test_rects() {
+        with_mode(|emulator, mode| {
+            draw_rect(mode, (10, 20), (1, 2), 1);
+            assert_eq!(count(emulator, 1), 2 * 10 + 2 * 17);
+            fill_rect(mode, (-5, -5), (4, 4), 2);
+            assert_eq!(count(emulator, 2), 25);
+            fill_rect(mode, (310, 190), (400, 400), 3);
+            assert_eq!(count(emulator, 3), 100);
+        });
+    }
+
+    #[test]
+    fn test_circles() {
+        with_mode(|emulator, mode| {
+            draw_circle(mode, (100, 100), 10, 1);
+            for &(x, y) in &[(110, 100), (90, 100), (100, 110), (100, 90)] {
+                assert_eq!(emulator.read_pixel(x, y), 1);
+            }
+            assert_eq!(emulator.read_pixel(100, 100), 0);
+
+            fill_circle(mode, (200, 100), 10, 2);
+            assert_eq!(emulator.read_pixel(200, 100), 2);
+            assert_eq!(emulator.read_pixel(210, 100), 2);
+            assert_eq!(emulator.read_pixel(211, 100), 0);
+
+            // Circles around the corner must not wrap around to other rows.
+            fill_circle(mode, (0, 0), 20, 3);
+            assert_eq!(emulator.read_pixel(319, 5), 0);
+            assert_eq!(emulator.read_pixel(0, 20), 3);
+        });
+    }
+
+    #[test]
+    fn test_arcs() {
+        with_mode(|emulator, mode| {
+            draw_arc(mode, (100, 100), 20, 0, 90, 1);
+            assert_eq!(emulator.read_pixel(120, 100), 1);
+            assert_eq!(emulator.read_pixel(100, 80), 1);
+            assert_eq!(emulator.read_pixel(80, 100), 0);
+            assert_eq!(emulator.read_pixel(100, 120), 0);
+
+            draw_arc(mode, (200, 100), 20, 90, 0, 2);
+            assert_eq!(emulator.read_pixel(180, 100), 2);
+            assert_eq!(emulator.read_pixel(200, 120), 2);
+            assert_eq!(emulator.read_pixel(214, 86), 0);
+        });
+    }
+
+    #[test]
+    fn test_ellipses() {
+        with_mode(|emulator, mode| {
+            draw_ellipse(mode, (100, 100), (30, 10), 1);
+            for &(x, y) in &[(130, 100), (70, 100), (100, 110), (100, 90)] {
+                assert_eq!(emulator.read_pixel(x, y), 1);
+            }
+            fill_ellipse(mode, (200, 100), (30, 10), 2);
+            assert_eq!(emulator.read_pixel(200, 100), 2);
+            assert_eq!(emulator.read_pixel(229, 100), 2);
+            assert_eq!(emulator.read_pixel(200, 111), 0);
+        });
+    }
+
+    #[test]
+    fn test_polygons() {
+        with_mode(|emulator, mode| {
+            // A concave "U" shape.
+            let points = [
+                (10, 10),
+                (40, 10),
+                (40, 40),
+                (30, 40),
+                (30, 20),
+                (20, 20),
+                (20, 40),
+                (10, 40),
+            ];
+            fill_polygon(mode, &points, 1);
+            assert_eq!(emulator.read_pixel(15, 30), 1);
+            assert_eq!(emulator.read_pixel(35, 30), 1);
+            assert_eq!(emulator.read_pixel(25, 15), 1);
+            assert_eq!(emulator.read_pixel(25, 30), 0);
+
+            draw_polyline(mode, &[(100, 100), (110, 100), (110, 110)], 2);
+            assert_eq!(count(emulator, 2), 21);
+        });
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        with_mode(|emulator, mode| {
+            draw_rect(mode, (10, 10), (50, 50), 1);
+            draw_circle(mode, (30, 30), 5, 1);
+            assert!(flood_fill(mode, (20, 20), 2));
+            assert_eq!(emulator.read_pixel(11, 11), 2);
+            assert_eq!(emulator.read_pixel(30, 30), 0);
+            assert_eq!(emulator.read_pixel(9, 9), 0);
+
+            assert!(flood_fill(mode, (0, 0), 3));
+            assert_eq!(emulator.read_pixel(319, 199), 3);
+            assert_eq!(emulator.read_pixel(20, 20), 2);
+            assert_eq!(emulator.read_pixel(30, 30), 0);
+        });
+    }
+
+    #[test]
+    fn test_shapes_without_session() {
+        // A `BackBuffer` draws shapes through its `GraphicsWriter` methods.
+        let mut pixels = [0u8; 320 * 200];
+        let back_buffer = BackBuffer::<Graphics320x200x256<()>, u8>::new(&mut pixels);
+        draw_rect(&back_buffer, (10, 10), (50, 50), 1);
+        fill_rect(&back_buffer, (60, 10), (70, 20), 2);
+        assert!(flood_fill(&back_buffer, (20, 20), 3));
+        assert_eq!(back_buffer.get_pixel(10, 30), 1);
+        assert_eq!(back_buffer.get_pixel(49, 49), 3);
+        assert_eq!(back_buffer.get_pixel(51, 51), 0);
+        assert_eq!(back_buffer.get_pixel(70, 20), 2);
+        assert_eq!(back_buffer.get_pixel(71, 20), 0);
+    }
+}
@@ -0,0 +1,495 @@
+use super::{shapes, Point};
+use crate::{
+    fonts::VgaFont,
+    writers::{GraphicsWriter, Screen},
+};
+use font8x8::UnicodeFonts;
+
+/// The width of every glyph in pixels, before scaling.
+pub const GLYPH_WIDTH: usize = 8;
+
+/// The maximum height of a glyph in pixels, before scaling, which is the
+/// maximum character height supported by vga hardware.
+pub const MAX_GLYPH_HEIGHT: usize = 32;
+
+/// The font that glyphs are taken from when drawing text.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum FontSource {
+    /// The 8x8 `font8x8::BASIC_FONTS`, which is the default font of the
+    /// graphics writers.
+    #[default]
+    Basic,
+    /// A `VgaFont`, such as `TEXT_8X8_FONT` or `TEXT_8X16_FONT`, with
+    /// characters mapped to its glyphs by `fonts::char_to_cp437`. This
+    /// draws text exactly like a text mode using the same font.
+    Vga(&'static VgaFont),
+}
+
+impl FontSource {
+    /// Returns the height of the glyphs in this font in pixels, before scaling.
+    pub fn glyph_height(self) -> usize {
+        match self {
+            FontSource::Basic => 8,
+            FontSource::Vga(font) => usize::from(font.character_height).min(MAX_GLYPH_HEIGHT),
+        }
+    }
+
+    /// Returns the rows of the glyph for `character`, with the leftmost
+    /// pixel in the most significant bit. Characters missing from the font
+    /// are drawn as a filled block.
+    pub(crate) fn glyph(self, character: char) -> [u8; MAX_GLYPH_HEIGHT] {
+        let mut rows = [0xFF; MAX_GLYPH_HEIGHT];
+        match self {
+            FontSource::Basic => {
+                if let Some(glyph) = font8x8::BASIC_FONTS.get(character) {
+                    for (row, byte) in rows.iter_mut().zip(glyph.iter()) {
+                        *row = byte.reverse_bits();
+                    }
+                }
+            }
+            FontSource::Vga(font) => {
+                if let Some(glyph) = font.glyph_for(character) {
+                    let height = self.glyph_height();
+                    rows[..height].copy_from_slice(&glyph[..height]);
+                }
+            }
+        }
+        rows
+    }
+
+    /// Calls `set_pixel` with the `(x, y)` offset of every pixel that is set
+    /// in the glyph for `character`.
+    pub(crate) fn for_each_pixel<F>(self, character: char, mut set_pixel: F)
+    where
+        F: FnMut(usize, usize),
+    {
+        let glyph = self.glyph(character);
+        for (y, bits) in glyph[..self.glyph_height()].iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if bits & (0x80 >> x) != 0 {
+                    set_pixel(x, y);
+                }
+            }
+        }
+    }
+}
+
+/// The horizontal alignment of text relative to its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// Lines start at the position.
+    Left,
+    /// Lines are centered on the position.
+    Center,
+    /// Lines end at the position.
+    Right,
+}
+
+/// Describes how `draw_text` and `draw_text_wrapped` draw text.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```no_run
+/// use vga::colors::Color16;
+/// use vga::drawing::{draw_text, Alignment, FontSource, TextStyle};
+/// use vga::fonts::TEXT_8X16_FONT;
+/// use vga::writers::{Graphics640x480x16, GraphicsWriter};
+///
+/// let mode = Graphics640x480x16::new();
+/// mode.set_mode();
+/// mode.clear_screen(Color16::Black);
+///
+/// let title = TextStyle::new(Color16::Yellow)
+///     .with_font(FontSource::Vga(&TEXT_8X16_FONT))
+///     .with_background(Color16::Blue)
+///     .with_scale(2)
+///     .with_bold(true)
+///     .with_alignment(Alignment::Center);
+/// draw_text(&mode, (320, 40), "Hello World!", &title);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle<Color> {
+    font: FontSource,
+    foreground: Color,
+    background: Option<Color>,
+    scale: usize,
+    bold: bool,
+    alignment: Alignment,
+}
+
+impl<Color: Copy> TextStyle<Color> {
+    /// Creates a new `TextStyle` that draws transparent, left aligned text
+    /// with the specified `foreground` color, using `FontSource::Basic`.
+    pub const fn new(foreground: Color) -> TextStyle<Color> {
+        TextStyle {
+            font: FontSource::Basic,
+            foreground,
+            background: None,
+            scale: 1,
+            bold: false,
+            alignment: Alignment::Left,
+        }
+    }
+
+    /// Returns this style with glyphs taken from `font`.
+    pub fn with_font(mut self, font: FontSource) -> TextStyle<Color> {
+        self.font = font;
+        self
+    }
+
+    /// Returns this style with the pixels around the glyphs set to
+    /// `background`, rather than left as they are.
+    pub fn with_background(mut self, background: Color) -> TextStyle<Color> {
+        self.background = Some(background);
+        self
+    }
+
+    /// Returns this style with every pixel of a glyph drawn as a square
+    /// of `scale` by `scale` pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scale` is 0.
+    pub fn with_scale(mut self, scale: usize) -> TextStyle<Color> {
+        assert!(scale > 0, "scale must be at least 1");
+        self.scale = scale;
+        self
+    }
+
+    /// Returns this style with bold glyphs, which are synthesized by
+    /// drawing every glyph a second time, one (unscaled) pixel to the right.
+    pub fn with_bold(mut self, bold: bool) -> TextStyle<Color> {
+        self.bold = bold;
+        self
+    }
+
+    /// Returns this style with lines aligned as specified by `alignment`.
+    pub fn with_alignment(mut self, alignment: Alignment) -> TextStyle<Color> {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Returns the `(width, height)` of a single character in pixels.
+    pub fn character_size(&self) -> Point<usize> {
+        (
+            GLYPH_WIDTH * self.scale,
+            self.font.glyph_height() * self.scale,
+        )
+    }
+
+    /// Returns the `(width, height)` in pixels of `text` when drawn with
+    /// `draw_text`, where every `'\n'` starts a new line.
+    pub fn measure(&self, text: &str) -> Point<usize> {
+        let (character_width, character_height) = self.character_size();
+        let columns = text
+            .split('\n')
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let rows = text.split('\n').count();
+        (columns * character_width, rows * character_height)
+    }
+
+    /// Returns the x coordinate of the first pixel of a line that is
+    /// `width` pixels wide and aligned to `x`.
+    fn line_start(&self, x: isize, width: usize) -> isize {
+        match self.alignment {
+            Alignment::Left => x,
+            Alignment::Center => x - width as isize / 2,
+            Alignment::Right => x - width as isize,
+        }
+    }
+}
+
+/// Draws `text` with its first line aligned to `position` as specified by
+/// `style`, clipped to the screen. Every `'\n'` starts a new line below
+/// the previous one, aligned to the same position.
+///
+/// Lines are aligned horizontally only, so `position.1` is always the
+/// top of the first line.
+pub fn draw_text<W, Color>(writer: &W, position: Point<isize>, text: &str, style: &TextStyle<Color>)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    let (_, character_height) = style.character_size();
+    for (row, line) in text.split('\n').enumerate() {
+        let y = position.1 + (row * character_height) as isize;
+        draw_line_of_text(writer, position.0, y, line, style);
+    }
+}
+
+/// Draws `text` into the rectangle with the corners `start` and `end`
+/// (inclusive), wrapping lines between words so they fit its width, and
+/// aligning them within it as specified by `style`. Every `'\n'` starts a
+/// new line, and words that are wider than the rectangle are split.
+///
+/// Lines that don't fit in the rectangle aren't drawn. Returns the part of
+/// `text` that wasn't drawn, which is empty if everything fit.
+pub fn draw_text_wrapped<'a, W, Color>(
+    writer: &W,
+    start: Point<isize>,
+    end: Point<isize>,
+    text: &'a str,
+    style: &TextStyle<Color>,
+) -> &'a str
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    let (left, right) = (start.0.min(end.0), start.0.max(end.0));
+    let (top, bottom) = (start.1.min(end.1), start.1.max(end.1));
+    let (character_width, character_height) = style.character_size();
+    let columns = (right - left + 1) as usize / character_width;
+    let rows = (bottom - top + 1) as usize / character_height;
+    let x = match style.alignment {
+        Alignment::Left => left,
+        Alignment::Center => left + (right - left + 1) / 2,
+        Alignment::Right => right + 1,
+    };
+
+    let mut lines = WrappedLines::new(text, columns);
+    for row in 0..rows {
+        match lines.next() {
+            Some(line) => {
+                let y = top + (row * character_height) as isize;
+                draw_line_of_text(writer, x, y, line, style);
+            }
+            None => break,
+        }
+    }
+    lines.remaining()
+}
+
+fn draw_line_of_text<W, Color>(writer: &W, x: isize, y: isize, line: &str, style: &TextStyle<Color>)
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    let (character_width, _) = style.character_size();
+    let width = line.chars().count() * character_width;
+    let start = style.line_start(x, width);
+    if start >= W::WIDTH as isize || start + width as isize <= 0 {
+        return;
+    }
+    for (column, character) in line.chars().enumerate() {
+        let x = start + (column * character_width) as isize;
+        if x + character_width as isize > 0 && x < W::WIDTH as isize {
+            draw_glyph(writer, (x, y), character, style);
+        }
+    }
+}
+
+/// Draws the glyph for `character` with its top left corner at
+/// `position`, as runs of pixels that share the same color.
+fn draw_glyph<W, Color>(
+    writer: &W,
+    position: Point<isize>,
+    character: char,
+    style: &TextStyle<Color>,
+) where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    let glyph = style.font.glyph(character);
+    let scale = style.scale as isize;
+    for (row, &bits) in glyph[..style.font.glyph_height()].iter().enumerate() {
+        let bits = if style.bold { bits | bits >> 1 } else { bits };
+        let mut column = 0;
+        while column < GLYPH_WIDTH {
+            let set = bits & (0x80 >> column) != 0;
+            let run_start = column;
+            while column < GLYPH_WIDTH && (bits & (0x80 >> column) != 0) == set {
+                column += 1;
+            }
+            let color = match (set, style.background) {
+                (true, _) => style.foreground,
+                (false, Some(background)) => background,
+                (false, None) => continue,
+            };
+            let start_x = position.0 + run_start as isize * scale;
+            let end_x = position.0 + column as isize * scale - 1;
+            let start_y = position.1 + row as isize * scale;
+            shapes::fill_rect(
+                writer,
+                (start_x, start_y),
+                (end_x, start_y + scale - 1),
+                color,
+            );
+        }
+    }
+}
+
+/// Splits text into lines of at most `columns` characters, breaking lines
+/// at `'\n'` and between words.
+struct WrappedLines<'a> {
+    text: &'a str,
+    columns: usize,
+    done: bool,
+}
+
+impl<'a> WrappedLines<'a> {
+    fn new(text: &'a str, columns: usize) -> WrappedLines<'a> {
+        WrappedLines {
+            text,
+            columns,
+            done: columns == 0,
+        }
+    }
+
+    /// Returns the text that hasn't been split into lines yet.
+    fn remaining(&self) -> &'a str {
+        if self.done && self.columns > 0 {
+            ""
+        } else {
+            self.text
+        }
+    }
+}
+
+impl<'a> Iterator for WrappedLines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.done {
+            return None;
+        }
+
+        let text = self.text;
+        // The end of the line, and the start of the text after it.
+        let (mut end, mut next) = (text.len(), text.len());
+        let mut word_break = None;
+        let mut wrapped = false;
+        self.done = true;
+        for (count, (index, character)) in text.char_indices().enumerate() {
+            if character == '\n' {
+                end = index;
+                next = index + 1;
+                self.done = false;
+                break;
+            }
+            if count == self.columns {
+                let (break_end, break_next) = match (character, word_break) {
+                    (' ', _) => (index, index + 1),
+                    (_, Some(word_break)) => word_break,
+                    (_, None) => (index, index),
+                };
+                end = break_end;
+                next = break_next;
+                wrapped = true;
+                self.done = false;
+                break;
+            }
+            if character == ' ' {
+                word_break = Some((index, index + 1));
+            }
+        }
+
+        self.text = &text[next..];
+        if wrapped {
+            self.text = self.text.trim_start_matches(' ');
+            // Text that ends with the wrapped line doesn't start another one.
+            self.done = self.text.is_empty();
+        }
+        Some(text[..end].trim_end_matches(' '))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        emulator::VgaEmulator, fonts::TEXT_8X16_FONT, vga::Vga, writers::Graphics320x200x256,
+    };
+    use spinning_top::Spinlock;
+
+    fn assert_lines(text: &str, columns: usize, expected: &[&str]) {
+        let mut lines = WrappedLines::new(text, columns);
+        for line in expected {
+            assert_eq!(lines.next(), Some(*line), "wrapping {:?}", text);
+        }
+        assert_eq!(lines.next(), None, "wrapping {:?}", text);
+    }
+
+    #[test]
+    fn test_wrapped_lines() {
+        assert_lines("hello world", 20, &["hello world"]);
+        assert_lines("hello world", 5, &["hello", "world"]);
+        assert_lines("hello world", 8, &["hello", "world"]);
+        assert_lines("hello  big\nworld", 6, &["hello", "big", "world"]);
+        assert_lines("abcdefghij", 4, &["abcd", "efgh", "ij"]);
+        assert_lines("ab\n\ncd\n", 4, &["ab", "", "cd", ""]);
+        assert_lines("", 4, &[""]);
+        assert_lines("hello", 0, &[]);
+    }
+
+    #[test]
+    fn test_draw_text() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics320x200x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(0);
+
+        // A scaled, opaque 'I' from the 8x16 vga font is 3 * 8 pixels wide,
+        // centered on x = 100, and every scaled pixel is a 3x3 square.
+        let style = TextStyle::new(15)
+            .with_font(FontSource::Vga(&TEXT_8X16_FONT))
+            .with_background(1)
+            .with_scale(3)
+            .with_alignment(Alignment::Center);
+        assert_eq!(style.measure("I\nII"), (48, 96));
+        draw_text(&mode, (100, 10), "I", &style);
+        let glyph = FontSource::Vga(&TEXT_8X16_FONT).glyph('I');
+        for y in 0..48 {
+            for x in 0..24 {
+                let set = glyph[y / 3] & (0x80 >> (x / 3)) != 0;
+                let expected = if set { 15 } else { 1 };
+                assert_eq!(
+                    emulator.read_pixel(88 + x, 10 + y),
+                    expected,
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+        assert_eq!(emulator.read_pixel(87, 10), 0);
+        assert_eq!(emulator.read_pixel(112, 10), 0);
+
+        // Bold glyphs are widened by a pixel, and transparent text leaves the
+        // background as it is. Text is clipped to the screen.
+        mode.clear_screen(0);
+        let style = TextStyle::new(2).with_bold(true);
+        draw_text(&mode, (-4, 0), "|", &style);
+        draw_text(&mode, (316, 196), "|", &style);
+        let count = |color| {
+            (0..200)
+                .flat_map(|y| (0..320).map(move |x| (x, y)))
+                .filter(|&(x, y)| emulator.read_pixel(x, y) == color)
+                .count()
+        };
+        let glyph = FontSource::Basic.glyph('|');
+        let visible = |columns: core::ops::Range<usize>, rows: core::ops::Range<usize>| {
+            rows.map(|row| {
+                let bits = glyph[row] | glyph[row] >> 1;
+                columns
+                    .clone()
+                    .filter(|column| bits & (0x80 >> column) != 0)
+                    .count()
+            })
+            .sum::<usize>()
+        };
+        assert_eq!(count(2), visible(4..8, 0..8) + visible(0..4, 0..4));
+
+        // Wrapped text stops at the bottom of the rectangle.
+        mode.clear_screen(0);
+        let style = TextStyle::new(3).with_alignment(Alignment::Right);
+        let remaining = draw_text_wrapped(&mode, (0, 0), (47, 15), "one two three four", &style);
+        assert_eq!(remaining, "three four");
+        assert_ne!(count(3), 0);
+        assert!((0..200).all(|y| emulator.read_pixel(0, y) == 0));
+    }
+}
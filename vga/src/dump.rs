@@ -0,0 +1,277 @@
+//! Decoded, human readable dumps of the vga registers.
+
+use super::{
+    colors::PALETTE_SIZE,
+    registers::{
+        CrtcControllerIndex, EmulationMode, GraphicsControllerIndex, GraphicsMiscellaneous,
+        MemoryMode, MiscellaneousOutput, SequencerIndex,
+    },
+    renderer::DisplayRegisters,
+    vga::FrameBuffer,
+};
+use core::fmt;
+
+/// A snapshot of every vga register and the dac palette, as taken by
+/// `Vga::dump_registers`, which formats as a decoded report.
+///
+/// The report lists the resolution, character size, memory layout, cursor
+/// position and palette mapping, followed by the raw value of every
+/// register, which helps with finding out why a mode looks wrong on screen.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```no_run
+/// use core::fmt::Write;
+/// use vga::vga::VGA;
+///
+/// # fn example(serial: &mut impl Write) -> core::fmt::Result {
+/// let dump = VGA.lock().dump_registers();
+/// write!(serial, "{}", dump)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegisterDump {
+    registers: DisplayRegisters,
+    feature_control: u8,
+    palette: [u8; PALETTE_SIZE],
+}
+
+impl RegisterDump {
+    /// Creates a new `RegisterDump` from the given `registers`, feature
+    /// control register value and dac `palette`, such as the ones saved
+    /// in a `VgaState`.
+    pub fn new(
+        registers: DisplayRegisters,
+        feature_control: u8,
+        palette: &[u8; PALETTE_SIZE],
+    ) -> RegisterDump {
+        RegisterDump {
+            registers,
+            feature_control,
+            palette: *palette,
+        }
+    }
+
+    /// Returns the dumped registers.
+    pub fn registers(&self) -> &DisplayRegisters {
+        &self.registers
+    }
+
+    /// Returns the dumped 256 color palette, with every 3 bytes
+    /// representing a color.
+    pub fn palette(&self) -> &[u8; PALETTE_SIZE] {
+        &self.palette
+    }
+
+    fn crtc(&self, index: CrtcControllerIndex) -> u8 {
+        self.registers.crtc_controller_registers[usize::from(u8::from(index))]
+    }
+}
+
+impl fmt::Display for RegisterDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registers = &self.registers;
+        let info = registers.video_mode_info();
+        let (width, height) = registers.dimensions();
+        let memory_mode = MemoryMode::from_bits_truncate(
+            registers.sequencer_registers[usize::from(u8::from(SequencerIndex::MemoryMode))],
+        );
+        let miscellaneous_output =
+            MiscellaneousOutput::from_bits_truncate(registers.miscellaneous_output);
+        let memory_map = GraphicsMiscellaneous::from_bits_truncate(
+            registers.graphics_controller_registers
+                [usize::from(u8::from(GraphicsControllerIndex::Miscellaneous))],
+        )
+        .memory_map();
+        let (memory_start, memory_end) = match FrameBuffer::from_memory_map(memory_map) {
+            FrameBuffer::ExtendedGraphicsMode => (0xA0000, 0xBFFFF),
+            FrameBuffer::GraphicsMode => (0xA0000, 0xAFFFF),
+            FrameBuffer::MdaMode => (0xB0000, 0xB7FFF),
+            FrameBuffer::CgaMode => (0xB8000, 0xBFFFF),
+        };
+
+        writeln!(f, "Video mode: {}", info)?;
+        writeln!(f, "Resolution: {}x{} pixels", width, height)?;
+        if !info.graphics {
+            writeln!(
+                f,
+                "Character size: {}x{}",
+                registers.character_width(),
+                registers.scan_line_height()
+            )?;
+        }
+        writeln!(f, "Chain-4: {}", on_off(info.chain_4))?;
+        writeln!(
+            f,
+            "Odd/even: {}",
+            on_off(!memory_mode.contains(MemoryMode::ODD_EVEN_DISABLE))
+        )?;
+        writeln!(
+            f,
+            "Memory map: {} ({:#X} -> {:#X})",
+            memory_map, memory_start, memory_end
+        )?;
+        writeln!(
+            f,
+            "Emulation mode: {:?}",
+            EmulationMode::from_miscellaneous_output(miscellaneous_output.bits())
+        )?;
+        writeln!(
+            f,
+            "Start address: {:#06X}, line offset: {}, line compare: {}",
+            registers.start_address(),
+            registers.line_offset(),
+            registers.line_compare()
+        )?;
+        match registers.cursor_location() {
+            Some(location) if !info.graphics => {
+                let columns = registers.line_offset().max(1);
+                let start = registers.start_address();
+                let offset = location.wrapping_sub(start);
+                writeln!(
+                    f,
+                    "Cursor: column {}, row {}, scan lines {} -> {}",
+                    offset % columns,
+                    offset / columns,
+                    self.crtc(CrtcControllerIndex::TextCursorStart) & 0x1F,
+                    self.crtc(CrtcControllerIndex::TextCursorEnd) & 0x1F
+                )?;
+            }
+            _ => writeln!(f, "Cursor: disabled")?,
+        }
+
+        writeln!(f, "Palette:")?;
+        for attribute in 0..16 {
+            let index = registers.palette_index(attribute);
+            let offset = usize::from(index) * 3;
+            writeln!(
+                f,
+                "  {:#X} -> {:#04X} ({:#04X}, {:#04X}, {:#04X})",
+                attribute,
+                index,
+                self.palette[offset],
+                self.palette[offset + 1],
+                self.palette[offset + 2]
+            )?;
+        }
+
+        writeln!(
+            f,
+            "Miscellaneous output: {:#04X}",
+            registers.miscellaneous_output
+        )?;
+        writeln!(f, "Feature control: {:#04X}", self.feature_control)?;
+        write_registers(f, "Sequencer", &registers.sequencer_registers)?;
+        write_registers(f, "Crtc controller", &registers.crtc_controller_registers)?;
+        write_registers(
+            f,
+            "Graphics controller",
+            &registers.graphics_controller_registers,
+        )?;
+        write_registers(
+            f,
+            "Attribute controller",
+            &registers.attribute_controller_registers,
+        )
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// Writes `values` as rows of 8 hex bytes, each prefixed by
+/// the index of its first register.
+fn write_registers(f: &mut fmt::Formatter<'_>, name: &str, values: &[u8]) -> fmt::Result {
+    writeln!(f, "{}:", name)?;
+    for (row, chunk) in values.chunks(8).enumerate() {
+        write!(f, "  {:02X}:", row * 8)?;
+        for value in chunk {
+            write!(f, " {:02X}", value)?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        emulator::VgaEmulator,
+        vga::{Vga, VideoMode},
+        writers::{Text80x25, TextWriter},
+    };
+    use core::fmt::{self, Write};
+    use spinning_top::Spinlock;
+
+    struct Buffer {
+        bytes: [u8; 4096],
+        len: usize,
+    }
+
+    impl Buffer {
+        fn as_str(&self) -> &str {
+            core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_dump_registers() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text80x25::with_device(&vga);
+        text_mode.set_mode();
+        text_mode.set_cursor_position(12, 3);
+
+        let mut buffer = Buffer {
+            bytes: [0; 4096],
+            len: 0,
+        };
+        write!(buffer, "{}", vga.lock().dump_registers()).unwrap();
+        let report = buffer.as_str();
+        for line in [
+            "Video mode: 80x25 text with 16 colors",
+            "Resolution: 720x400 pixels",
+            "Character size: 9x16",
+            "Chain-4: off",
+            "Odd/even: on",
+            "Memory map: 3 (0xB8000 -> 0xBFFFF)",
+            "Emulation mode: Cga",
+            "Cursor: column 12, row 3, scan lines 13 -> 14",
+            "  0x1 -> 0x01 (0x00, 0x00, 0x2A)",
+            "Miscellaneous output: 0x67",
+        ]
+        .iter()
+        {
+            assert!(report.contains(line), "missing {:?} in\n{}", line, report);
+        }
+
+        vga.lock().set_video_mode(VideoMode::Mode320x200x256);
+        buffer.len = 0;
+        write!(buffer, "{}", vga.lock().dump_registers()).unwrap();
+        let report = buffer.as_str();
+        assert!(report.contains("Video mode: 320x200 graphics with 256 colors"));
+        assert!(report.contains("Chain-4: on"));
+        assert!(report.contains("Cursor: disabled"));
+        assert!(!report.contains("Character size"));
+    }
+}
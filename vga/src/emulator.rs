@@ -0,0 +1,796 @@
+//! A software model of vga hardware, used to exercise the register
+//! structs and writers on the host.
+
+use super::{
+    colors::PALETTE_SIZE,
+    io::{MemoryIo, PortIo},
+    registers::{
+        AttributeControllerIndex, CrtcControllerIndex, GraphicsControllerIndex, SequencerIndex,
+        ARX_DATA_ADDRESS, ARX_INDEX_ADDRESS, COLOR_PALETTE_DATA_ADDRESS,
+        COLOR_PALETTE_INDEX_READ_ADDRESS, COLOR_PALETTE_INDEX_WRITE_ADDRESSS, CRX_DATA_CGA_ADDRESS,
+        CRX_DATA_MDA_ADDRESS, CRX_INDEX_CGA_ADDRESS, CRX_INDEX_MDA_ADDRESS, FCR_CGA_WRITE_ADDRESS,
+        FCR_MDA_WRITE_ADDRESS, FCR_READ_ADDRESS, GRX_DATA_ADDRESS, GRX_INDEX_ADDRESS,
+        MSR_READ_ADDRESS, MSR_WRITE_ADDRESS, SRX_DATA_ADDRESS, SRX_INDEX_ADDRESS,
+        ST00_READ_ADDRESS, ST01_READ_CGA_ADDRESS, ST01_READ_MDA_ADDRESS,
+    },
+    renderer::{DisplayRegisters, Renderer},
+};
+use core::cell::RefCell;
+
+/// Represents the size of a single vga memory plane in bytes.
+pub const PLANE_SIZE: usize = 0x10000;
+
+const SEQUENCER_REGISTERS: usize = 0x08;
+const GRAPHICS_CONTROLLER_REGISTERS: usize = 0x19;
+const ATTRIBUTE_CONTROLLER_REGISTERS: usize = 0x15;
+const CRTC_CONTROLLER_REGISTERS: usize = 0x25;
+
+const LEGACY_FRAME_BUFFER_ADDRESS: usize = 0xA0000;
+
+// Number of `ST01` reads that make up one emulated frame, the last
+// `RETRACE_READS` of which are reported as vertical retrace.
+const FRAME_READS: u32 = 8;
+const RETRACE_READS: u32 = 2;
+
+/// A software model of a vga graphics card.
+///
+/// `&VgaEmulator` implements both `PortIo` and `MemoryIo`, so it can be used
+/// as the backend of a `Vga`, which in turn can be handed to any writer in
+/// `vga::writers`. The emulator models the four 64K memory planes, the
+/// memory read latches, set/reset, data rotation and logical operations,
+/// the bit mask, all write and read modes, as well as chain-4 and odd/even
+/// addressing.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use spinning_top::Spinlock;
+/// use vga::colors::Color16;
+/// use vga::emulator::VgaEmulator;
+/// use vga::vga::Vga;
+/// use vga::writers::{Graphics640x480x16, GraphicsWriter};
+///
+/// let emulator = VgaEmulator::new();
+/// let vga = Spinlock::new(Vga::with_io(&emulator));
+/// let mode = Graphics640x480x16::with_device(&vga);
+///
+/// mode.set_mode();
+/// mode.clear_screen(Color16::Black);
+/// mode.draw_line((10, 10), (20, 10), Color16::Yellow);
+///
+/// assert_eq!(emulator.read_pixel(15, 10), Color16::Yellow as u8);
+/// assert_eq!(emulator.read_pixel(15, 11), Color16::Black as u8);
+/// ```
+#[derive(Debug)]
+pub struct VgaEmulator {
+    state: RefCell<EmulatorState>,
+}
+
+#[derive(Debug)]
+struct EmulatorState {
+    planes: [[u8; PLANE_SIZE]; 4],
+    latches: [u8; 4],
+    miscellaneous_output: u8,
+    feature_control: u8,
+    status_reads: u32,
+    sequencer_index: u8,
+    sequencer_registers: [u8; SEQUENCER_REGISTERS],
+    graphics_controller_index: u8,
+    graphics_controller_registers: [u8; GRAPHICS_CONTROLLER_REGISTERS],
+    attribute_controller_index: u8,
+    attribute_controller_data: bool,
+    attribute_controller_registers: [u8; ATTRIBUTE_CONTROLLER_REGISTERS],
+    crtc_controller_index: u8,
+    crtc_controller_registers: [u8; CRTC_CONTROLLER_REGISTERS],
+    palette: [u8; PALETTE_SIZE],
+    palette_read_index: usize,
+    palette_write_index: usize,
+    frame_buffer_base: usize,
+}
+
+impl VgaEmulator {
+    /// Creates a new `VgaEmulator` with all registers and memory cleared,
+    /// except for the miscellaneous output register, which selects color
+    /// emulation.
+    pub const fn new() -> VgaEmulator {
+        VgaEmulator {
+            state: RefCell::new(EmulatorState {
+                planes: [[0; PLANE_SIZE]; 4],
+                latches: [0; 4],
+                miscellaneous_output: 0x01,
+                feature_control: 0,
+                status_reads: 0,
+                sequencer_index: 0,
+                sequencer_registers: [0; SEQUENCER_REGISTERS],
+                graphics_controller_index: 0,
+                graphics_controller_registers: [0; GRAPHICS_CONTROLLER_REGISTERS],
+                attribute_controller_index: 0,
+                attribute_controller_data: false,
+                attribute_controller_registers: [0; ATTRIBUTE_CONTROLLER_REGISTERS],
+                crtc_controller_index: 0,
+                crtc_controller_registers: [0; CRTC_CONTROLLER_REGISTERS],
+                palette: [0; PALETTE_SIZE],
+                palette_read_index: 0,
+                palette_write_index: 0,
+                frame_buffer_base: LEGACY_FRAME_BUFFER_ADDRESS,
+            }),
+        }
+    }
+
+    /// Returns the byte at `address` of the memory plane `plane`.
+    pub fn read_plane(&self, plane: usize, address: usize) -> u8 {
+        self.state.borrow().planes[plane & 0x3][address % PLANE_SIZE]
+    }
+
+    /// Sets the byte at `address` of the memory plane `plane` to `value`,
+    /// bypassing the graphics controller.
+    pub fn write_plane(&self, plane: usize, address: usize, value: u8) {
+        self.state.borrow_mut().planes[plane & 0x3][address % PLANE_SIZE] = value;
+    }
+
+    /// Returns the current contents of the memory read latches, indexed by plane.
+    pub fn latches(&self) -> [u8; 4] {
+        self.state.borrow().latches
+    }
+
+    /// Returns the current value of the miscellaneous output register.
+    pub fn miscellaneous_output(&self) -> u8 {
+        self.state.borrow().miscellaneous_output
+    }
+
+    /// Returns the current value of the sequencer register at `index`.
+    pub fn sequencer_register(&self, index: SequencerIndex) -> u8 {
+        self.state.borrow().sequencer_registers[usize::from(u8::from(index))]
+    }
+
+    /// Returns the current value of the graphics controller register at `index`.
+    pub fn graphics_controller_register(&self, index: GraphicsControllerIndex) -> u8 {
+        self.state
+            .borrow()
+            .graphics_controller_registers
+            .get(usize::from(u8::from(index)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the current value of the attribute controller register at `index`.
+    pub fn attribute_controller_register(&self, index: AttributeControllerIndex) -> u8 {
+        self.state.borrow().attribute_controller_registers[usize::from(u8::from(index))]
+    }
+
+    /// Returns the current value of the crtc controller register at `index`.
+    pub fn crtc_controller_register(&self, index: CrtcControllerIndex) -> u8 {
+        self.state.borrow().crtc_controller_registers[usize::from(u8::from(index))]
+    }
+
+    /// Copies the current 256 color palette into `palette`, with every 3
+    /// bytes representing a color.
+    pub fn read_palette(&self, palette: &mut [u8; PALETTE_SIZE]) {
+        palette.copy_from_slice(&self.state.borrow().palette);
+    }
+
+    /// Returns the color index of the pixel at `(x, y)`, decoded from vga
+    /// memory as the crtc controller would display it.
+    ///
+    /// 256 color modes (both chain-4 and unchained) return the full byte,
+    /// while planar 16 color modes combine one bit from each plane.
+    pub fn read_pixel(&self, x: usize, y: usize) -> u8 {
+        let state = self.state.borrow();
+        let line_start = state.start_address() + y * state.line_offset();
+        if state.graphics_controller_registers[0x05] & 0x40 != 0 {
+            let address = (line_start + x / 4) % PLANE_SIZE;
+            state.planes[x & 0x3][address]
+        } else {
+            let address = (line_start + x / 8) % PLANE_SIZE;
+            let bit = 7 - (x & 0x7);
+            (0..4).fold(0, |color, plane| {
+                color | (((state.planes[plane][address] >> bit) & 0x1) << plane)
+            })
+        }
+    }
+
+    /// Returns the registers that determine how vga memory is displayed.
+    pub fn display_registers(&self) -> DisplayRegisters {
+        let state = self.state.borrow();
+        let mut registers = DisplayRegisters {
+            miscellaneous_output: state.miscellaneous_output,
+            sequencer_registers: state.sequencer_registers,
+            crtc_controller_registers: [0; 0x19],
+            graphics_controller_registers: [0; 0x09],
+            attribute_controller_registers: state.attribute_controller_registers,
+        };
+        registers
+            .crtc_controller_registers
+            .copy_from_slice(&state.crtc_controller_registers[..0x19]);
+        registers
+            .graphics_controller_registers
+            .copy_from_slice(&state.graphics_controller_registers[..0x09]);
+        registers
+    }
+
+    /// Calls `f` with a `Renderer` for the current contents of vga memory,
+    /// the current registers and the current palette.
+    pub fn with_renderer<R>(&self, f: impl FnOnce(&Renderer<'_>) -> R) -> R {
+        let registers = self.display_registers();
+        let mut palette = [0; PALETTE_SIZE];
+        self.read_palette(&mut palette);
+        let state = self.state.borrow();
+        let [plane0, plane1, plane2, plane3] = &state.planes;
+        f(&Renderer::new(
+            [plane0, plane1, plane2, plane3],
+            registers,
+            &palette,
+        ))
+    }
+}
+
+impl Default for VgaEmulator {
+    fn default() -> VgaEmulator {
+        VgaEmulator::new()
+    }
+}
+
+impl EmulatorState {
+    fn is_color_emulation(&self) -> bool {
+        self.miscellaneous_output & 0x1 != 0
+    }
+
+    fn start_address(&self) -> usize {
+        usize::from(self.crtc_controller_registers[0x0C]) << 8
+            | usize::from(self.crtc_controller_registers[0x0D])
+    }
+
+    fn line_offset(&self) -> usize {
+        usize::from(self.crtc_controller_registers[0x13]) * 2
+    }
+
+    fn read_port(&mut self, port: u16) -> u8 {
+        match port {
+            ARX_INDEX_ADDRESS => self.attribute_controller_index,
+            ARX_DATA_ADDRESS => self
+                .attribute_controller_registers
+                .get(usize::from(self.attribute_controller_index & 0x1F))
+                .copied()
+                .unwrap_or(0),
+            ST00_READ_ADDRESS => 0x00,
+            SRX_INDEX_ADDRESS => self.sequencer_index,
+            SRX_DATA_ADDRESS => self
+                .sequencer_registers
+                .get(usize::from(self.sequencer_index))
+                .copied()
+                .unwrap_or(0),
+            COLOR_PALETTE_DATA_ADDRESS => {
+                let value = self.palette[self.palette_read_index];
+                self.palette_read_index = (self.palette_read_index + 1) % PALETTE_SIZE;
+                value
+            }
+            COLOR_PALETTE_INDEX_WRITE_ADDRESSS => (self.palette_write_index / 3) as u8,
+            FCR_READ_ADDRESS => self.feature_control,
+            MSR_READ_ADDRESS => self.miscellaneous_output,
+            GRX_INDEX_ADDRESS => self.graphics_controller_index,
+            GRX_DATA_ADDRESS => self
+                .graphics_controller_registers
+                .get(usize::from(self.graphics_controller_index))
+                .copied()
+                .unwrap_or(0),
+            CRX_INDEX_CGA_ADDRESS | CRX_INDEX_MDA_ADDRESS if self.crtc_port_enabled(port) => {
+                self.crtc_controller_index
+            }
+            CRX_DATA_CGA_ADDRESS | CRX_DATA_MDA_ADDRESS if self.crtc_port_enabled(port) => self
+                .crtc_controller_registers
+                .get(usize::from(self.crtc_controller_index))
+                .copied()
+                .unwrap_or(0),
+            ST01_READ_CGA_ADDRESS | ST01_READ_MDA_ADDRESS => {
+                self.attribute_controller_data = false;
+                self.status_reads = (self.status_reads + 1) % FRAME_READS;
+                if self.status_reads >= FRAME_READS - RETRACE_READS {
+                    0x09
+                } else {
+                    0x00
+                }
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write_port(&mut self, port: u16, value: u8) {
+        match port {
+            ARX_INDEX_ADDRESS => {
+                if self.attribute_controller_data {
+                    let index = usize::from(self.attribute_controller_index & 0x1F);
+                    if let Some(register) = self.attribute_controller_registers.get_mut(index) {
+                        *register = value;
+                    }
+                } else {
+                    self.attribute_controller_index = value & 0x3F;
+                }
+                self.attribute_controller_data = !self.attribute_controller_data;
+            }
+            MSR_WRITE_ADDRESS => self.miscellaneous_output = value,
+            SRX_INDEX_ADDRESS => self.sequencer_index = value,
+            SRX_DATA_ADDRESS => {
+                if let Some(register) = self
+                    .sequencer_registers
+                    .get_mut(usize::from(self.sequencer_index))
+                {
+                    *register = value;
+                }
+            }
+            COLOR_PALETTE_INDEX_READ_ADDRESS => self.palette_read_index = usize::from(value) * 3,
+            COLOR_PALETTE_INDEX_WRITE_ADDRESSS => self.palette_write_index = usize::from(value) * 3,
+            COLOR_PALETTE_DATA_ADDRESS => {
+                self.palette[self.palette_write_index] = value & 0x3F;
+                self.palette_write_index = (self.palette_write_index + 1) % PALETTE_SIZE;
+            }
+            GRX_INDEX_ADDRESS => self.graphics_controller_index = value,
+            GRX_DATA_ADDRESS => {
+                if let Some(register) = self
+                    .graphics_controller_registers
+                    .get_mut(usize::from(self.graphics_controller_index))
+                {
+                    *register = value;
+                }
+            }
+            CRX_INDEX_CGA_ADDRESS | CRX_INDEX_MDA_ADDRESS if self.crtc_port_enabled(port) => {
+                self.crtc_controller_index = value
+            }
+            CRX_DATA_CGA_ADDRESS | CRX_DATA_MDA_ADDRESS if self.crtc_port_enabled(port) => {
+                self.write_crtc_controller(value)
+            }
+            FCR_CGA_WRITE_ADDRESS | FCR_MDA_WRITE_ADDRESS => self.feature_control = value,
+            _ => (),
+        }
+    }
+
+    fn crtc_port_enabled(&self, port: u16) -> bool {
+        match port {
+            CRX_INDEX_CGA_ADDRESS | CRX_DATA_CGA_ADDRESS => self.is_color_emulation(),
+            _ => !self.is_color_emulation(),
+        }
+    }
+
+    fn write_crtc_controller(&mut self, value: u8) {
+        let index = usize::from(self.crtc_controller_index);
+        let protected = self.crtc_controller_registers[0x11] & 0x80 != 0;
+        if let Some(register) = self.crtc_controller_registers.get_mut(index) {
+            *register = match index {
+                // Only the line compare bit of the overflow register can be
+                // written while registers `CR[00:07]` are protected.
+                0x07 if protected => (*register & !0x10) | (value & 0x10),
+                0x00..=0x06 if protected => *register,
+                _ => value,
+            };
+        }
+    }
+
+    /// Translates a cpu `address` into an offset into the memory window
+    /// selected by the memory map bits of `GraphicsControllerIndex::Miscellaneous`,
+    /// with the legacy vga memory window mapped at the frame buffer base.
+    fn memory_offset(&self, address: usize) -> Option<usize> {
+        let address = address.checked_sub(self.frame_buffer_base)? + LEGACY_FRAME_BUFFER_ADDRESS;
+        let (start, size) = match (self.graphics_controller_registers[0x06] >> 0x2) & 0x3 {
+            0x0 => (0xA0000, 0x20000),
+            0x1 => (0xA0000, 0x10000),
+            0x2 => (0xB0000, 0x8000),
+            _ => (0xB8000, 0x8000),
+        };
+        if address >= start && address < start + size {
+            Some(address - start)
+        } else {
+            None
+        }
+    }
+
+    fn read_memory(&mut self, address: usize) -> u8 {
+        let offset = match self.memory_offset(address) {
+            Some(offset) => offset,
+            None => return 0xFF,
+        };
+        let memory_mode = self.sequencer_registers[0x04];
+        let graphics_mode = self.graphics_controller_registers[0x05];
+        let read_plane = usize::from(self.graphics_controller_registers[0x04] & 0x3);
+
+        // In chain 4 mode, the low 2 bits of the address select the plane,
+        // while in odd/even mode the low bit selects a plane of the pair
+        // selected by the read plane.
+        let (address, read_plane) = if memory_mode & 0x08 != 0 {
+            ((offset >> 2) % PLANE_SIZE, offset & 0x3)
+        } else if graphics_mode & 0x10 != 0 {
+            let plane = (read_plane & 0x2) | (offset & 0x1);
+            ((offset >> 1) % PLANE_SIZE, plane)
+        } else {
+            (offset % PLANE_SIZE, read_plane)
+        };
+        self.load_latches(address);
+        if graphics_mode & 0x08 == 0 {
+            return self.latches[read_plane];
+        }
+
+        // Read mode 1 compares every plane enabled by `ColorDontCare`
+        // against `ColorCompare`, returning 1 for each matching pixel.
+        let color_compare = self.graphics_controller_registers[0x02];
+        let color_dont_care = self.graphics_controller_registers[0x07];
+        let mut mismatch = 0;
+        for plane in 0..4 {
+            if color_dont_care & (1 << plane) != 0 {
+                mismatch |= self.latches[plane] ^ expand_bit(color_compare, plane);
+            }
+        }
+        !mismatch
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) {
+        let offset = match self.memory_offset(address) {
+            Some(offset) => offset,
+            None => return,
+        };
+        let memory_mode = self.sequencer_registers[0x04];
+        let plane_mask = self.sequencer_registers[0x02];
+
+        // In chain 4 mode, the low 2 bits of the address select the plane,
+        // while in odd/even mode, even addresses select planes 0 and 2 and odd
+        // addresses planes 1 and 3. The plane mask narrows these down further.
+        let (address, plane_mask) = if memory_mode & 0x08 != 0 {
+            let chain_4_mask = 1 << (offset & 0x3);
+            ((offset >> 2) % PLANE_SIZE, plane_mask & chain_4_mask)
+        } else if memory_mode & 0x04 == 0 {
+            let odd_even_mask = if offset & 0x1 == 0 { 0x5 } else { 0xA };
+            ((offset >> 1) % PLANE_SIZE, plane_mask & odd_even_mask)
+        } else {
+            (offset % PLANE_SIZE, plane_mask)
+        };
+        for plane in 0..4 {
+            if plane_mask & (1 << plane) != 0 {
+                self.planes[plane][address] = self.write_pipeline(plane, value);
+            }
+        }
+    }
+
+    /// Computes the byte written to `plane` for a cpu write of `value`,
+    /// as specified by the current write mode.
+    fn write_pipeline(&self, plane: usize, value: u8) -> u8 {
+        let set_reset = self.graphics_controller_registers[0x00];
+        let enable_set_reset = self.graphics_controller_registers[0x01];
+        let data_rotate = self.graphics_controller_registers[0x03];
+        let graphics_mode = self.graphics_controller_registers[0x05];
+        let mut bit_mask = self.graphics_controller_registers[0x08];
+        let latch = self.latches[plane];
+        let rotated = value.rotate_right(u32::from(data_rotate & 0x7));
+
+        let data = match graphics_mode & 0x3 {
+            0x0 => {
+                if enable_set_reset & (1 << plane) != 0 {
+                    expand_bit(set_reset, plane)
+                } else {
+                    rotated
+                }
+            }
+            0x1 => return latch,
+            0x2 => expand_bit(value, plane),
+            _ => {
+                bit_mask &= rotated;
+                expand_bit(set_reset, plane)
+            }
+        };
+
+        let data = match (data_rotate >> 3) & 0x3 {
+            0x0 => data,
+            0x1 => data & latch,
+            0x2 => data | latch,
+            _ => data ^ latch,
+        };
+
+        (data & bit_mask) | (latch & !bit_mask)
+    }
+
+    fn load_latches(&mut self, address: usize) {
+        for plane in 0..4 {
+            self.latches[plane] = self.planes[plane][address];
+        }
+    }
+}
+
+/// Expands bit `plane` of `value` into a full byte.
+fn expand_bit(value: u8, plane: usize) -> u8 {
+    if value & (1 << plane) != 0 {
+        0xFF
+    } else {
+        0x00
+    }
+}
+
+impl PortIo for &VgaEmulator {
+    unsafe fn read_port(&mut self, port: u16) -> u8 {
+        self.state.borrow_mut().read_port(port)
+    }
+
+    unsafe fn write_port(&mut self, port: u16, value: u8) {
+        self.state.borrow_mut().write_port(port, value);
+    }
+}
+
+impl MemoryIo for &VgaEmulator {
+    unsafe fn read_memory(&mut self, address: usize) -> u8 {
+        self.state.borrow_mut().read_memory(address)
+    }
+
+    unsafe fn write_memory(&mut self, address: usize, value: u8) {
+        self.state.borrow_mut().write_memory(address, value);
+    }
+
+    fn set_frame_buffer_base(&mut self, base: usize) {
+        self.state.borrow_mut().frame_buffer_base = base;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        colors::{Color16, TextModeColor},
+        fonts::TEXT_8X16_FONT,
+        registers::{PlaneMask, WriteMode},
+        vga::{Vga, VideoMode},
+        writers::{ScreenCharacter, Text80x25, TextWriter},
+    };
+    use spinning_top::Spinlock;
+
+    fn planar_vga(emulator: &VgaEmulator) -> Vga<&VgaEmulator> {
+        let mut vga = Vga::with_io(emulator);
+        vga.set_video_mode(VideoMode::Mode640x480x16);
+        vga
+    }
+
+    #[test]
+    fn test_write_mode_0_set_reset() {
+        let emulator = VgaEmulator::new();
+        let mut vga = planar_vga(&emulator);
+        vga.sequencer_registers
+            .set_plane_mask(PlaneMask::ALL_PLANES);
+        vga.graphics_controller_registers
+            .write_set_reset(Color16::Cyan);
+        vga.graphics_controller_registers
+            .write_enable_set_reset(0xF);
+        vga.graphics_controller_registers
+            .set_write_mode(WriteMode::Mode0);
+        vga.graphics_controller_registers.set_bit_mask(0x0F);
+        unsafe {
+            vga.write_memory(0xA0000, 0x00);
+        }
+        assert_eq!(emulator.read_plane(0, 0), 0x0F);
+        assert_eq!(emulator.read_plane(1, 0), 0x0F);
+        assert_eq!(emulator.read_plane(2, 0), 0x00);
+        assert_eq!(emulator.read_plane(3, 0), 0x00);
+    }
+
+    #[test]
+    fn test_write_mode_2_keeps_masked_bits_from_latches() {
+        let emulator = VgaEmulator::new();
+        let mut vga = planar_vga(&emulator);
+        for plane in 0..4 {
+            emulator.write_plane(plane, 1, 0xFF);
+        }
+        vga.sequencer_registers
+            .set_plane_mask(PlaneMask::ALL_PLANES);
+        vga.graphics_controller_registers
+            .set_write_mode(WriteMode::Mode2);
+        vga.graphics_controller_registers.set_bit_mask(0x80);
+        unsafe {
+            vga.read_memory(0xA0001);
+            vga.write_memory(0xA0001, u8::from(Color16::Red));
+        }
+        assert_eq!(emulator.read_pixel(8, 0), Color16::Red as u8);
+        assert_eq!(emulator.read_pixel(9, 0), Color16::White as u8);
+    }
+
+    #[test]
+    fn test_write_mode_1_copies_latches() {
+        let emulator = VgaEmulator::new();
+        let mut vga = planar_vga(&emulator);
+        for plane in 0..4 {
+            emulator.write_plane(plane, 0, plane as u8 + 1);
+        }
+        vga.sequencer_registers
+            .set_plane_mask(PlaneMask::ALL_PLANES);
+        vga.graphics_controller_registers
+            .set_write_mode(WriteMode::Mode1);
+        unsafe {
+            vga.read_memory(0xA0000);
+            vga.write_memory(0xA0010, 0x00);
+        }
+        for plane in 0..4 {
+            assert_eq!(emulator.read_plane(plane, 0x10), plane as u8 + 1);
+        }
+    }
+
+    #[test]
+    fn test_data_rotate_xor() {
+        let emulator = VgaEmulator::new();
+        let mut vga = planar_vga(&emulator);
+        emulator.write_plane(0, 0, 0xF0);
+        vga.sequencer_registers.set_plane_mask(PlaneMask::PLANE0);
+        vga.graphics_controller_registers
+            .set_write_mode(WriteMode::Mode0);
+        vga.graphics_controller_registers
+            .write_enable_set_reset(0x0);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::DataRotate, 0x18);
+        vga.graphics_controller_registers.set_bit_mask(0xFF);
+        unsafe {
+            vga.read_memory(0xA0000);
+            vga.write_memory(0xA0000, 0xFF);
+        }
+        assert_eq!(emulator.read_plane(0, 0), 0x0F);
+    }
+
+    #[test]
+    fn test_read_mode_1_color_compare() {
+        let emulator = VgaEmulator::new();
+        let mut vga = planar_vga(&emulator);
+        emulator.write_plane(0, 0, 0b1010_0000);
+        emulator.write_plane(2, 0, 0b1000_0000);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::GraphicsMode, 0x08);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::ColorCompare, 0x05);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::ColorDontCare, 0x0F);
+        assert_eq!(unsafe { vga.read_memory(0xA0000) }, 0b1000_0000);
+    }
+
+    #[test]
+    fn test_chain_4_addressing() {
+        let emulator = VgaEmulator::new();
+        let mut vga = Vga::with_io(&emulator);
+        vga.set_video_mode(VideoMode::Mode320x200x256);
+        unsafe {
+            vga.write_memory(0xA0000 + 321, 0x42);
+        }
+        assert_eq!(emulator.read_plane(1, 80), 0x42);
+        assert_eq!(emulator.read_pixel(1, 1), 0x42);
+    }
+
+    #[test]
+    fn test_chain_4_write_pipeline_and_read_mode_1() {
+        let emulator = VgaEmulator::new();
+        let mut vga = Vga::with_io(&emulator);
+        vga.set_video_mode(VideoMode::Mode320x200x256);
+
+        // Chain 4 writes go through the function select and bit mask.
+        emulator.write_plane(1, 80, 0xF0);
+        vga.graphics_controller_registers.set_bit_mask(0x3C);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::DataRotate, 0x18);
+        unsafe {
+            vga.read_memory(0xA0000 + 321);
+            vga.write_memory(0xA0000 + 321, 0xFF);
+        }
+        assert_eq!(emulator.read_plane(1, 80), 0xCC);
+
+        // And through set/reset, which only reaches the addressed plane.
+        vga.graphics_controller_registers.set_bit_mask(0xFF);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::DataRotate, 0x00);
+        vga.graphics_controller_registers
+            .write_set_reset(Color16::Cyan);
+        vga.graphics_controller_registers
+            .write_enable_set_reset(0xF);
+        unsafe {
+            vga.write_memory(0xA0000 + 4, 0x00);
+        }
+        assert_eq!(emulator.read_plane(0, 1), 0xFF);
+        assert_eq!(emulator.read_plane(1, 1), 0x00);
+
+        // Chain 4 reads compare the colors of all 4 planes in read mode 1.
+        emulator.write_plane(0, 2, 0b1010_0000);
+        emulator.write_plane(2, 2, 0b1000_0000);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::GraphicsMode, 0x48);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::ColorCompare, 0x05);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::ColorDontCare, 0x0F);
+        assert_eq!(unsafe { vga.read_memory(0xA0000 + 9) }, 0b1000_0000);
+    }
+
+    #[test]
+    fn test_odd_even_addressing() {
+        let emulator = VgaEmulator::new();
+        let mut vga = Vga::with_io(&emulator);
+        vga.set_video_mode(VideoMode::Mode80x25);
+        unsafe {
+            vga.write_memory(0xB8000 + 4, b'A');
+            vga.write_memory(0xB8000 + 5, 0x1E);
+        }
+        assert_eq!(emulator.read_plane(0, 2), b'A');
+        assert_eq!(emulator.read_plane(1, 2), 0x1E);
+        assert_eq!(unsafe { vga.read_memory(0xB8000 + 5) }, 0x1E);
+    }
+
+    #[test]
+    fn test_odd_even_write_pipeline() {
+        let emulator = VgaEmulator::new();
+        let mut vga = Vga::with_io(&emulator);
+        vga.set_video_mode(VideoMode::Mode80x25);
+
+        // Odd/even writes go through the bit mask and function select,
+        // and reach plane 2 or 3 when the plane mask enables them.
+        emulator.write_plane(0, 3, 0xF0);
+        vga.sequencer_registers
+            .set_plane_mask(PlaneMask::ALL_PLANES);
+        vga.graphics_controller_registers.set_bit_mask(0x0F);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::DataRotate, 0x18);
+        unsafe {
+            vga.read_memory(0xB8000 + 6);
+            vga.write_memory(0xB8000 + 6, 0xFF);
+        }
+        assert_eq!(emulator.read_plane(0, 3), 0xFF);
+        assert_eq!(emulator.read_plane(1, 3), 0x00);
+        assert_eq!(emulator.read_plane(2, 3), 0x0F);
+        assert_eq!(emulator.read_plane(3, 3), 0x00);
+
+        vga.sequencer_registers.set_plane_mask(PlaneMask::PLANE1);
+        vga.graphics_controller_registers.set_bit_mask(0xFF);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::DataRotate, 0x00);
+        unsafe {
+            vga.write_memory(0xB8000 + 6, 0x11);
+            vga.write_memory(0xB8000 + 7, 0x22);
+        }
+        assert_eq!(emulator.read_plane(0, 3), 0xFF);
+        assert_eq!(emulator.read_plane(1, 3), 0x22);
+    }
+
+    #[test]
+    fn test_crtc_protection() {
+        let emulator = VgaEmulator::new();
+        let mut vga = Vga::with_io(&emulator);
+        let emulation_mode = vga.get_emulation_mode();
+        vga.crtc_controller_registers.write(
+            emulation_mode,
+            CrtcControllerIndex::VerticalSyncEnd,
+            0x80,
+        );
+        vga.crtc_controller_registers.write(
+            emulation_mode,
+            CrtcControllerIndex::HorizontalTotal,
+            0x5F,
+        );
+        assert_eq!(
+            emulator.crtc_controller_register(CrtcControllerIndex::HorizontalTotal),
+            0x00
+        );
+        vga.set_video_mode(VideoMode::Mode80x25);
+        assert_eq!(
+            emulator.crtc_controller_register(CrtcControllerIndex::HorizontalTotal),
+            0x5F
+        );
+    }
+
+    #[test]
+    fn test_text_writer() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text80x25::with_device(&vga);
+        let color = TextModeColor::new(Color16::Yellow, Color16::Blue);
+        let screen_character = ScreenCharacter::new(b'R', color);
+
+        text_mode.set_mode();
+        text_mode.clear_screen().unwrap();
+        text_mode.write_character(3, 1, screen_character).unwrap();
+
+        assert_eq!(text_mode.read_character(3, 1), Ok(screen_character));
+        assert_eq!(emulator.read_plane(0, 83), b'R');
+        assert_eq!(emulator.read_plane(1, 83), u8::from(color));
+        for row in 0..16 {
+            assert_eq!(
+                emulator.read_plane(2, usize::from(b'R') * 32 + row),
+                TEXT_8X16_FONT.font_data[usize::from(b'R') * 16 + row]
+            );
+        }
+    }
+}
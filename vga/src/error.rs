@@ -0,0 +1,43 @@
+//! The error type of fallible vga operations.
+
+use super::vga::VideoModeInfo;
+use core::fmt;
+
+/// Represents the errors returned by fallible vga operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The value isn't a valid `EmulationMode`.
+    InvalidEmulationMode(u8),
+    /// The value isn't a valid memory map of the graphics controller
+    /// `Miscellaneous` register.
+    InvalidMemoryMap(u8),
+    /// The value isn't a valid plane of vga memory.
+    InvalidPlane(u8),
+    /// The value isn't a valid `Color16`.
+    InvalidColor(u8),
+    /// The registers don't match any `VideoMode`, and describe the
+    /// contained mode instead.
+    UnknownVideoMode(VideoModeInfo),
+    /// A writer was used while the card is in a different video mode,
+    /// which is described by the contained `VideoModeInfo`.
+    UnexpectedVideoMode(VideoModeInfo),
+    /// The value is outside of the range accepted by the operation, such
+    /// as a scroll position past the end of vga memory.
+    OutOfRange(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidEmulationMode(value) => {
+                write!(f, "{:#X} is an invalid emulation mode", value)
+            }
+            Error::InvalidMemoryMap(value) => write!(f, "{:#X} is an invalid memory map", value),
+            Error::InvalidPlane(value) => write!(f, "{} is an invalid plane", value),
+            Error::InvalidColor(value) => write!(f, "{:#X} is an invalid Color16", value),
+            Error::UnknownVideoMode(info) => write!(f, "unknown video mode {}", info),
+            Error::UnexpectedVideoMode(info) => write!(f, "unexpected video mode {}", info),
+            Error::OutOfRange(value) => write!(f, "{} is out of range", value),
+        }
+    }
+}
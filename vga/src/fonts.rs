@@ -1,6 +1,8 @@
 //! Common font structures used in vga programming.
 
-/// Represents a font to be used for text mode.
+/// Represents a font to be used for text mode, or for drawing text in
+/// graphics modes.
+#[derive(Debug)]
 pub struct VgaFont {
     /// Represents the number of characters contained in the font.
     pub characters: u16,
@@ -10,6 +12,80 @@ pub struct VgaFont {
     pub font_data: &'static [u8],
 }
 
+impl VgaFont {
+    /// Returns the rows of the glyph at `index`, one byte per row with the
+    /// leftmost pixel in the most significant bit, or `None` if the font
+    /// doesn't contain `index`.
+    pub fn glyph(&self, index: u8) -> Option<&'static [u8]> {
+        let height = usize::from(self.character_height);
+        let start = usize::from(index) * height;
+        if u16::from(index) < self.characters {
+            self.font_data.get(start..start + height)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the rows of the glyph for `character`, as mapped to code
+    /// page 437 by `char_to_cp437`, or `None` if there is no such glyph.
+    pub fn glyph_for(&self, character: char) -> Option<&'static [u8]> {
+        char_to_cp437(character).and_then(|index| self.glyph(index))
+    }
+}
+
+/// The characters shown for the control codes 0x00 to 0x1F of code page 437.
+const CP437_CONTROL: [char; 32] = [
+    '\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼', '►', '◄', '↕',
+    '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// The characters of code page 437 from 0x80 to 0xFF.
+const CP437_EXTENDED: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', 'É', 'æ', 'Æ',
+    'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', 'á', 'í', 'ó', 'ú', 'ñ', 'Ñ',
+    'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕',
+    '╣', '║', '╗', '╝', '╜', '╛', '┐', '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦',
+    '╠', '═', '╬', '╧', '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐',
+    '▀', 'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', '≡', '±',
+    '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+/// Maps `character` to its code point in code page 437, the character set
+/// of the vga bios fonts such as `TEXT_8X8_FONT` and `TEXT_8X16_FONT`.
+///
+/// Ascii characters map to themselves, and the symbols that code page 437
+/// shows for control codes, such as `'☺'` and `'♥'`, map to those control
+/// codes, as does `'⌂'` to 0x7F. Returns `None` for characters that aren't
+/// in code page 437.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use vga::fonts::char_to_cp437;
+///
+/// assert_eq!(char_to_cp437('A'), Some(0x41));
+/// assert_eq!(char_to_cp437('╔'), Some(0xC9));
+/// assert_eq!(char_to_cp437('♥'), Some(0x03));
+/// assert_eq!(char_to_cp437('€'), None);
+/// ```
+pub fn char_to_cp437(character: char) -> Option<u8> {
+    if character.is_ascii() {
+        return Some(character as u8);
+    }
+    if character == '⌂' {
+        return Some(0x7F);
+    }
+    if let Some(index) = CP437_CONTROL.iter().position(|&c| c == character) {
+        return Some(index as u8);
+    }
+    CP437_EXTENDED
+        .iter()
+        .position(|&c| c == character)
+        .map(|index| 0x80 + index as u8)
+}
+
 /// Standard 8x8 character font.
 pub const TEXT_8X8_FONT: VgaFont = VgaFont {
     characters: 256,
@@ -0,0 +1,155 @@
+//! Backends used to access the vga hardware.
+
+use x86_64::instructions::port::Port;
+
+/// Provides byte sized access to the I/O ports used by the vga registers.
+///
+/// Every register struct in `vga::registers` is generic over a `PortIo`,
+/// defaulting to `X86Io`, which talks to the real hardware. A custom backend
+/// can be used to record or emulate register accesses, for example when
+/// testing register sequences on the host.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use core::cell::RefCell;
+/// use vga::io::PortIo;
+/// use vga::vga::{Vga, VideoMode};
+///
+/// #[derive(Clone, Copy)]
+/// struct Recorder<'a>(&'a RefCell<Vec<(u16, u8)>>);
+///
+/// impl PortIo for Recorder<'_> {
+///     unsafe fn read_port(&mut self, _port: u16) -> u8 {
+///         0x01
+///     }
+///
+///     unsafe fn write_port(&mut self, port: u16, value: u8) {
+///         self.0.borrow_mut().push((port, value));
+///     }
+/// }
+///
+/// let writes = RefCell::new(Vec::new());
+/// let mut vga = Vga::with_io(Recorder(&writes));
+/// vga.set_video_mode(VideoMode::Mode80x25);
+///
+/// // The miscellaneous output register is written first.
+/// assert_eq!(writes.borrow()[0], (0x3C2, 0x67));
+/// // Followed by the sequencer reset register.
+/// assert_eq!(writes.borrow()[1..3], [(0x3C4, 0x00), (0x3C5, 0x03)]);
+/// ```
+pub trait PortIo {
+    /// Reads a byte from the I/O port at `port`.
+    ///
+    /// # Safety
+    ///
+    /// Reading from an I/O port can have side effects on the hardware.
+    unsafe fn read_port(&mut self, port: u16) -> u8;
+
+    /// Writes `value` to the I/O port at `port`.
+    ///
+    /// # Safety
+    ///
+    /// Writing to an I/O port can have side effects on the hardware.
+    unsafe fn write_port(&mut self, port: u16, value: u8);
+}
+
+/// Provides byte sized access to vga memory, as seen by the cpu.
+///
+/// Addresses passed to a `MemoryIo` are virtual addresses inside of the
+/// legacy vga memory window `0xA0000 -> 0xBFFFF`, offset by the frame
+/// buffer base set with `Vga::set_frame_buffer_base`. The `VgaEmulator` in
+/// `vga::emulator` implements this trait to model how the graphics
+/// controller transforms those accesses, translating them back into the
+/// legacy window with the base passed to `set_frame_buffer_base`.
+pub trait MemoryIo {
+    /// Reads the byte at `address`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be readable, and reading it loads the vga latches.
+    unsafe fn read_memory(&mut self, address: usize) -> u8;
+
+    /// Writes `value` to the byte at `address`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be writeable.
+    unsafe fn write_memory(&mut self, address: usize, value: u8);
+
+    /// Writes `value` to `count` consecutive bytes starting at `address`.
+    ///
+    /// # Safety
+    ///
+    /// Every byte in the range must be writeable.
+    unsafe fn fill_memory(&mut self, address: usize, value: u8, count: usize) {
+        for offset in 0..count {
+            self.write_memory(address + offset, value);
+        }
+    }
+
+    /// Writes the bytes of `data` to consecutive bytes starting at `address`.
+    ///
+    /// # Safety
+    ///
+    /// Every byte in the range must be writeable.
+    unsafe fn copy_to_memory(&mut self, address: usize, data: &[u8]) {
+        for (offset, value) in data.iter().enumerate() {
+            self.write_memory(address + offset, *value);
+        }
+    }
+
+    /// Called by `Vga::set_frame_buffer_base` with the virtual address that
+    /// the legacy vga memory window is mapped at. Backends that access the
+    /// addresses they're given directly don't need it, so this does nothing
+    /// by default.
+    fn set_frame_buffer_base(&mut self, _base: usize) {}
+}
+
+/// Accesses the real vga hardware using x86 `in` and `out` instructions,
+/// and the vga memory window at the addresses it's given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct X86Io;
+
+impl X86Io {
+    /// Creates a new `X86Io`.
+    pub const fn new() -> X86Io {
+        X86Io
+    }
+}
+
+impl PortIo for X86Io {
+    #[inline]
+    unsafe fn read_port(&mut self, port: u16) -> u8 {
+        Port::new(port).read()
+    }
+
+    #[inline]
+    unsafe fn write_port(&mut self, port: u16, value: u8) {
+        Port::new(port).write(value);
+    }
+}
+
+impl MemoryIo for X86Io {
+    #[inline]
+    unsafe fn read_memory(&mut self, address: usize) -> u8 {
+        (address as *const u8).read_volatile()
+    }
+
+    #[inline]
+    unsafe fn write_memory(&mut self, address: usize, value: u8) {
+        (address as *mut u8).write_volatile(value);
+    }
+
+    #[inline]
+    unsafe fn fill_memory(&mut self, address: usize, value: u8, count: usize) {
+        (address as *mut u8).write_bytes(value, count);
+    }
+
+    #[inline]
+    unsafe fn copy_to_memory(&mut self, address: usize, data: &[u8]) {
+        (address as *mut u8).copy_from_nonoverlapping(data.as_ptr(), data.len());
+    }
+}
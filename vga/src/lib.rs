@@ -10,7 +10,14 @@
 pub mod colors;
 pub mod configurations;
 pub mod drawing;
+pub mod dump;
+pub mod emulator;
+mod error;
 pub mod fonts;
+pub mod io;
 pub mod registers;
+pub mod renderer;
 pub mod vga;
 pub mod writers;
+
+pub use error::Error;
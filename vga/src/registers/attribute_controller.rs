@@ -2,7 +2,34 @@ use super::{
     EmulationMode, ARX_DATA_ADDRESS, ARX_INDEX_ADDRESS, ST01_READ_CGA_ADDRESS,
     ST01_READ_MDA_ADDRESS,
 };
-use x86_64::instructions::port::Port;
+use crate::io::{PortIo, X86Io};
+use bitflags::bitflags;
+
+bitflags! {
+    /// Represents the value of the `AttributeControllerIndex::ModeControl` register.
+    pub struct AttributeModeControl: u8 {
+        /// Represents the `Attribute Controller Graphics Enable` field, which
+        /// selects graphics rather than alphanumeric mode.
+        const GRAPHICS = 0b0000_0001;
+        /// Represents the `Monochrome Emulation` field.
+        const MONOCHROME = 0b0000_0010;
+        /// Represents the `Line Graphics Enable` field, which repeats the
+        /// eighth column of characters 0xC0 through 0xDF in the ninth.
+        const LINE_GRAPHICS = 0b0000_0100;
+        /// Represents the `Blink Enable` field, which makes bit 7 of the
+        /// text attribute blink rather than select a bright background.
+        const BLINK = 0b0000_1000;
+        /// Represents the `Pixel Panning Mode` field, which makes the lines
+        /// after a line compare match ignore the horizontal pixel panning.
+        const PIXEL_PANNING_MODE = 0b0010_0000;
+        /// Represents the `8-bit Color Enable` field, which combines two
+        /// 4 bit pixels into one 8 bit pixel, as in mode 13h.
+        const PIXEL_WIDTH = 0b0100_0000;
+        /// Represents the `Palette Bits 5-4 Select` field, which takes bits
+        /// 5 and 4 of the color from the `Color Select` register.
+        const PALETTE_BITS_5_4_SELECT = 0b1000_0000;
+    }
+}
 
 /// Represents an index for the attribute controller registers.
 #[derive(Debug, Copy, Clone)]
@@ -60,21 +87,13 @@ impl From<AttributeControllerIndex> for u8 {
 
 /// Represents the attribute controller registers on vga hardware.
 #[derive(Debug)]
-pub struct AttributeControllerRegisters {
-    arx_index: Port<u8>,
-    arx_data: Port<u8>,
-    st01_read_cga: Port<u8>,
-    st01_read_mda: Port<u8>,
+pub struct AttributeControllerRegisters<P: PortIo = X86Io> {
+    port_io: P,
 }
 
-impl AttributeControllerRegisters {
-    pub(crate) fn new() -> AttributeControllerRegisters {
-        AttributeControllerRegisters {
-            arx_index: Port::new(ARX_INDEX_ADDRESS),
-            arx_data: Port::new(ARX_DATA_ADDRESS),
-            st01_read_cga: Port::new(ST01_READ_CGA_ADDRESS),
-            st01_read_mda: Port::new(ST01_READ_MDA_ADDRESS),
-        }
+impl<P: PortIo> AttributeControllerRegisters<P> {
+    pub(crate) fn new(port_io: P) -> AttributeControllerRegisters<P> {
+        AttributeControllerRegisters { port_io }
     }
 
     /// Reads the current value of the attribute controller, as specified
@@ -82,7 +101,7 @@ impl AttributeControllerRegisters {
     pub fn read(&mut self, emulation_mode: EmulationMode, index: AttributeControllerIndex) -> u8 {
         self.toggle_index(emulation_mode);
         self.set_index(index);
-        unsafe { self.arx_data.read() }
+        unsafe { self.port_io.read_port(ARX_DATA_ADDRESS) }
     }
 
     /// Writes the `value` to the attribute controller, as specified
@@ -96,7 +115,7 @@ impl AttributeControllerRegisters {
         self.toggle_index(emulation_mode);
         self.set_index(index);
         unsafe {
-            self.arx_index.write(value);
+            self.port_io.write_port(ARX_INDEX_ADDRESS, value);
         }
     }
 
@@ -110,9 +129,10 @@ impl AttributeControllerRegisters {
     /// 1 = Enable. Attribute controller color registers (AR[00:0F]) are inaccessible by the CPU.
     pub fn blank_screen(&mut self, emulation_mode: EmulationMode) {
         self.toggle_index(emulation_mode);
-        let arx_index_value = unsafe { self.arx_index.read() };
+        let arx_index_value = unsafe { self.port_io.read_port(ARX_INDEX_ADDRESS) };
         unsafe {
-            self.arx_index.write(arx_index_value & 0xDF);
+            self.port_io
+                .write_port(ARX_INDEX_ADDRESS, arx_index_value & 0xDF);
         }
     }
 
@@ -126,25 +146,116 @@ impl AttributeControllerRegisters {
     /// 1 = Enable. Attribute controller color registers (AR[00:0F]) are inaccessible by the CPU.
     pub fn unblank_screen(&mut self, emulation_mode: EmulationMode) {
         self.toggle_index(emulation_mode);
-        let arx_index_value = unsafe { self.arx_index.read() };
+        let arx_index_value = unsafe { self.port_io.read_port(ARX_INDEX_ADDRESS) };
         unsafe {
-            self.arx_index.write(arx_index_value | 0x20);
+            self.port_io
+                .write_port(ARX_INDEX_ADDRESS, arx_index_value | 0x20);
+        }
+    }
+
+    /// Sets the number of pixels the display is shifted to the left to
+    /// `panning`, as specified by `emulation_mode`.
+    ///
+    /// Unlike `write`, this keeps the display enabled, so it can be used
+    /// while the screen is visible.
+    pub fn set_horizontal_pixel_panning(&mut self, emulation_mode: EmulationMode, panning: u8) {
+        self.set_index_enabled(
+            emulation_mode,
+            AttributeControllerIndex::HorizontalPixelPanning,
+        );
+        unsafe {
+            self.port_io.write_port(ARX_INDEX_ADDRESS, panning & 0x0F);
+        }
+    }
+
+    /// Sets whether the lines after a line compare match, which make up the
+    /// lower window of a split screen, ignore the horizontal pixel panning,
+    /// as specified by `emulation_mode`.
+    ///
+    /// Like `set_horizontal_pixel_panning`, this keeps the display enabled.
+    pub fn set_pixel_panning_compatibility(
+        &mut self,
+        emulation_mode: EmulationMode,
+        enabled: bool,
+    ) {
+        self.modify_mode_control(emulation_mode, |mut mode_control| {
+            mode_control.set(AttributeModeControl::PIXEL_PANNING_MODE, enabled);
+            mode_control
+        });
+    }
+
+    /// Reads the current value of the `AttributeControllerIndex::ModeControl`
+    /// register, as specified by `emulation_mode`.
+    ///
+    /// Unlike `read`, this keeps the display enabled.
+    pub fn read_mode_control(&mut self, emulation_mode: EmulationMode) -> AttributeModeControl {
+        self.set_index_enabled(emulation_mode, AttributeControllerIndex::ModeControl);
+        AttributeModeControl::from_bits_truncate(unsafe {
+            self.port_io.read_port(ARX_DATA_ADDRESS)
+        })
+    }
+
+    /// Writes `mode_control` to the `AttributeControllerIndex::ModeControl`
+    /// register, as specified by `emulation_mode`.
+    ///
+    /// Unlike `write`, this keeps the display enabled.
+    pub fn write_mode_control(
+        &mut self,
+        emulation_mode: EmulationMode,
+        mode_control: AttributeModeControl,
+    ) {
+        self.set_index_enabled(emulation_mode, AttributeControllerIndex::ModeControl);
+        unsafe {
+            self.port_io
+                .write_port(ARX_INDEX_ADDRESS, mode_control.bits());
+        }
+    }
+
+    /// Replaces the value of the `AttributeControllerIndex::ModeControl`
+    /// register with the result of `f`, keeping its reserved bits.
+    ///
+    /// Like `write_mode_control`, this keeps the display enabled.
+    pub fn modify_mode_control(
+        &mut self,
+        emulation_mode: EmulationMode,
+        f: impl FnOnce(AttributeModeControl) -> AttributeModeControl,
+    ) {
+        self.set_index_enabled(emulation_mode, AttributeControllerIndex::ModeControl);
+        let value = unsafe { self.port_io.read_port(ARX_DATA_ADDRESS) };
+        let reserved = value & !AttributeModeControl::all().bits();
+        let mode_control = f(AttributeModeControl::from_bits_truncate(value)).bits() | reserved;
+        unsafe {
+            self.port_io.write_port(ARX_INDEX_ADDRESS, mode_control);
         }
     }
 
     fn set_index(&mut self, index: AttributeControllerIndex) {
         unsafe {
-            self.arx_index.write(u8::from(index));
+            self.port_io.write_port(ARX_INDEX_ADDRESS, u8::from(index));
+        }
+    }
+
+    /// Selects `index` while keeping the palette address source bit set,
+    /// so the display stays enabled.
+    fn set_index_enabled(
+        &mut self,
+        emulation_mode: EmulationMode,
+        index: AttributeControllerIndex,
+    ) {
+        self.toggle_index(emulation_mode);
+        unsafe {
+            self.port_io
+                .write_port(ARX_INDEX_ADDRESS, u8::from(index) | 0x20);
         }
     }
 
     fn toggle_index(&mut self, emulation_mode: EmulationMode) {
         let st01_read = match emulation_mode {
-            EmulationMode::Cga => &mut self.st01_read_cga,
-            EmulationMode::Mda => &mut self.st01_read_mda,
+            EmulationMode::Cga => ST01_READ_CGA_ADDRESS,
+            EmulationMode::Mda => ST01_READ_MDA_ADDRESS,
         };
         unsafe {
-            st01_read.read();
+            self.port_io.read_port(st01_read);
         }
     }
 }
@@ -2,34 +2,29 @@ use super::{
     COLOR_PALETTE_DATA_ADDRESS, COLOR_PALETTE_INDEX_READ_ADDRESS,
     COLOR_PALETTE_INDEX_WRITE_ADDRESSS, PALETTE_SIZE,
 };
-use x86_64::instructions::port::Port;
+use crate::io::{PortIo, X86Io};
 
 /// Represents the color palette registers on vga hardware.
 #[derive(Debug)]
-pub struct ColorPaletteRegisters {
-    data_port: Port<u8>,
-    index_read_port: Port<u8>,
-    index_write_port: Port<u8>,
+pub struct ColorPaletteRegisters<P: PortIo = X86Io> {
+    port_io: P,
 }
 
-impl ColorPaletteRegisters {
-    pub(crate) fn new() -> ColorPaletteRegisters {
-        ColorPaletteRegisters {
-            data_port: Port::new(COLOR_PALETTE_DATA_ADDRESS),
-            index_read_port: Port::new(COLOR_PALETTE_INDEX_READ_ADDRESS),
-            index_write_port: Port::new(COLOR_PALETTE_INDEX_WRITE_ADDRESSS),
-        }
+impl<P: PortIo> ColorPaletteRegisters<P> {
+    pub(crate) fn new(port_io: P) -> ColorPaletteRegisters<P> {
+        ColorPaletteRegisters { port_io }
     }
 
     /// Loads a 256 color palette, as specified by `palette`, with every 3
     /// bytes representing a color.
     pub fn load_palette(&mut self, palette: &[u8; PALETTE_SIZE]) {
         unsafe {
-            self.index_write_port.write(0);
+            self.port_io
+                .write_port(COLOR_PALETTE_INDEX_WRITE_ADDRESSS, 0);
         }
         for i in palette.iter() {
             unsafe {
-                self.data_port.write(*i);
+                self.port_io.write_port(COLOR_PALETTE_DATA_ADDRESS, *i);
             }
         }
     }
@@ -38,11 +33,11 @@ impl ColorPaletteRegisters {
     /// bytes representing a color.
     pub fn read_palette(&mut self, palette: &mut [u8; PALETTE_SIZE]) {
         unsafe {
-            self.index_read_port.write(0);
+            self.port_io.write_port(COLOR_PALETTE_INDEX_READ_ADDRESS, 0);
         }
         for byte in palette.iter_mut().take(PALETTE_SIZE) {
             unsafe {
-                *byte = self.data_port.read();
+                *byte = self.port_io.read_port(COLOR_PALETTE_DATA_ADDRESS);
             }
         }
     }
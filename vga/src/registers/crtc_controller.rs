@@ -2,7 +2,151 @@ use super::{
     EmulationMode, CRX_DATA_CGA_ADDRESS, CRX_DATA_MDA_ADDRESS, CRX_INDEX_CGA_ADDRESS,
     CRX_INDEX_MDA_ADDRESS,
 };
-use x86_64::instructions::port::Port;
+use crate::io::{PortIo, X86Io};
+use bitflags::bitflags;
+
+bitflags! {
+    /// Represents the value of the `CrtcControllerIndex::HorizontalBlankingEnd` register.
+    pub struct HorizontalBlankingEnd: u8 {
+        /// Represents the `End Horizontal Blanking` field, which holds the
+        /// low 5 bits of the character count that blanking ends at.
+        const END_HORIZONTAL_BLANKING = 0b0001_1111;
+        /// Represents the `Display Enable Skew` field, which delays the
+        /// display enable signal by up to 3 character clocks.
+        const DISPLAY_ENABLE_SKEW = 0b0110_0000;
+        /// Represents the `Enable Vertical Retrace Access` field, which has
+        /// to be set for the vertical retrace registers to be readable.
+        const ENABLE_VERTICAL_RETRACE_ACCESS = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    /// Represents the value of the `CrtcControllerIndex::Overflow` register,
+    /// which holds the high bits of the vertical timing values.
+    pub struct Overflow: u8 {
+        /// Represents bit 8 of the `Vertical Total` value.
+        const VERTICAL_TOTAL_8 = 0b0000_0001;
+        /// Represents bit 8 of the `Vertical Display End` value.
+        const VERTICAL_DISPLAY_END_8 = 0b0000_0010;
+        /// Represents bit 8 of the `Vertical Retrace Start` value.
+        const VERTICAL_RETRACE_START_8 = 0b0000_0100;
+        /// Represents bit 8 of the `Vertical Blanking Start` value.
+        const VERTICAL_BLANKING_START_8 = 0b0000_1000;
+        /// Represents bit 8 of the `Line Compare` value.
+        const LINE_COMPARE_8 = 0b0001_0000;
+        /// Represents bit 9 of the `Vertical Total` value.
+        const VERTICAL_TOTAL_9 = 0b0010_0000;
+        /// Represents bit 9 of the `Vertical Display End` value.
+        const VERTICAL_DISPLAY_END_9 = 0b0100_0000;
+        /// Represents bit 9 of the `Vertical Retrace Start` value.
+        const VERTICAL_RETRACE_START_9 = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    /// Represents the value of the `CrtcControllerIndex::MaximumScanLine` register.
+    pub struct MaximumScanLine: u8 {
+        /// Represents the `Maximum Scan Line` field, which holds the
+        /// character height in scan lines minus 1.
+        const SCAN_LINES = 0b0001_1111;
+        /// Represents bit 9 of the `Vertical Blanking Start` value.
+        const VERTICAL_BLANKING_START_9 = 0b0010_0000;
+        /// Represents bit 9 of the `Line Compare` value.
+        const LINE_COMPARE_9 = 0b0100_0000;
+        /// Represents the `Scan Doubling` field, which displays every
+        /// scan line twice.
+        const SCAN_DOUBLING = 0b1000_0000;
+    }
+}
+
+impl MaximumScanLine {
+    /// Returns the character height in scan lines.
+    pub fn character_height(self) -> u8 {
+        (self & MaximumScanLine::SCAN_LINES).bits() + 1
+    }
+
+    /// Returns a copy of `self` with the character height set to `height`
+    /// scan lines, which should be between 1 and 32.
+    pub fn with_character_height(self, height: u8) -> MaximumScanLine {
+        let scan_lines = MaximumScanLine::from_bits_truncate(height.wrapping_sub(1))
+            & MaximumScanLine::SCAN_LINES;
+        (self - MaximumScanLine::SCAN_LINES) | scan_lines
+    }
+}
+
+bitflags! {
+    /// Represents the value of the `CrtcControllerIndex::PresetRowScan` register.
+    pub struct PresetRowScan: u8 {
+        /// Represents the `Preset Row Scan` field, which holds the scan
+        /// line of the first displayed character row.
+        const PRESET_ROW_SCAN = 0b0001_1111;
+        /// Represents the `Byte Panning` field, which shifts the display
+        /// left by up to 3 bytes.
+        const BYTE_PANNING = 0b0110_0000;
+    }
+}
+
+impl PresetRowScan {
+    /// Returns the scan line of the first displayed character row.
+    pub fn row_scan(self) -> u8 {
+        (self & PresetRowScan::PRESET_ROW_SCAN).bits()
+    }
+
+    /// Returns a copy of `self` with the scan line of the first displayed
+    /// character row set to `row_scan`, which should be less than 32.
+    pub fn with_row_scan(self, row_scan: u8) -> PresetRowScan {
+        let row_scan = PresetRowScan::from_bits_truncate(row_scan) & PresetRowScan::PRESET_ROW_SCAN;
+        (self - PresetRowScan::PRESET_ROW_SCAN) | row_scan
+    }
+}
+
+bitflags! {
+    /// Represents the value of the `CrtcControllerIndex::VerticalSyncEnd` register.
+    pub struct VerticalSyncEnd: u8 {
+        /// Represents the `Vertical Retrace End` field, which holds the
+        /// low 4 bits of the scan line that vertical retrace ends at.
+        const VERTICAL_RETRACE_END = 0b0000_1111;
+        /// Represents the `Clear Vertical Interrupt` field, which has to be
+        /// cleared to acknowledge a vertical retrace interrupt.
+        const CLEAR_VERTICAL_INTERRUPT = 0b0001_0000;
+        /// Represents the `Disable Vertical Interrupt` field.
+        const DISABLE_VERTICAL_INTERRUPT = 0b0010_0000;
+        /// Represents the `Memory Refresh Bandwidth` field, which selects
+        /// 5 rather than 3 memory refresh cycles per scan line.
+        const MEMORY_REFRESH_BANDWIDTH = 0b0100_0000;
+        /// Represents the `Protect` field, which makes the registers
+        /// 0x00 through 0x07 read only, except for the line compare bit
+        /// of the `Overflow` register.
+        const PROTECT_REGISTERS = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    /// Represents the value of the `CrtcControllerIndex::ModeControl` register.
+    pub struct CrtcModeControl: u8 {
+        /// Represents the `Map Display Address 13` field, which when clear
+        /// substitutes bit 13 of the address with bit 0 of the row scan
+        /// counter, as in cga graphics modes.
+        const MAP_DISPLAY_ADDRESS_13 = 0b0000_0001;
+        /// Represents the `Map Display Address 14` field, which when clear
+        /// substitutes bit 14 of the address with bit 1 of the row scan
+        /// counter.
+        const MAP_DISPLAY_ADDRESS_14 = 0b0000_0010;
+        /// Represents the `Divide Scan Line Clock by 2` field.
+        const DIVIDE_SCAN_LINE_CLOCK = 0b0000_0100;
+        /// Represents the `Divide Memory Address Clock by 2` field.
+        const DIVIDE_MEMORY_ADDRESS_CLOCK = 0b0000_1000;
+        /// Represents the `Address Wrap Select` field, which selects the
+        /// address bit moved to bit 0 in word mode.
+        const ADDRESS_WRAP = 0b0010_0000;
+        /// Represents the `Word/Byte Mode Select` field, which selects
+        /// byte rather than word addressing.
+        const BYTE_MODE = 0b0100_0000;
+        /// Represents the `Sync Enable` field, which enables the
+        /// horizontal and vertical retrace signals.
+        const SYNC_ENABLE = 0b1000_0000;
+    }
+}
 
 /// Represents an index for the crtc controller registers.
 #[derive(Debug, Copy, Clone)]
@@ -72,28 +216,20 @@ impl From<CrtcControllerIndex> for u8 {
 
 /// Represents the crtc controller registers on vga hardware.
 #[derive(Debug)]
-pub struct CrtcControllerRegisters {
-    crx_index_cga: Port<u8>,
-    crx_index_mda: Port<u8>,
-    crx_data_cga: Port<u8>,
-    crx_data_mda: Port<u8>,
+pub struct CrtcControllerRegisters<P: PortIo = X86Io> {
+    port_io: P,
 }
 
-impl CrtcControllerRegisters {
-    pub(crate) fn new() -> CrtcControllerRegisters {
-        CrtcControllerRegisters {
-            crx_index_cga: Port::new(CRX_INDEX_CGA_ADDRESS),
-            crx_index_mda: Port::new(CRX_INDEX_MDA_ADDRESS),
-            crx_data_cga: Port::new(CRX_DATA_CGA_ADDRESS),
-            crx_data_mda: Port::new(CRX_DATA_MDA_ADDRESS),
-        }
+impl<P: PortIo> CrtcControllerRegisters<P> {
+    pub(crate) fn new(port_io: P) -> CrtcControllerRegisters<P> {
+        CrtcControllerRegisters { port_io }
     }
 
     /// Reads the current value from the crtc controller, as specified
     /// by `emulation_mode` and `index`.
     pub fn read(&mut self, emulation_mode: EmulationMode, index: CrtcControllerIndex) -> u8 {
         self.set_index(emulation_mode, index);
-        unsafe { self.get_data_port(emulation_mode).read() }
+        unsafe { self.port_io.read_port(get_data_address(emulation_mode)) }
     }
 
     /// Writes the `value` to the crtc_controller, as specified
@@ -101,27 +237,373 @@ impl CrtcControllerRegisters {
     pub fn write(&mut self, emulation_mode: EmulationMode, index: CrtcControllerIndex, value: u8) {
         self.set_index(emulation_mode, index);
         unsafe {
-            self.get_data_port(emulation_mode).write(value);
+            self.port_io
+                .write_port(get_data_address(emulation_mode), value);
         }
     }
 
+    /// Reads the address of the first displayed byte from the
+    /// `Start Address High` and `Start Address Low` registers.
+    pub fn read_start_address(&mut self, emulation_mode: EmulationMode) -> u16 {
+        let high = self.read(emulation_mode, CrtcControllerIndex::StartAddressHigh);
+        let low = self.read(emulation_mode, CrtcControllerIndex::StartAddressLow);
+        u16::from_be_bytes([high, low])
+    }
+
+    /// Sets the address of the first displayed byte to `start_address`.
+    ///
+    /// The hardware only latches a new start address at the beginning of
+    /// vertical retrace, so it should be written while the display is
+    /// enabled to take effect on the next frame.
+    pub fn set_start_address(&mut self, emulation_mode: EmulationMode, start_address: u16) {
+        let [high, low] = start_address.to_be_bytes();
+        self.write(emulation_mode, CrtcControllerIndex::StartAddressHigh, high);
+        self.write(emulation_mode, CrtcControllerIndex::StartAddressLow, low);
+    }
+
+    /// Sets the scan line of the first displayed character row to `row_scan`,
+    /// which allows scrolling text modes by single scan lines.
+    pub fn set_preset_row_scan(&mut self, emulation_mode: EmulationMode, row_scan: u8) {
+        self.modify_preset_row_scan(emulation_mode, |preset_row_scan| {
+            preset_row_scan.with_row_scan(row_scan)
+        });
+    }
+
+    /// Reads the 10 bit line compare value, which is split across the
+    /// `Line Compare`, `Overflow` and `Maximum Scan Line` registers.
+    pub fn read_line_compare(&mut self, emulation_mode: EmulationMode) -> u16 {
+        let line_compare = self.read(emulation_mode, CrtcControllerIndex::LineCompare);
+        let overflow = self.read_overflow(emulation_mode);
+        let maximum_scan_line = self.read_maximum_scan_line(emulation_mode);
+        u16::from(line_compare)
+            | u16::from(overflow.contains(Overflow::LINE_COMPARE_8)) << 8
+            | u16::from(maximum_scan_line.contains(MaximumScanLine::LINE_COMPARE_9)) << 9
+    }
+
+    /// Sets the 10 bit line compare value to `line_compare`, which is the
+    /// last scan line before the display restarts from address 0.
+    pub fn set_line_compare(&mut self, emulation_mode: EmulationMode, line_compare: u16) {
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::LineCompare,
+            line_compare as u8,
+        );
+        self.modify_overflow(emulation_mode, |mut overflow| {
+            overflow.set(Overflow::LINE_COMPARE_8, line_compare & 0x100 != 0);
+            overflow
+        });
+        self.modify_maximum_scan_line(emulation_mode, |mut maximum_scan_line| {
+            maximum_scan_line.set(MaximumScanLine::LINE_COMPARE_9, line_compare & 0x200 != 0);
+            maximum_scan_line
+        });
+    }
+
+    /// Reads the current value of the `CrtcControllerIndex::HorizontalBlankingEnd` register.
+    pub fn read_horizontal_blanking_end(
+        &mut self,
+        emulation_mode: EmulationMode,
+    ) -> HorizontalBlankingEnd {
+        HorizontalBlankingEnd::from_bits_truncate(
+            self.read(emulation_mode, CrtcControllerIndex::HorizontalBlankingEnd),
+        )
+    }
+
+    /// Writes `horizontal_blanking_end` to the `CrtcControllerIndex::HorizontalBlankingEnd` register.
+    pub fn write_horizontal_blanking_end(
+        &mut self,
+        emulation_mode: EmulationMode,
+        horizontal_blanking_end: HorizontalBlankingEnd,
+    ) {
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::HorizontalBlankingEnd,
+            horizontal_blanking_end.bits(),
+        );
+    }
+
+    /// Replaces the value of the `CrtcControllerIndex::HorizontalBlankingEnd` register
+    /// with the result of `f`, keeping its other bits.
+    pub fn modify_horizontal_blanking_end(
+        &mut self,
+        emulation_mode: EmulationMode,
+        f: impl FnOnce(HorizontalBlankingEnd) -> HorizontalBlankingEnd,
+    ) {
+        let value = self.read(emulation_mode, CrtcControllerIndex::HorizontalBlankingEnd);
+        let reserved = value & !HorizontalBlankingEnd::all().bits();
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::HorizontalBlankingEnd,
+            f(HorizontalBlankingEnd::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
+    /// Reads the current value of the `CrtcControllerIndex::Overflow` register.
+    pub fn read_overflow(&mut self, emulation_mode: EmulationMode) -> Overflow {
+        Overflow::from_bits_truncate(self.read(emulation_mode, CrtcControllerIndex::Overflow))
+    }
+
+    /// Writes `overflow` to the `CrtcControllerIndex::Overflow` register.
+    pub fn write_overflow(&mut self, emulation_mode: EmulationMode, overflow: Overflow) {
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::Overflow,
+            overflow.bits(),
+        );
+    }
+
+    /// Replaces the value of the `CrtcControllerIndex::Overflow` register
+    /// with the result of `f`, keeping its other bits.
+    pub fn modify_overflow(
+        &mut self,
+        emulation_mode: EmulationMode,
+        f: impl FnOnce(Overflow) -> Overflow,
+    ) {
+        let value = self.read(emulation_mode, CrtcControllerIndex::Overflow);
+        let reserved = value & !Overflow::all().bits();
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::Overflow,
+            f(Overflow::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
+    /// Reads the current value of the `CrtcControllerIndex::MaximumScanLine` register.
+    pub fn read_maximum_scan_line(&mut self, emulation_mode: EmulationMode) -> MaximumScanLine {
+        MaximumScanLine::from_bits_truncate(
+            self.read(emulation_mode, CrtcControllerIndex::MaximumScanLine),
+        )
+    }
+
+    /// Writes `maximum_scan_line` to the `CrtcControllerIndex::MaximumScanLine` register.
+    pub fn write_maximum_scan_line(
+        &mut self,
+        emulation_mode: EmulationMode,
+        maximum_scan_line: MaximumScanLine,
+    ) {
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::MaximumScanLine,
+            maximum_scan_line.bits(),
+        );
+    }
+
+    /// Replaces the value of the `CrtcControllerIndex::MaximumScanLine` register
+    /// with the result of `f`, keeping its other bits.
+    pub fn modify_maximum_scan_line(
+        &mut self,
+        emulation_mode: EmulationMode,
+        f: impl FnOnce(MaximumScanLine) -> MaximumScanLine,
+    ) {
+        let value = self.read(emulation_mode, CrtcControllerIndex::MaximumScanLine);
+        let reserved = value & !MaximumScanLine::all().bits();
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::MaximumScanLine,
+            f(MaximumScanLine::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
+    /// Reads the current value of the `CrtcControllerIndex::PresetRowScan` register.
+    pub fn read_preset_row_scan(&mut self, emulation_mode: EmulationMode) -> PresetRowScan {
+        PresetRowScan::from_bits_truncate(
+            self.read(emulation_mode, CrtcControllerIndex::PresetRowScan),
+        )
+    }
+
+    /// Writes `preset_row_scan` to the `CrtcControllerIndex::PresetRowScan` register.
+    pub fn write_preset_row_scan(
+        &mut self,
+        emulation_mode: EmulationMode,
+        preset_row_scan: PresetRowScan,
+    ) {
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::PresetRowScan,
+            preset_row_scan.bits(),
+        );
+    }
+
+    /// Replaces the value of the `CrtcControllerIndex::PresetRowScan` register
+    /// with the result of `f`, keeping its other bits.
+    pub fn modify_preset_row_scan(
+        &mut self,
+        emulation_mode: EmulationMode,
+        f: impl FnOnce(PresetRowScan) -> PresetRowScan,
+    ) {
+        let value = self.read(emulation_mode, CrtcControllerIndex::PresetRowScan);
+        let reserved = value & !PresetRowScan::all().bits();
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::PresetRowScan,
+            f(PresetRowScan::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
+    /// Reads the current value of the `CrtcControllerIndex::VerticalSyncEnd` register.
+    pub fn read_vertical_sync_end(&mut self, emulation_mode: EmulationMode) -> VerticalSyncEnd {
+        VerticalSyncEnd::from_bits_truncate(
+            self.read(emulation_mode, CrtcControllerIndex::VerticalSyncEnd),
+        )
+    }
+
+    /// Writes `vertical_sync_end` to the `CrtcControllerIndex::VerticalSyncEnd` register.
+    pub fn write_vertical_sync_end(
+        &mut self,
+        emulation_mode: EmulationMode,
+        vertical_sync_end: VerticalSyncEnd,
+    ) {
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::VerticalSyncEnd,
+            vertical_sync_end.bits(),
+        );
+    }
+
+    /// Replaces the value of the `CrtcControllerIndex::VerticalSyncEnd` register
+    /// with the result of `f`, keeping its other bits.
+    pub fn modify_vertical_sync_end(
+        &mut self,
+        emulation_mode: EmulationMode,
+        f: impl FnOnce(VerticalSyncEnd) -> VerticalSyncEnd,
+    ) {
+        let value = self.read(emulation_mode, CrtcControllerIndex::VerticalSyncEnd);
+        let reserved = value & !VerticalSyncEnd::all().bits();
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::VerticalSyncEnd,
+            f(VerticalSyncEnd::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
+    /// Reads the current value of the `CrtcControllerIndex::ModeControl` register.
+    pub fn read_mode_control(&mut self, emulation_mode: EmulationMode) -> CrtcModeControl {
+        CrtcModeControl::from_bits_truncate(
+            self.read(emulation_mode, CrtcControllerIndex::ModeControl),
+        )
+    }
+
+    /// Writes `mode_control` to the `CrtcControllerIndex::ModeControl` register.
+    pub fn write_mode_control(
+        &mut self,
+        emulation_mode: EmulationMode,
+        mode_control: CrtcModeControl,
+    ) {
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::ModeControl,
+            mode_control.bits(),
+        );
+    }
+
+    /// Replaces the value of the `CrtcControllerIndex::ModeControl` register
+    /// with the result of `f`, keeping its other bits.
+    pub fn modify_mode_control(
+        &mut self,
+        emulation_mode: EmulationMode,
+        f: impl FnOnce(CrtcModeControl) -> CrtcModeControl,
+    ) {
+        let value = self.read(emulation_mode, CrtcControllerIndex::ModeControl);
+        let reserved = value & !CrtcModeControl::all().bits();
+        self.write(
+            emulation_mode,
+            CrtcControllerIndex::ModeControl,
+            f(CrtcModeControl::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
     fn set_index(&mut self, emulation_mode: EmulationMode, index: CrtcControllerIndex) {
         unsafe {
-            self.get_index_port(emulation_mode).write(u8::from(index));
+            self.port_io
+                .write_port(get_index_address(emulation_mode), u8::from(index));
         }
     }
+}
 
-    fn get_data_port(&mut self, emulation_mode: EmulationMode) -> &mut Port<u8> {
-        match emulation_mode {
-            EmulationMode::Cga => &mut self.crx_data_cga,
-            EmulationMode::Mda => &mut self.crx_data_mda,
-        }
+fn get_data_address(emulation_mode: EmulationMode) -> u16 {
+    match emulation_mode {
+        EmulationMode::Cga => CRX_DATA_CGA_ADDRESS,
+        EmulationMode::Mda => CRX_DATA_MDA_ADDRESS,
     }
+}
 
-    fn get_index_port(&mut self, emulation_mode: EmulationMode) -> &mut Port<u8> {
-        match emulation_mode {
-            EmulationMode::Cga => &mut self.crx_index_cga,
-            EmulationMode::Mda => &mut self.crx_index_mda,
-        }
+fn get_index_address(emulation_mode: EmulationMode) -> u16 {
+    match emulation_mode {
+        EmulationMode::Cga => CRX_INDEX_CGA_ADDRESS,
+        EmulationMode::Mda => CRX_INDEX_MDA_ADDRESS,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::emulator::VgaEmulator;
+
+    #[test]
+    fn test_typed_registers() {
+        let emulator = VgaEmulator::new();
+        let mut crtc_controller_registers = CrtcControllerRegisters::new(&emulator);
+        let emulation_mode = EmulationMode::Cga;
+
+        crtc_controller_registers.write(emulation_mode, CrtcControllerIndex::VerticalSyncEnd, 0x8C);
+        crtc_controller_registers.modify_vertical_sync_end(emulation_mode, |vertical_sync_end| {
+            vertical_sync_end - VerticalSyncEnd::PROTECT_REGISTERS
+        });
+        // The vertical retrace end value in the low bits is kept.
+        assert_eq!(
+            crtc_controller_registers.read(emulation_mode, CrtcControllerIndex::VerticalSyncEnd),
+            0x0C
+        );
+
+        // Writing back what was read keeps the timing fields, since the
+        // types cover every bit of their register.
+        crtc_controller_registers.write(
+            emulation_mode,
+            CrtcControllerIndex::HorizontalBlankingEnd,
+            0x42,
+        );
+        let horizontal_blanking_end = crtc_controller_registers
+            .read_horizontal_blanking_end(emulation_mode)
+            | HorizontalBlankingEnd::ENABLE_VERTICAL_RETRACE_ACCESS;
+        crtc_controller_registers
+            .write_horizontal_blanking_end(emulation_mode, horizontal_blanking_end);
+        assert_eq!(
+            crtc_controller_registers
+                .read(emulation_mode, CrtcControllerIndex::HorizontalBlankingEnd),
+            0xC2
+        );
+        let vertical_sync_end = crtc_controller_registers.read_vertical_sync_end(emulation_mode)
+            | VerticalSyncEnd::PROTECT_REGISTERS;
+        crtc_controller_registers.write_vertical_sync_end(emulation_mode, vertical_sync_end);
+        assert_eq!(
+            crtc_controller_registers.read(emulation_mode, CrtcControllerIndex::VerticalSyncEnd),
+            0x8C
+        );
+
+        crtc_controller_registers.write_maximum_scan_line(
+            emulation_mode,
+            MaximumScanLine::SCAN_DOUBLING.with_character_height(16),
+        );
+        crtc_controller_registers.set_line_compare(emulation_mode, 0x3FF);
+        let maximum_scan_line = crtc_controller_registers.read_maximum_scan_line(emulation_mode);
+        assert_eq!(maximum_scan_line.character_height(), 16);
+        assert!(maximum_scan_line.contains(MaximumScanLine::SCAN_DOUBLING));
+        assert!(crtc_controller_registers
+            .read_overflow(emulation_mode)
+            .contains(Overflow::LINE_COMPARE_8));
+        assert_eq!(
+            crtc_controller_registers.read_line_compare(emulation_mode),
+            0x3FF
+        );
+
+        // Setting the row scan keeps the byte panning and the reserved bit.
+        crtc_controller_registers.write(emulation_mode, CrtcControllerIndex::PresetRowScan, 0xDF);
+        crtc_controller_registers.set_preset_row_scan(emulation_mode, 5);
+        assert_eq!(
+            crtc_controller_registers.read(emulation_mode, CrtcControllerIndex::PresetRowScan),
+            0xC5
+        );
+        let preset_row_scan = crtc_controller_registers.read_preset_row_scan(emulation_mode);
+        assert_eq!(preset_row_scan.row_scan(), 5);
+        assert!(preset_row_scan.intersects(PresetRowScan::BYTE_PANNING));
     }
 }
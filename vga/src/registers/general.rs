@@ -1,45 +1,181 @@
 use super::{
-    FCR_CGA_WRITE_ADDRESS, FCR_MDA_WRITE_ADDRESS, FCR_READ_ADDRESS, MSR_READ_ADDRESS,
-    MSR_WRITE_ADDRESS, ST00_READ_ADDRESS, ST01_READ_CGA_ADDRESS, ST01_READ_MDA_ADDRESS,
+    EmulationMode, FCR_CGA_WRITE_ADDRESS, FCR_MDA_WRITE_ADDRESS, FCR_READ_ADDRESS,
+    MSR_READ_ADDRESS, MSR_WRITE_ADDRESS, ST00_READ_ADDRESS, ST01_READ_CGA_ADDRESS,
+    ST01_READ_MDA_ADDRESS,
 };
-use x86_64::instructions::port::{PortReadOnly, PortWriteOnly};
+use crate::io::{PortIo, X86Io};
+use bitflags::bitflags;
+
+bitflags! {
+    /// Represents the flags of the input status #0 register.
+    pub struct InputStatus0: u8 {
+        /// Represents the `Switch Sense` flag, which reports the state
+        /// of the monitor sense line.
+        const SWITCH_SENSE = 0b0001_0000;
+    }
+}
+
+bitflags! {
+    /// Represents the flags of the input status #1 register.
+    pub struct InputStatus1: u8 {
+        /// Represents the `Display Disabled` flag, which is set during
+        /// both horizontal and vertical retrace.
+        const DISPLAY_DISABLED = 0b0000_0001;
+        /// Represents the `Vertical Retrace` flag, which is set
+        /// during vertical retrace.
+        const VERTICAL_RETRACE = 0b0000_1000;
+    }
+}
+
+bitflags! {
+    /// Represents the value of the miscellaneous output register.
+    pub struct MiscellaneousOutput: u8 {
+        /// Represents the `Input/Output Address Select` field, which selects
+        /// the cga rather than mda emulation mode.
+        const IO_ADDRESS_SELECT = 0b0000_0001;
+        /// Represents the `RAM Enable` field, which gives the cpu access
+        /// to vga memory.
+        const RAM_ENABLE = 0b0000_0010;
+        /// Represents the `Clock Select` field, which selects the 25 MHz
+        /// clock with 0b00 and the 28 MHz clock with 0b01.
+        const CLOCK_SELECT = 0b0000_1100;
+        /// Represents the `Odd/Even Page Select` field, which selects the
+        /// page of memory accessed in odd/even modes.
+        const ODD_EVEN_PAGE_SELECT = 0b0010_0000;
+        /// Represents the `Horizontal Sync Polarity` field.
+        const HORIZONTAL_SYNC_POLARITY = 0b0100_0000;
+        /// Represents the `Vertical Sync Polarity` field.
+        const VERTICAL_SYNC_POLARITY = 0b1000_0000;
+    }
+}
 
 /// Represents the general registers on vga hardware.
 #[derive(Debug)]
-pub struct GeneralRegisters {
-    st00_read: PortReadOnly<u8>,
-    st01_read_cga: PortReadOnly<u8>,
-    st01_read_mda: PortReadOnly<u8>,
-    fcr_read: PortReadOnly<u8>,
-    fcr_write_cga: PortWriteOnly<u8>,
-    fcr_write_mda: PortWriteOnly<u8>,
-    msr_read: PortReadOnly<u8>,
-    msr_write: PortWriteOnly<u8>,
+pub struct GeneralRegisters<P: PortIo = X86Io> {
+    port_io: P,
 }
 
-impl GeneralRegisters {
-    pub(crate) fn new() -> GeneralRegisters {
-        GeneralRegisters {
-            st00_read: PortReadOnly::new(ST00_READ_ADDRESS),
-            st01_read_cga: PortReadOnly::new(ST01_READ_CGA_ADDRESS),
-            st01_read_mda: PortReadOnly::new(ST01_READ_MDA_ADDRESS),
-            fcr_read: PortReadOnly::new(FCR_READ_ADDRESS),
-            fcr_write_cga: PortWriteOnly::new(FCR_CGA_WRITE_ADDRESS),
-            fcr_write_mda: PortWriteOnly::new(FCR_MDA_WRITE_ADDRESS),
-            msr_read: PortReadOnly::new(MSR_READ_ADDRESS),
-            msr_write: PortWriteOnly::new(MSR_WRITE_ADDRESS),
-        }
+impl<P: PortIo> GeneralRegisters<P> {
+    pub(crate) fn new(port_io: P) -> GeneralRegisters<P> {
+        GeneralRegisters { port_io }
     }
 
     /// Reads the current value from the miscellaneous output register.
     pub fn read_msr(&mut self) -> u8 {
-        unsafe { self.msr_read.read() }
+        unsafe { self.port_io.read_port(MSR_READ_ADDRESS) }
     }
 
     /// Writes the `value` to the miscellaneous output register.
     pub fn write_msr(&mut self, value: u8) {
         unsafe {
-            self.msr_write.write(value);
+            self.port_io.write_port(MSR_WRITE_ADDRESS, value);
         }
     }
+
+    /// Reads the current value from the miscellaneous output register.
+    pub fn read_miscellaneous_output(&mut self) -> MiscellaneousOutput {
+        MiscellaneousOutput::from_bits_truncate(self.read_msr())
+    }
+
+    /// Writes `miscellaneous_output` to the miscellaneous output register.
+    pub fn write_miscellaneous_output(&mut self, miscellaneous_output: MiscellaneousOutput) {
+        self.write_msr(miscellaneous_output.bits());
+    }
+
+    /// Replaces the value of the miscellaneous output register with the
+    /// result of `f`, keeping its reserved bits.
+    pub fn modify_miscellaneous_output(
+        &mut self,
+        f: impl FnOnce(MiscellaneousOutput) -> MiscellaneousOutput,
+    ) {
+        let value = self.read_msr();
+        let reserved = value & !MiscellaneousOutput::all().bits();
+        self.write_msr(f(MiscellaneousOutput::from_bits_truncate(value)).bits() | reserved);
+    }
+
+    /// Reads the current value from the feature control register.
+    pub fn read_fcr(&mut self) -> u8 {
+        unsafe { self.port_io.read_port(FCR_READ_ADDRESS) }
+    }
+
+    /// Writes the `value` to the feature control register, as specified
+    /// by `emulation_mode`.
+    pub fn write_fcr(&mut self, emulation_mode: EmulationMode, value: u8) {
+        let fcr_write = match emulation_mode {
+            EmulationMode::Cga => FCR_CGA_WRITE_ADDRESS,
+            EmulationMode::Mda => FCR_MDA_WRITE_ADDRESS,
+        };
+        unsafe {
+            self.port_io.write_port(fcr_write, value);
+        }
+    }
+
+    /// Reads the current value from input status register 0.
+    pub fn read_st00(&mut self) -> InputStatus0 {
+        let value = unsafe { self.port_io.read_port(ST00_READ_ADDRESS) };
+        InputStatus0::from_bits_truncate(value)
+    }
+
+    /// Reads the current value from input status register 1, as specified
+    /// by `emulation_mode`.
+    ///
+    /// Reading this register also resets the attribute controller
+    /// to expect an index.
+    pub fn read_st01(&mut self, emulation_mode: EmulationMode) -> InputStatus1 {
+        let st01_read = match emulation_mode {
+            EmulationMode::Cga => ST01_READ_CGA_ADDRESS,
+            EmulationMode::Mda => ST01_READ_MDA_ADDRESS,
+        };
+        let value = unsafe { self.port_io.read_port(st01_read) };
+        InputStatus1::from_bits_truncate(value)
+    }
+
+    /// Waits for the start of the next vertical retrace, as specified
+    /// by `emulation_mode`.
+    ///
+    /// If the display is already in vertical retrace, that retrace is
+    /// waited out first, so the caller always gets a full retrace period.
+    pub fn wait_for_vsync(&mut self, emulation_mode: EmulationMode) {
+        while self
+            .read_st01(emulation_mode)
+            .contains(InputStatus1::VERTICAL_RETRACE)
+        {}
+        while !self
+            .read_st01(emulation_mode)
+            .contains(InputStatus1::VERTICAL_RETRACE)
+        {}
+    }
+
+    /// Waits until the display is enabled, as specified by `emulation_mode`,
+    /// which is when the start address registers can be changed without
+    /// the change being missed by the next frame.
+    pub fn wait_for_display_enable(&mut self, emulation_mode: EmulationMode) {
+        while self
+            .read_st01(emulation_mode)
+            .contains(InputStatus1::DISPLAY_DISABLED)
+        {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::emulator::VgaEmulator;
+
+    #[test]
+    fn test_wait_for_retrace() {
+        let emulator = VgaEmulator::new();
+        let mut general_registers = GeneralRegisters::new(&emulator);
+
+        general_registers.wait_for_vsync(EmulationMode::Cga);
+        // The retrace that was just waited for continues on the next read.
+        assert!(general_registers
+            .read_st01(EmulationMode::Cga)
+            .contains(InputStatus1::VERTICAL_RETRACE));
+
+        general_registers.wait_for_display_enable(EmulationMode::Cga);
+        assert!(!general_registers
+            .read_st01(EmulationMode::Cga)
+            .contains(InputStatus1::DISPLAY_DISABLED));
+    }
 }
@@ -1,10 +1,13 @@
 use super::{Color16, GRX_DATA_ADDRESS, GRX_INDEX_ADDRESS};
+use crate::{
+    io::{PortIo, X86Io},
+    Error,
+};
+use bitflags::bitflags;
 use core::convert::TryFrom;
-use x86_64::instructions::port::Port;
 
 /// Represents a plane for the `GraphicsControllerIndex::ReadPlaneSelect` register.
-#[allow(dead_code)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ReadPlane {
     /// Represents `Plane 0 (0x0)`.
@@ -18,7 +21,7 @@ pub enum ReadPlane {
 }
 
 impl TryFrom<u8> for ReadPlane {
-    type Error = &'static str;
+    type Error = Error;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
@@ -26,7 +29,7 @@ impl TryFrom<u8> for ReadPlane {
             1 => Ok(ReadPlane::Plane1),
             2 => Ok(ReadPlane::Plane2),
             3 => Ok(ReadPlane::Plane3),
-            _ => Err("ReadPlane only accepts values between 0-3!"),
+            _ => Err(Error::InvalidPlane(value)),
         }
     }
 }
@@ -74,7 +77,7 @@ impl From<GraphicsControllerIndex> for u8 {
 }
 
 /// Represents a write mode for vga hardware.
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum WriteMode {
     /// Represents `WriteMode` 0.
@@ -130,26 +133,135 @@ impl From<WriteMode> for u8 {
     }
 }
 
+/// Represents the logical operation that combines written data with the
+/// memory read latches, as selected by the `Function Select` field of the
+/// `GraphicsControllerIndex::DataRotate` register.
+///
+/// Only `WriteMode::Mode0`, `WriteMode::Mode2` and `WriteMode::Mode3` go
+/// through this operation, and the latches have to be loaded by reading
+/// the byte before writing it for the result to make sense.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use vga::registers::RasterOp;
+///
+/// let cursor = 0x0F;
+/// let drawn = RasterOp::Xor.apply(0x42, cursor);
+/// assert_eq!(RasterOp::Xor.apply(drawn, cursor), 0x42);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RasterOp {
+    /// Data is written unmodified.
+    Replace = 0x00,
+    /// Data is ANDed with the latched data.
+    And = 0x08,
+    /// Data is ORed with the latched data.
+    Or = 0x10,
+    /// Data is XORed with the latched data.
+    Xor = 0x18,
+}
+
+impl RasterOp {
+    /// Combines the `source` color with the `destination` color already
+    /// in memory, as the hardware does for every plane.
+    pub fn apply(self, destination: u8, source: u8) -> u8 {
+        match self {
+            RasterOp::Replace => source,
+            RasterOp::And => destination & source,
+            RasterOp::Or => destination | source,
+            RasterOp::Xor => destination ^ source,
+        }
+    }
+}
+
+impl From<RasterOp> for u8 {
+    fn from(value: RasterOp) -> u8 {
+        value as u8
+    }
+}
+
+bitflags! {
+    /// Represents the value of the `GraphicsControllerIndex::GraphicsMode` register.
+    pub struct GraphicsMode: u8 {
+        /// Represents the two bit `Write Mode` field, see `write_mode`.
+        const WRITE_MODE = 0b0000_0011;
+        /// Represents the `Read Mode` field, which selects color compare
+        /// reads rather than reading a single plane.
+        const READ_MODE = 0b0000_1000;
+        /// Represents the `Host Odd/Even Memory Read Addressing Enable` field.
+        const HOST_ODD_EVEN = 0b0001_0000;
+        /// Represents the `Shift Register Interleave Mode` field, as used
+        /// by cga compatible 4 color modes.
+        const SHIFT_REGISTER_INTERLEAVE = 0b0010_0000;
+        /// Represents the `256-Color Shift Mode` field.
+        const SHIFT_256_COLOR = 0b0100_0000;
+    }
+}
+
+impl GraphicsMode {
+    /// Returns the `WriteMode` selected by the `WRITE_MODE` field.
+    pub fn write_mode(self) -> WriteMode {
+        match self.bits() & Self::WRITE_MODE.bits() {
+            0x0 => WriteMode::Mode0,
+            0x1 => WriteMode::Mode1,
+            0x2 => WriteMode::Mode2,
+            _ => WriteMode::Mode3,
+        }
+    }
+
+    /// Returns a copy with the `WRITE_MODE` field set to `write_mode`.
+    pub fn with_write_mode(self, write_mode: WriteMode) -> GraphicsMode {
+        (self - Self::WRITE_MODE) | GraphicsMode::from_bits_truncate(u8::from(write_mode))
+    }
+}
+
+bitflags! {
+    /// Represents the value of the `GraphicsControllerIndex::Miscellaneous` register.
+    pub struct GraphicsMiscellaneous: u8 {
+        /// Represents the `Alphanumeric Mode Disable` field, which selects
+        /// graphics rather than text modes.
+        const GRAPHICS_MODE = 0b0000_0001;
+        /// Represents the `Chain Odd/Even Enable` field.
+        const CHAIN_ODD_EVEN = 0b0000_0010;
+        /// Represents the two bit `Memory Map Select` field, see `memory_map`.
+        const MEMORY_MAP = 0b0000_1100;
+    }
+}
+
+impl GraphicsMiscellaneous {
+    /// Returns the value of the `MEMORY_MAP` field, from 0 for the 128K
+    /// window at `0xA0000` through 3 for the 32K window at `0xB8000`.
+    pub fn memory_map(self) -> u8 {
+        (self.bits() & Self::MEMORY_MAP.bits()) >> 2
+    }
+
+    /// Returns a copy with the `MEMORY_MAP` field set to `memory_map`.
+    pub fn with_memory_map(self, memory_map: u8) -> GraphicsMiscellaneous {
+        (self - Self::MEMORY_MAP)
+            | GraphicsMiscellaneous::from_bits_truncate((memory_map & 0x3) << 2)
+    }
+}
+
 /// Represents the graphics controller registers on vga hardware.
 #[derive(Debug)]
-pub struct GraphicsControllerRegisters {
-    grx_index: Port<u8>,
-    grx_data: Port<u8>,
+pub struct GraphicsControllerRegisters<P: PortIo = X86Io> {
+    port_io: P,
 }
 
-impl GraphicsControllerRegisters {
-    pub(crate) fn new() -> GraphicsControllerRegisters {
-        GraphicsControllerRegisters {
-            grx_index: Port::new(GRX_INDEX_ADDRESS),
-            grx_data: Port::new(GRX_DATA_ADDRESS),
-        }
+impl<P: PortIo> GraphicsControllerRegisters<P> {
+    pub(crate) fn new(port_io: P) -> GraphicsControllerRegisters<P> {
+        GraphicsControllerRegisters { port_io }
     }
 
     /// Reads the current value from the graphics controller, as specified
     /// by `index`.
     pub fn read(&mut self, index: GraphicsControllerIndex) -> u8 {
         self.set_index(index);
-        unsafe { self.grx_data.read() }
+        unsafe { self.port_io.read_port(GRX_DATA_ADDRESS) }
     }
 
     /// Writes the `value` to the graphics controller, as specified
@@ -157,7 +269,7 @@ impl GraphicsControllerRegisters {
     pub fn write(&mut self, index: GraphicsControllerIndex, value: u8) {
         self.set_index(index);
         unsafe {
-            self.grx_data.write(value);
+            self.port_io.write_port(GRX_DATA_ADDRESS, value);
         }
     }
 
@@ -189,10 +301,51 @@ impl GraphicsControllerRegisters {
 
     /// Sets which mode the vga writes in, as specified by `write_mode`.
     pub fn set_write_mode(&mut self, write_mode: WriteMode) {
-        let original_value = self.read(GraphicsControllerIndex::GraphicsMode) & 0xFC;
+        self.modify_graphics_mode(|graphics_mode| graphics_mode.with_write_mode(write_mode));
+    }
+
+    /// Reads the current value of the `GraphicsControllerIndex::GraphicsMode` register.
+    pub fn read_graphics_mode(&mut self) -> GraphicsMode {
+        GraphicsMode::from_bits_truncate(self.read(GraphicsControllerIndex::GraphicsMode))
+    }
+
+    /// Writes `graphics_mode` to the `GraphicsControllerIndex::GraphicsMode` register.
+    pub fn write_graphics_mode(&mut self, graphics_mode: GraphicsMode) {
+        self.write(GraphicsControllerIndex::GraphicsMode, graphics_mode.bits());
+    }
+
+    /// Replaces the value of the `GraphicsControllerIndex::GraphicsMode`
+    /// register with the result of `f`, keeping its reserved bits.
+    pub fn modify_graphics_mode(&mut self, f: impl FnOnce(GraphicsMode) -> GraphicsMode) {
+        let value = self.read(GraphicsControllerIndex::GraphicsMode);
+        let reserved = value & !GraphicsMode::all().bits();
         self.write(
             GraphicsControllerIndex::GraphicsMode,
-            original_value | u8::from(write_mode),
+            f(GraphicsMode::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
+    /// Reads the current value of the `GraphicsControllerIndex::Miscellaneous` register.
+    pub fn read_miscellaneous(&mut self) -> GraphicsMiscellaneous {
+        GraphicsMiscellaneous::from_bits_truncate(self.read(GraphicsControllerIndex::Miscellaneous))
+    }
+
+    /// Writes `miscellaneous` to the `GraphicsControllerIndex::Miscellaneous` register.
+    pub fn write_miscellaneous(&mut self, miscellaneous: GraphicsMiscellaneous) {
+        self.write(GraphicsControllerIndex::Miscellaneous, miscellaneous.bits());
+    }
+
+    /// Replaces the value of the `GraphicsControllerIndex::Miscellaneous`
+    /// register with the result of `f`, keeping its reserved bits.
+    pub fn modify_miscellaneous(
+        &mut self,
+        f: impl FnOnce(GraphicsMiscellaneous) -> GraphicsMiscellaneous,
+    ) {
+        let value = self.read(GraphicsControllerIndex::Miscellaneous);
+        let reserved = value & !GraphicsMiscellaneous::all().bits();
+        self.write(
+            GraphicsControllerIndex::Miscellaneous,
+            f(GraphicsMiscellaneous::from_bits_truncate(value)).bits() | reserved,
         );
     }
 
@@ -202,9 +355,19 @@ impl GraphicsControllerRegisters {
         self.write(GraphicsControllerIndex::BitMask, bit_mask);
     }
 
+    /// Sets how written data is combined with the memory read latches,
+    /// as specified by `raster_op`, keeping the rotate count.
+    pub fn set_function_select(&mut self, raster_op: RasterOp) {
+        let original_value = self.read(GraphicsControllerIndex::DataRotate) & 0xE7;
+        self.write(
+            GraphicsControllerIndex::DataRotate,
+            original_value | u8::from(raster_op),
+        );
+    }
+
     fn set_index(&mut self, index: GraphicsControllerIndex) {
         unsafe {
-            self.grx_index.write(u8::from(index));
+            self.port_io.write_port(GRX_INDEX_ADDRESS, u8::from(index));
         }
     }
 }
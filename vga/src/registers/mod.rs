@@ -7,41 +7,53 @@ mod general;
 mod graphics_controller;
 mod sequencer;
 
-use crate::colors::{Color16, PALETTE_SIZE};
+use crate::{
+    colors::{Color16, PALETTE_SIZE},
+    Error,
+};
+use core::convert::TryFrom;
 
-pub use attribute_controller::{AttributeControllerIndex, AttributeControllerRegisters};
+pub use attribute_controller::{
+    AttributeControllerIndex, AttributeControllerRegisters, AttributeModeControl,
+};
 pub use color_palette::ColorPaletteRegisters;
-pub use crtc_controller::{CrtcControllerIndex, CrtcControllerRegisters};
-pub use general::GeneralRegisters;
-pub use graphics_controller::{GraphicsControllerIndex, GraphicsControllerRegisters, WriteMode};
-pub use sequencer::{PlaneMask, SequencerIndex, SequencerRegisters};
+pub use crtc_controller::{
+    CrtcControllerIndex, CrtcControllerRegisters, CrtcModeControl, HorizontalBlankingEnd,
+    MaximumScanLine, Overflow, PresetRowScan, VerticalSyncEnd,
+};
+pub use general::{GeneralRegisters, InputStatus0, InputStatus1, MiscellaneousOutput};
+pub use graphics_controller::{
+    GraphicsControllerIndex, GraphicsControllerRegisters, GraphicsMiscellaneous, GraphicsMode,
+    RasterOp, ReadPlane, WriteMode,
+};
+pub use sequencer::{ClockingMode, MemoryMode, PlaneMask, SequencerIndex, SequencerRegisters};
 
-const ST00_READ_ADDRESS: u16 = 0x3C2;
-const ST01_READ_CGA_ADDRESS: u16 = 0x3DA;
-const ST01_READ_MDA_ADDRESS: u16 = 0x3BA;
-const FCR_READ_ADDRESS: u16 = 0x3CA;
-const FCR_CGA_WRITE_ADDRESS: u16 = 0x3DA;
-const FCR_MDA_WRITE_ADDRESS: u16 = 0x3BA;
-const MSR_READ_ADDRESS: u16 = 0x3CC;
-const MSR_WRITE_ADDRESS: u16 = 0x3C2;
+pub(crate) const ST00_READ_ADDRESS: u16 = 0x3C2;
+pub(crate) const ST01_READ_CGA_ADDRESS: u16 = 0x3DA;
+pub(crate) const ST01_READ_MDA_ADDRESS: u16 = 0x3BA;
+pub(crate) const FCR_READ_ADDRESS: u16 = 0x3CA;
+pub(crate) const FCR_CGA_WRITE_ADDRESS: u16 = 0x3DA;
+pub(crate) const FCR_MDA_WRITE_ADDRESS: u16 = 0x3BA;
+pub(crate) const MSR_READ_ADDRESS: u16 = 0x3CC;
+pub(crate) const MSR_WRITE_ADDRESS: u16 = 0x3C2;
 
-const SRX_INDEX_ADDRESS: u16 = 0x3C4;
-const SRX_DATA_ADDRESS: u16 = 0x3C5;
+pub(crate) const SRX_INDEX_ADDRESS: u16 = 0x3C4;
+pub(crate) const SRX_DATA_ADDRESS: u16 = 0x3C5;
 
-const GRX_INDEX_ADDRESS: u16 = 0x3CE;
-const GRX_DATA_ADDRESS: u16 = 0x3CF;
+pub(crate) const GRX_INDEX_ADDRESS: u16 = 0x3CE;
+pub(crate) const GRX_DATA_ADDRESS: u16 = 0x3CF;
 
-const ARX_INDEX_ADDRESS: u16 = 0x3C0;
-const ARX_DATA_ADDRESS: u16 = 0x3C1;
+pub(crate) const ARX_INDEX_ADDRESS: u16 = 0x3C0;
+pub(crate) const ARX_DATA_ADDRESS: u16 = 0x3C1;
 
-const CRX_INDEX_CGA_ADDRESS: u16 = 0x3D4;
-const CRX_INDEX_MDA_ADDRESS: u16 = 0x3B4;
-const CRX_DATA_CGA_ADDRESS: u16 = 0x3D5;
-const CRX_DATA_MDA_ADDRESS: u16 = 0x3B5;
+pub(crate) const CRX_INDEX_CGA_ADDRESS: u16 = 0x3D4;
+pub(crate) const CRX_INDEX_MDA_ADDRESS: u16 = 0x3B4;
+pub(crate) const CRX_DATA_CGA_ADDRESS: u16 = 0x3D5;
+pub(crate) const CRX_DATA_MDA_ADDRESS: u16 = 0x3B5;
 
-const COLOR_PALETTE_DATA_ADDRESS: u16 = 0x3C9;
-const COLOR_PALETTE_INDEX_READ_ADDRESS: u16 = 0x3C7;
-const COLOR_PALETTE_INDEX_WRITE_ADDRESSS: u16 = 0x3C8;
+pub(crate) const COLOR_PALETTE_DATA_ADDRESS: u16 = 0x3C9;
+pub(crate) const COLOR_PALETTE_INDEX_READ_ADDRESS: u16 = 0x3C7;
+pub(crate) const COLOR_PALETTE_INDEX_WRITE_ADDRESSS: u16 = 0x3C8;
 
 /// Represents a vga emulation mode.
 #[derive(Debug, Copy, Clone)]
@@ -53,12 +65,28 @@ pub enum EmulationMode {
     Cga = 0x1,
 }
 
-impl From<u8> for EmulationMode {
-    fn from(value: u8) -> EmulationMode {
+impl EmulationMode {
+    /// Returns the `EmulationMode` selected by the io address select bit
+    /// of the miscellaneous output register value `miscellaneous_output`.
+    pub(crate) fn from_miscellaneous_output(miscellaneous_output: u8) -> EmulationMode {
+        if MiscellaneousOutput::from_bits_truncate(miscellaneous_output)
+            .contains(MiscellaneousOutput::IO_ADDRESS_SELECT)
+        {
+            EmulationMode::Cga
+        } else {
+            EmulationMode::Mda
+        }
+    }
+}
+
+impl TryFrom<u8> for EmulationMode {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0x0 => EmulationMode::Mda,
-            0x1 => EmulationMode::Cga,
-            _ => panic!("{} is an invalid emulation mode", value),
+            0x0 => Ok(EmulationMode::Mda),
+            0x1 => Ok(EmulationMode::Cga),
+            _ => Err(Error::InvalidEmulationMode(value)),
         }
     }
 }
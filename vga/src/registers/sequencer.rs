@@ -1,7 +1,10 @@
 use super::{SRX_DATA_ADDRESS, SRX_INDEX_ADDRESS};
+use crate::{
+    io::{PortIo, X86Io},
+    Error,
+};
 use bitflags::bitflags;
 use core::convert::TryFrom;
-use x86_64::instructions::port::Port;
 
 bitflags! {
     /// Represents the plane masks of the `SequencerIndex::PlaneMask` register.
@@ -22,7 +25,7 @@ bitflags! {
 }
 
 impl TryFrom<u8> for PlaneMask {
-    type Error = &'static str;
+    type Error = Error;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
@@ -30,7 +33,7 @@ impl TryFrom<u8> for PlaneMask {
             1 => Ok(PlaneMask::PLANE1),
             2 => Ok(PlaneMask::PLANE2),
             3 => Ok(PlaneMask::PLANE3),
-            _ => Err("PlaneMask only accepts values between 0-3!"),
+            _ => Err(Error::InvalidPlane(value)),
         }
     }
 }
@@ -41,6 +44,42 @@ impl From<PlaneMask> for u8 {
     }
 }
 
+bitflags! {
+    /// Represents the value of the `SequencerIndex::ClockingMode` register.
+    pub struct ClockingMode: u8 {
+        /// Represents the `8/9 Dot Mode` field, which makes characters
+        /// 8 rather than 9 dots wide.
+        const EIGHT_DOT_CHARACTERS = 0b0000_0001;
+        /// Represents the `Shift/Load Rate` field, which loads the video
+        /// serializers every other character clock.
+        const SHIFT_LOAD_RATE = 0b0000_0100;
+        /// Represents the `Dot Clock Rate` field, which divides the dot
+        /// clock by 2, as used by 320 and 360 pixel wide modes.
+        const DOT_CLOCK_RATE = 0b0000_1000;
+        /// Represents the `Shift Four Enable` field, which loads the video
+        /// serializers every fourth character clock.
+        const SHIFT_FOUR_ENABLE = 0b0001_0000;
+        /// Represents the `Screen Disable` field, which turns off the
+        /// display while keeping the video timing.
+        const SCREEN_DISABLE = 0b0010_0000;
+    }
+}
+
+bitflags! {
+    /// Represents the value of the `SequencerIndex::MemoryMode` register.
+    pub struct MemoryMode: u8 {
+        /// Represents the `Extended Memory` field, which enables access
+        /// to more than 64K of vga memory.
+        const EXTENDED_MEMORY = 0b0000_0010;
+        /// Represents the `Odd/Even Host Memory Write Adressing Disable`
+        /// field, which gives the cpu sequential access to every plane.
+        const ODD_EVEN_DISABLE = 0b0000_0100;
+        /// Represents the `Chain 4 Enable` field, which selects the plane
+        /// from the lowest two bits of the address, as in mode 13h.
+        const CHAIN_4 = 0b0000_1000;
+    }
+}
+
 /// Represents an index for the seqeuncer registers.
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
@@ -67,30 +106,26 @@ impl From<SequencerIndex> for u8 {
 
 /// Represents the sequencer registers on vga hardware.
 #[derive(Debug)]
-pub struct SequencerRegisters {
-    srx_index: Port<u8>,
-    srx_data: Port<u8>,
+pub struct SequencerRegisters<P: PortIo = X86Io> {
+    port_io: P,
 }
 
-impl SequencerRegisters {
-    pub(crate) fn new() -> SequencerRegisters {
-        SequencerRegisters {
-            srx_index: Port::new(SRX_INDEX_ADDRESS),
-            srx_data: Port::new(SRX_DATA_ADDRESS),
-        }
+impl<P: PortIo> SequencerRegisters<P> {
+    pub(crate) fn new(port_io: P) -> SequencerRegisters<P> {
+        SequencerRegisters { port_io }
     }
 
     /// Reads the current value from the sequencer, as specified by `index`.
     pub fn read(&mut self, index: SequencerIndex) -> u8 {
         self.set_index(index);
-        unsafe { self.srx_data.read() }
+        unsafe { self.port_io.read_port(SRX_DATA_ADDRESS) }
     }
 
     /// Writes the `value` to the sequencer, as specified by `index`.
     pub fn write(&mut self, index: SequencerIndex, value: u8) {
         self.set_index(index);
         unsafe {
-            self.srx_data.write(value);
+            self.port_io.write_port(SRX_DATA_ADDRESS, value);
         }
     }
 
@@ -103,9 +138,51 @@ impl SequencerRegisters {
         );
     }
 
+    /// Reads the current value of the `SequencerIndex::ClockingMode` register.
+    pub fn read_clocking_mode(&mut self) -> ClockingMode {
+        ClockingMode::from_bits_truncate(self.read(SequencerIndex::ClockingMode))
+    }
+
+    /// Writes `clocking_mode` to the `SequencerIndex::ClockingMode` register.
+    pub fn write_clocking_mode(&mut self, clocking_mode: ClockingMode) {
+        self.write(SequencerIndex::ClockingMode, clocking_mode.bits());
+    }
+
+    /// Replaces the value of the `SequencerIndex::ClockingMode` register
+    /// with the result of `f`, keeping its reserved bits.
+    pub fn modify_clocking_mode(&mut self, f: impl FnOnce(ClockingMode) -> ClockingMode) {
+        let value = self.read(SequencerIndex::ClockingMode);
+        let reserved = value & !ClockingMode::all().bits();
+        self.write(
+            SequencerIndex::ClockingMode,
+            f(ClockingMode::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
+    /// Reads the current value of the `SequencerIndex::MemoryMode` register.
+    pub fn read_memory_mode(&mut self) -> MemoryMode {
+        MemoryMode::from_bits_truncate(self.read(SequencerIndex::MemoryMode))
+    }
+
+    /// Writes `memory_mode` to the `SequencerIndex::MemoryMode` register.
+    pub fn write_memory_mode(&mut self, memory_mode: MemoryMode) {
+        self.write(SequencerIndex::MemoryMode, memory_mode.bits());
+    }
+
+    /// Replaces the value of the `SequencerIndex::MemoryMode` register
+    /// with the result of `f`, keeping its reserved bits.
+    pub fn modify_memory_mode(&mut self, f: impl FnOnce(MemoryMode) -> MemoryMode) {
+        let value = self.read(SequencerIndex::MemoryMode);
+        let reserved = value & !MemoryMode::all().bits();
+        self.write(
+            SequencerIndex::MemoryMode,
+            f(MemoryMode::from_bits_truncate(value)).bits() | reserved,
+        );
+    }
+
     fn set_index(&mut self, index: SequencerIndex) {
         unsafe {
-            self.srx_index.write(u8::from(index));
+            self.port_io.write_port(SRX_INDEX_ADDRESS, u8::from(index));
         }
     }
 }
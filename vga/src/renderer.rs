@@ -0,0 +1,916 @@
+//! Renders the contents of vga memory into rgb images.
+//!
+//! The renderer works on plain data, so the planes can come from a
+//! `VgaEmulator` or from a dump of real (or qemu) vga memory. Images can be
+//! written out as PPM or PNG, which makes it possible to keep golden
+//! screenshots of every writer and compare them in tests.
+
+use super::{
+    colors::PALETTE_SIZE,
+    configurations::VgaConfiguration,
+    fonts::VgaFont,
+    registers::{
+        AttributeControllerIndex, AttributeModeControl, ClockingMode, CrtcControllerIndex,
+        GraphicsControllerIndex, GraphicsMiscellaneous, GraphicsMode, MaximumScanLine, MemoryMode,
+        Overflow, SequencerIndex,
+    },
+    vga::VideoModeInfo,
+};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const MAX_STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+/// The register values that determine how vga memory is displayed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayRegisters {
+    /// Represents the value of the miscellaneous output register.
+    pub miscellaneous_output: u8,
+    /// Represents the values of the sequencer registers, indexed by `SequencerIndex`.
+    pub sequencer_registers: [u8; 0x08],
+    /// Represents the values of the crtc controller registers, indexed by `CrtcControllerIndex`.
+    pub crtc_controller_registers: [u8; 0x19],
+    /// Represents the values of the graphics controller registers, indexed by
+    /// `GraphicsControllerIndex`.
+    pub graphics_controller_registers: [u8; 0x09],
+    /// Represents the values of the attribute controller registers, indexed by
+    /// `AttributeControllerIndex`.
+    pub attribute_controller_registers: [u8; 0x15],
+}
+
+impl DisplayRegisters {
+    /// Decodes the layout of the video mode set by these registers.
+    pub fn video_mode_info(&self) -> VideoModeInfo {
+        let graphics = self.is_graphics_mode();
+        let height = self.display_scan_lines() / self.scan_line_height();
+        let (width, colors) = if !graphics {
+            (self.display_columns(), 16)
+        } else if self.is_256_color_mode() {
+            (self.display_columns() * 4, 256)
+        } else {
+            let planes =
+                (self.attribute(AttributeControllerIndex::MemoryPlaneEnable) & 0x0F).count_ones();
+            (self.display_columns() * 8, 1 << planes)
+        };
+        VideoModeInfo {
+            graphics,
+            width,
+            height,
+            colors,
+            chain_4: MemoryMode::from_bits_truncate(self.sequencer(SequencerIndex::MemoryMode))
+                .contains(MemoryMode::CHAIN_4),
+        }
+    }
+
+    /// Sets the attribute controller palette registers to `palette`.
+    pub fn set_attribute_palette(&mut self, palette: &[u8; 16]) {
+        self.attribute_controller_registers[..16].copy_from_slice(palette);
+    }
+
+    /// Returns the `(width, height)` of the displayed image in pixels.
+    pub(crate) fn dimensions(&self) -> (usize, usize) {
+        if self.is_graphics_mode() {
+            let width = self.display_columns() * 8;
+            let width = if self.is_256_color_mode() {
+                width / 2
+            } else {
+                width
+            };
+            (width, self.display_scan_lines() / self.scan_line_height())
+        } else {
+            let rows = self.display_scan_lines() / self.scan_line_height();
+            (
+                self.display_columns() * self.character_width(),
+                rows * self.scan_line_height(),
+            )
+        }
+    }
+
+    fn sequencer(&self, index: SequencerIndex) -> u8 {
+        self.sequencer_registers[usize::from(u8::from(index))]
+    }
+
+    fn graphics_controller(&self, index: GraphicsControllerIndex) -> u8 {
+        self.graphics_controller_registers[usize::from(u8::from(index))]
+    }
+
+    fn crtc(&self, index: CrtcControllerIndex) -> u8 {
+        self.crtc_controller_registers[usize::from(u8::from(index))]
+    }
+
+    fn attribute(&self, index: AttributeControllerIndex) -> u8 {
+        self.attribute_controller_registers[usize::from(u8::from(index))]
+    }
+
+    fn is_graphics_mode(&self) -> bool {
+        GraphicsMiscellaneous::from_bits_truncate(
+            self.graphics_controller(GraphicsControllerIndex::Miscellaneous),
+        )
+        .contains(GraphicsMiscellaneous::GRAPHICS_MODE)
+    }
+
+    fn is_256_color_mode(&self) -> bool {
+        GraphicsMode::from_bits_truncate(
+            self.graphics_controller(GraphicsControllerIndex::GraphicsMode),
+        )
+        .contains(GraphicsMode::SHIFT_256_COLOR)
+    }
+
+    pub(crate) fn character_width(&self) -> usize {
+        if ClockingMode::from_bits_truncate(self.sequencer(SequencerIndex::ClockingMode))
+            .contains(ClockingMode::EIGHT_DOT_CHARACTERS)
+        {
+            8
+        } else {
+            9
+        }
+    }
+
+    pub(crate) fn scan_line_height(&self) -> usize {
+        let maximum_scan_line =
+            MaximumScanLine::from_bits_truncate(self.crtc(CrtcControllerIndex::MaximumScanLine));
+        let height = usize::from(maximum_scan_line.character_height());
+        if maximum_scan_line.contains(MaximumScanLine::SCAN_DOUBLING) {
+            height * 2
+        } else {
+            height
+        }
+    }
+
+    fn display_columns(&self) -> usize {
+        usize::from(self.crtc(CrtcControllerIndex::HorizontalDisplayEnableEnd)) + 1
+    }
+
+    fn display_scan_lines(&self) -> usize {
+        let overflow = Overflow::from_bits_truncate(self.crtc(CrtcControllerIndex::Overflow));
+        let vertical_display_end =
+            usize::from(self.crtc(CrtcControllerIndex::VerticalDisplayEnableEnd))
+                | usize::from(overflow.contains(Overflow::VERTICAL_DISPLAY_END_8)) << 8
+                | usize::from(overflow.contains(Overflow::VERTICAL_DISPLAY_END_9)) << 9;
+        vertical_display_end + 1
+    }
+
+    pub(crate) fn start_address(&self) -> usize {
+        usize::from(self.crtc(CrtcControllerIndex::StartAddressHigh)) << 8
+            | usize::from(self.crtc(CrtcControllerIndex::StartAddressLow))
+    }
+
+    pub(crate) fn line_offset(&self) -> usize {
+        usize::from(self.crtc(CrtcControllerIndex::Offset)) * 2
+    }
+
+    fn preset_row_scan(&self) -> usize {
+        usize::from(self.crtc(CrtcControllerIndex::PresetRowScan) & 0x1F)
+    }
+
+    pub(crate) fn line_compare(&self) -> usize {
+        let overflow = Overflow::from_bits_truncate(self.crtc(CrtcControllerIndex::Overflow));
+        let maximum_scan_line =
+            MaximumScanLine::from_bits_truncate(self.crtc(CrtcControllerIndex::MaximumScanLine));
+        usize::from(self.crtc(CrtcControllerIndex::LineCompare))
+            | usize::from(overflow.contains(Overflow::LINE_COMPARE_8)) << 8
+            | usize::from(maximum_scan_line.contains(MaximumScanLine::LINE_COMPARE_9)) << 9
+    }
+
+    /// Returns the start address, the scan line relative to that address and
+    /// the pixel panning used to display physical scan line `scan_line`,
+    /// which differ between the upper and lower window of a split screen.
+    fn display_window(&self, scan_line: usize) -> (usize, usize, usize) {
+        let line_compare = self.line_compare();
+        if scan_line <= line_compare {
+            let scan_line = if self.is_graphics_mode() {
+                scan_line
+            } else {
+                scan_line + self.preset_row_scan()
+            };
+            (self.start_address(), scan_line, self.pixel_panning())
+        } else {
+            let mode_control = AttributeModeControl::from_bits_truncate(
+                self.attribute(AttributeControllerIndex::ModeControl),
+            );
+            let pixel_panning = if mode_control.contains(AttributeModeControl::PIXEL_PANNING_MODE) {
+                0
+            } else {
+                self.pixel_panning()
+            };
+            (0, scan_line - line_compare - 1, pixel_panning)
+        }
+    }
+
+    /// Returns the number of pixels the display is shifted to the left.
+    fn pixel_panning(&self) -> usize {
+        let panning =
+            usize::from(self.attribute(AttributeControllerIndex::HorizontalPixelPanning) & 0x0F);
+        if self.is_graphics_mode() {
+            if self.is_256_color_mode() {
+                (panning & 0x07) / 2
+            } else {
+                panning & 0x07
+            }
+        } else if self.character_width() == 9 {
+            // A panning of 8 shows the first dot of a 9 dot character.
+            (panning + 1) % 9
+        } else {
+            panning & 0x07
+        }
+    }
+
+    pub(crate) fn cursor_location(&self) -> Option<usize> {
+        if self.crtc(CrtcControllerIndex::TextCursorStart) & 0x20 != 0 {
+            return None;
+        }
+        Some(
+            usize::from(self.crtc(CrtcControllerIndex::TextCursorLocationHigh)) << 8
+                | usize::from(self.crtc(CrtcControllerIndex::TextCursorLocationLow)),
+        )
+    }
+
+    /// Maps a 4 bit attribute to an index into the dac palette.
+    pub(crate) fn palette_index(&self, attribute: u8) -> u8 {
+        let mode_control = AttributeModeControl::from_bits_truncate(
+            self.attribute(AttributeControllerIndex::ModeControl),
+        );
+        let color_select = self.attribute(AttributeControllerIndex::ColorSelect);
+        let attribute = attribute & self.attribute(AttributeControllerIndex::MemoryPlaneEnable);
+        let palette = self.attribute_controller_registers[usize::from(attribute & 0x0F)] & 0x3F;
+        let palette = if mode_control.contains(AttributeModeControl::PALETTE_BITS_5_4_SELECT) {
+            (palette & 0x0F) | ((color_select & 0x03) << 4)
+        } else {
+            palette
+        };
+        palette | ((color_select & 0x0C) << 4)
+    }
+}
+
+impl From<&VgaConfiguration> for DisplayRegisters {
+    fn from(configuration: &VgaConfiguration) -> DisplayRegisters {
+        let mut registers = DisplayRegisters {
+            miscellaneous_output: configuration.miscellaneous_output,
+            sequencer_registers: [0; 0x08],
+            crtc_controller_registers: [0; 0x19],
+            graphics_controller_registers: [0; 0x09],
+            attribute_controller_registers: [0; 0x15],
+        };
+        for (index, value) in configuration.sequencer_registers {
+            if let Some(register) = registers
+                .sequencer_registers
+                .get_mut(usize::from(u8::from(*index)))
+            {
+                *register = *value;
+            }
+        }
+        for (index, value) in configuration.crtc_controller_registers {
+            if let Some(register) = registers
+                .crtc_controller_registers
+                .get_mut(usize::from(u8::from(*index)))
+            {
+                *register = *value;
+            }
+        }
+        for (index, value) in configuration.graphics_controller_registers {
+            if let Some(register) = registers
+                .graphics_controller_registers
+                .get_mut(usize::from(u8::from(*index)))
+            {
+                *register = *value;
+            }
+        }
+        for (index, value) in configuration.attribute_controller_registers {
+            if let Some(register) = registers
+                .attribute_controller_registers
+                .get_mut(usize::from(u8::from(*index)))
+            {
+                *register = *value;
+            }
+        }
+        registers
+    }
+}
+
+/// Renders vga memory into rgb pixels, as specified by a set of
+/// `DisplayRegisters` and the dac palette.
+///
+/// Graphics modes are rendered at their logical resolution (for example
+/// 320x200 for mode 13h), while text modes are rendered one pixel per dot,
+/// using either the font in plane 2 or an explicit `VgaFont`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use vga::colors::DEFAULT_PALETTE;
+/// use vga::configurations::MODE_320X200X256_CONFIGURATION;
+/// use vga::renderer::{DisplayRegisters, Renderer};
+///
+/// let mut planes = [[0u8; 0x10000], [0u8; 0x10000], [0u8; 0x10000], [0u8; 0x10000]];
+/// // Pixel (1, 0) of mode 13h lives in plane 1.
+/// planes[1][0] = 0x0F;
+///
+/// let registers = DisplayRegisters::from(&MODE_320X200X256_CONFIGURATION);
+/// let renderer = Renderer::new(
+///     [&planes[0], &planes[1], &planes[2], &planes[3]],
+///     registers,
+///     &DEFAULT_PALETTE,
+/// );
+///
+/// assert_eq!(renderer.dimensions(), (320, 200));
+/// assert_eq!(renderer.pixel(0, 0), [0x00, 0x00, 0x00]);
+/// assert_eq!(renderer.pixel(1, 0), [0xFF, 0xFF, 0xFF]);
+///
+/// let mut ppm = Vec::new();
+/// renderer.write_ppm(|bytes| ppm.extend_from_slice(bytes));
+/// assert!(ppm.starts_with(b"P6\n320 200\n255\n"));
+/// assert_eq!(renderer.diff_ppm(&ppm), Some(0));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Renderer<'a> {
+    planes: [&'a [u8]; 4],
+    registers: DisplayRegisters,
+    palette: &'a [u8; PALETTE_SIZE],
+    font: Option<&'a VgaFont>,
+}
+
+impl<'a> Renderer<'a> {
+    /// Creates a new `Renderer` for the given `planes`, `registers` and
+    /// dac `palette`.
+    pub fn new(
+        planes: [&'a [u8]; 4],
+        registers: DisplayRegisters,
+        palette: &'a [u8; PALETTE_SIZE],
+    ) -> Renderer<'a> {
+        Renderer {
+            planes,
+            registers,
+            palette,
+            font: None,
+        }
+    }
+
+    /// Renders text modes using `font`, instead of the font loaded into plane 2.
+    pub fn with_font(mut self, font: &'a VgaFont) -> Renderer<'a> {
+        self.font = Some(font);
+        self
+    }
+
+    /// Returns the `(width, height)` of the rendered image in pixels.
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.registers.dimensions()
+    }
+
+    /// Returns the rgb color of the pixel at `(x, y)`.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let palette_index = if self.registers.is_graphics_mode() {
+            self.graphics_palette_index(x, y)
+        } else {
+            self.text_palette_index(x, y)
+        };
+        let offset = usize::from(palette_index) * 3;
+        [
+            expand_color(self.palette[offset]),
+            expand_color(self.palette[offset + 1]),
+            expand_color(self.palette[offset + 2]),
+        ]
+    }
+
+    /// Renders the image into `image` as consecutive rgb triples, row by
+    /// row, returning its `(width, height)`, or `None` if `image` is too small.
+    pub fn render(&self, image: &mut [u8]) -> Option<(usize, usize)> {
+        let (width, height) = self.dimensions();
+        if image.len() < width * height * 3 {
+            return None;
+        }
+        for (index, pixel) in image.chunks_exact_mut(3).take(width * height).enumerate() {
+            pixel.copy_from_slice(&self.pixel(index % width, index / width));
+        }
+        Some((width, height))
+    }
+
+    /// Writes the image as a binary PPM (`P6`), passing the encoded bytes to `sink`.
+    pub fn write_ppm<F: FnMut(&[u8])>(&self, mut sink: F) {
+        let (width, height) = self.dimensions();
+        sink(b"P6\n");
+        write_decimal(&mut sink, width);
+        sink(b" ");
+        write_decimal(&mut sink, height);
+        sink(b"\n255\n");
+        for y in 0..height {
+            for x in 0..width {
+                sink(&self.pixel(x, y));
+            }
+        }
+    }
+
+    /// Writes the image as an uncompressed PNG, passing the encoded bytes to `sink`.
+    pub fn write_png<F: FnMut(&[u8])>(&self, mut sink: F) {
+        let (width, height) = self.dimensions();
+        let row_size = 1 + width * 3;
+        let data_size = row_size * height;
+        let blocks = data_size.div_ceil(MAX_STORED_BLOCK_SIZE);
+
+        sink(&PNG_SIGNATURE);
+
+        let mut header = [0; 13];
+        header[0..4].copy_from_slice(&(width as u32).to_be_bytes());
+        header[4..8].copy_from_slice(&(height as u32).to_be_bytes());
+        // 8 bits per channel, rgb color, no interlacing.
+        header[8] = 8;
+        header[9] = 2;
+        let mut chunk = PngChunk::new(&mut sink, b"IHDR", header.len());
+        chunk.write(&header);
+        chunk.finish();
+
+        let mut chunk = PngChunk::new(&mut sink, b"IDAT", 2 + blocks * 5 + data_size + 4);
+        // zlib header for a deflate stream without compression.
+        chunk.write(&[0x78, 0x01]);
+        let mut adler = Adler32::new();
+        let mut block_remaining = 0;
+        let mut written = 0;
+        for y in 0..height {
+            for x in 0..=width {
+                let pixel = if x == 0 {
+                    // Every row starts with filter type 0 (none).
+                    [0; 3]
+                } else {
+                    self.pixel(x - 1, y)
+                };
+                let bytes = if x == 0 { &pixel[..1] } else { &pixel[..] };
+                for byte in bytes {
+                    if block_remaining == 0 {
+                        let size = (data_size - written).min(MAX_STORED_BLOCK_SIZE);
+                        let last = u8::from(written + size == data_size);
+                        chunk.write(&[last]);
+                        chunk.write(&(size as u16).to_le_bytes());
+                        chunk.write(&(!(size as u16)).to_le_bytes());
+                        block_remaining = size;
+                    }
+                    chunk.write(&[*byte]);
+                    adler.update(*byte);
+                    block_remaining -= 1;
+                    written += 1;
+                }
+            }
+        }
+        chunk.write(&adler.finish().to_be_bytes());
+        chunk.finish();
+
+        let chunk = PngChunk::new(&mut sink, b"IEND", 0);
+        chunk.finish();
+    }
+
+    /// Compares the image against a binary PPM (`P6`) in `ppm`, returning
+    /// the number of pixels that differ, or `None` if `ppm` can't be parsed
+    /// or has different dimensions.
+    pub fn diff_ppm(&self, ppm: &[u8]) -> Option<usize> {
+        let (width, height) = self.dimensions();
+        let mut fields = [0; 3];
+        let mut position = 2;
+        if !ppm.starts_with(b"P6") {
+            return None;
+        }
+        for field in fields.iter_mut() {
+            while ppm.get(position)?.is_ascii_whitespace() {
+                position += 1;
+            }
+            let start = position;
+            while ppm.get(position)?.is_ascii_digit() {
+                *field = *field * 10 + usize::from(ppm[position] - b'0');
+                position += 1;
+            }
+            if start == position {
+                return None;
+            }
+        }
+        if fields != [width, height, 255] {
+            return None;
+        }
+        let pixels = ppm.get(position + 1..position + 1 + width * height * 3)?;
+        Some(
+            pixels
+                .chunks_exact(3)
+                .enumerate()
+                .filter(|(index, pixel)| *pixel != self.pixel(index % width, index / width))
+                .count(),
+        )
+    }
+
+    fn plane_byte(&self, plane: usize, address: usize) -> u8 {
+        let plane = self.planes[plane];
+        if plane.is_empty() {
+            0
+        } else {
+            plane[address % plane.len()]
+        }
+    }
+
+    fn graphics_palette_index(&self, x: usize, y: usize) -> u8 {
+        let registers = &self.registers;
+        let scan_line_height = registers.scan_line_height();
+        let (start_address, scan_line, pixel_panning) =
+            registers.display_window(y * scan_line_height);
+        let (x, y) = (x + pixel_panning, scan_line / scan_line_height);
+        let line_start = start_address + y * registers.line_offset();
+        if registers.is_256_color_mode() {
+            self.plane_byte(x & 0x3, line_start + x / 4)
+        } else {
+            let address = line_start + x / 8;
+            let bit = 7 - (x & 0x7);
+            let attribute = (0..4).fold(0, |color, plane| {
+                color | (((self.plane_byte(plane, address) >> bit) & 0x1) << plane)
+            });
+            registers.palette_index(attribute)
+        }
+    }
+
+    fn text_palette_index(&self, x: usize, y: usize) -> u8 {
+        let registers = &self.registers;
+        let character_width = registers.character_width();
+        let character_height = registers.scan_line_height();
+        let (start_address, y, pixel_panning) = registers.display_window(y);
+        let x = x + pixel_panning;
+        let (column, dot) = (x / character_width, x % character_width);
+        let (row, line) = (y / character_height, y % character_height);
+        let address = start_address + row * registers.line_offset() + column;
+        let character = self.plane_byte(0, address);
+        let attribute = self.plane_byte(1, address);
+
+        let glyph = self.glyph_line(character, line);
+        let mode_control = AttributeModeControl::from_bits_truncate(
+            registers.attribute(AttributeControllerIndex::ModeControl),
+        );
+        let foreground = if dot < 8 {
+            glyph & (0x80 >> dot) != 0
+        } else {
+            // The ninth dot repeats the eighth for the line graphics characters.
+            mode_control.contains(AttributeModeControl::LINE_GRAPHICS)
+                && (0xC0..=0xDF).contains(&character)
+                && glyph & 0x01 != 0
+        };
+        let cursor = registers.cursor_location() == Some(address) && {
+            let start = usize::from(registers.crtc(CrtcControllerIndex::TextCursorStart) & 0x1F);
+            let end = usize::from(registers.crtc(CrtcControllerIndex::TextCursorEnd) & 0x1F);
+            line >= start && line <= end
+        };
+
+        let background = if mode_control.contains(AttributeModeControl::BLINK) {
+            (attribute >> 4) & 0x07
+        } else {
+            attribute >> 4
+        };
+        if foreground || cursor {
+            registers.palette_index(attribute & 0x0F)
+        } else {
+            registers.palette_index(background)
+        }
+    }
+
+    fn glyph_line(&self, character: u8, line: usize) -> u8 {
+        match self.font {
+            Some(font) if line < usize::from(font.character_height) => {
+                font.font_data[usize::from(character) * usize::from(font.character_height) + line]
+            }
+            Some(_) => 0,
+            None => self.plane_byte(2, usize::from(character) * 32 + line),
+        }
+    }
+}
+
+/// Expands a 6 bit dac color component to 8 bits.
+fn expand_color(value: u8) -> u8 {
+    let value = value & 0x3F;
+    (value << 2) | (value >> 4)
+}
+
+fn write_decimal<F: FnMut(&[u8])>(sink: &mut F, value: usize) {
+    let mut digits = [0; 20];
+    let mut position = digits.len();
+    let mut value = value;
+    loop {
+        position -= 1;
+        digits[position] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    sink(&digits[position..]);
+}
+
+struct PngChunk<'a, F: FnMut(&[u8])> {
+    sink: &'a mut F,
+    crc: u32,
+}
+
+impl<'a, F: FnMut(&[u8])> PngChunk<'a, F> {
+    fn new(sink: &'a mut F, chunk_type: &[u8; 4], length: usize) -> PngChunk<'a, F> {
+        sink(&(length as u32).to_be_bytes());
+        let mut chunk = PngChunk { sink, crc: !0 };
+        chunk.write(chunk_type);
+        chunk
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.crc = CRC_TABLE[((self.crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (self.crc >> 8);
+        }
+        (self.sink)(bytes);
+    }
+
+    fn finish(self) {
+        (self.sink)(&(!self.crc).to_be_bytes());
+    }
+}
+
+struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    fn update(&mut self, byte: u8) {
+        self.a = (self.a + u32::from(byte)) % 65521;
+        self.b = (self.b + self.a) % 65521;
+    }
+
+    fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        colors::{Color16, TextModeColor, DEFAULT_PALETTE},
+        emulator::VgaEmulator,
+        fonts::TEXT_8X16_FONT,
+        vga::Vga,
+        writers::{
+            Graphics320x200x256, Graphics320x240x256, Graphics640x480x16, GraphicsWriter,
+            ScreenCharacter, Text40x25, Text40x50, Text80x25, TextWriter,
+        },
+    };
+    use spinning_top::Spinlock;
+
+    const WHITE: [u8; 3] = [0xFF, 0xFF, 0xFF];
+    const BLACK: [u8; 3] = [0x00, 0x00, 0x00];
+    const LIGHT_GREY: [u8; 3] = [0xAA, 0xAA, 0xAA];
+
+    const COLORS: [Color16; 16] = [
+        Color16::Black,
+        Color16::Blue,
+        Color16::Green,
+        Color16::Cyan,
+        Color16::Red,
+        Color16::Magenta,
+        Color16::Brown,
+        Color16::LightGrey,
+        Color16::DarkGrey,
+        Color16::LightBlue,
+        Color16::LightGreen,
+        Color16::LightCyan,
+        Color16::LightRed,
+        Color16::Pink,
+        Color16::Yellow,
+        Color16::White,
+    ];
+
+    /// Checks the crc32 of the rendered PPM image against the crc of the
+    /// golden image for this test, so any change to the rendered pixels fails.
+    fn assert_golden(emulator: &VgaEmulator, golden_crc: u32) {
+        let mut crc = !0u32;
+        emulator.with_renderer(|renderer| {
+            renderer.write_ppm(|bytes| {
+                for byte in bytes {
+                    crc = CRC_TABLE[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8);
+                }
+            })
+        });
+        assert_eq!(!crc, golden_crc, "rendered image has crc {:#010X}", !crc);
+    }
+
+    /// Draws the same scene in every graphics mode: a border, a
+    /// diagonal in every color, and some text.
+    fn draw_graphics_scene<W, Color>(mode: &W, colors: &[Color])
+    where
+        W: GraphicsWriter<Color> + crate::writers::Screen,
+        Color: Copy,
+    {
+        let (right, bottom) = (W::WIDTH as isize - 1, W::HEIGHT as isize - 1);
+        mode.clear_screen(colors[0]);
+        mode.draw_line((0, 0), (right, 0), colors[1]);
+        mode.draw_line((right, 0), (right, bottom), colors[1]);
+        mode.draw_line((right, bottom), (0, bottom), colors[1]);
+        mode.draw_line((0, bottom), (0, 0), colors[1]);
+        for (index, &color) in colors.iter().enumerate() {
+            let x = 10 + index as isize * 4;
+            mode.draw_line((x, 10), (x + 40, 50), color);
+        }
+        for (offset, character) in "Golden".chars().enumerate() {
+            mode.draw_character(20 + offset * 8, 60, character, colors[colors.len() - 1]);
+        }
+    }
+
+    /// Writes every character in a different color, followed by a line of
+    /// text, with the cursor shown after it.
+    fn draw_text_scene<T: TextWriter>(text_mode: &T) {
+        text_mode.set_mode();
+        text_mode.clear_screen().unwrap();
+        for character in 0..=255u8 {
+            let color = TextModeColor::new(
+                COLORS[usize::from(character & 0x0F)],
+                COLORS[usize::from((character >> 4) & 0x07)],
+            );
+            let (x, y) = (
+                usize::from(character) % T::WIDTH,
+                usize::from(character) / T::WIDTH,
+            );
+            text_mode
+                .write_character(x, y, ScreenCharacter::new(character, color))
+                .unwrap();
+        }
+        let color = TextModeColor::new(Color16::Yellow, Color16::Blue);
+        for (x, character) in b"Golden".iter().enumerate() {
+            text_mode
+                .write_character(x, 20, ScreenCharacter::new(*character, color))
+                .unwrap();
+        }
+        text_mode.enable_cursor();
+        text_mode.set_cursor_position(6, 20);
+    }
+
+    #[test]
+    fn test_png_crc() {
+        // The crc of an empty `IEND` chunk is fixed by the png specification.
+        let mut bytes = [0; 12];
+        let mut length = 0;
+        let mut sink = |data: &[u8]| {
+            bytes[length..length + data.len()].copy_from_slice(data);
+            length += data.len();
+        };
+        PngChunk::new(&mut sink, b"IEND", 0).finish();
+        assert_eq!(bytes[8..], [0xAE, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn test_graphics_640x480x16() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics640x480x16::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(Color16::Blue);
+        mode.draw_line((0, 0), (639, 0), Color16::LightGrey);
+
+        emulator.with_renderer(|renderer| {
+            assert_eq!(renderer.dimensions(), (640, 480));
+            assert_eq!(renderer.pixel(639, 0), LIGHT_GREY);
+            assert_eq!(renderer.pixel(639, 1), [0x00, 0x00, 0xAA]);
+        });
+    }
+
+    #[test]
+    fn test_graphics_320x200x256() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics320x200x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(0);
+        mode.set_pixel(319, 199, 15);
+
+        emulator.with_renderer(|renderer| {
+            assert_eq!(renderer.dimensions(), (320, 200));
+            assert_eq!(renderer.pixel(319, 199), WHITE);
+            assert_eq!(renderer.pixel(318, 199), BLACK);
+        });
+    }
+
+    #[test]
+    fn test_graphics_320x240x256() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(0);
+        mode.set_pixel(7, 239, 15);
+
+        emulator.with_renderer(|renderer| {
+            assert_eq!(renderer.dimensions(), (320, 240));
+            assert_eq!(renderer.pixel(7, 239), WHITE);
+            assert_eq!(renderer.pixel(6, 239), BLACK);
+        });
+    }
+
+    #[test]
+    fn test_text_80x25() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text80x25::with_device(&vga);
+        let color = TextModeColor::new(Color16::LightGrey, Color16::Black);
+        text_mode.set_mode();
+        text_mode.clear_screen().unwrap();
+        text_mode.disable_cursor();
+        text_mode
+            .write_character(1, 0, ScreenCharacter::new(0xDB, color))
+            .unwrap();
+
+        emulator.with_renderer(|renderer| {
+            assert_eq!(renderer.dimensions(), (720, 400));
+            // A full block covers the first 8 dots, and the 9th dot
+            // is extended for line graphics characters.
+            for y in 0..16 {
+                for x in 9..18 {
+                    assert_eq!(renderer.pixel(x, y), LIGHT_GREY);
+                }
+            }
+            assert_eq!(renderer.pixel(18, 0), BLACK);
+            assert_eq!(renderer.with_font(&TEXT_8X16_FONT).pixel(9, 0), LIGHT_GREY);
+        });
+    }
+
+    #[test]
+    fn test_text_40x50() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text40x50::with_device(&vga);
+        text_mode.set_mode();
+        text_mode.clear_screen().unwrap();
+
+        emulator.with_renderer(|renderer| {
+            assert_eq!(renderer.dimensions(), (360, 400));
+        });
+    }
+
+    #[test]
+    fn test_golden_images() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+
+        let mode = Graphics640x480x16::with_device(&vga);
+        mode.set_mode();
+        draw_graphics_scene(&mode, &COLORS);
+        assert_golden(&emulator, 0xAA10702E);
+
+        let colors = [
+            0, 40, 32, 48, 64, 80, 100, 120, 140, 160, 180, 200, 220, 240, 250, 15,
+        ];
+        let mode = Graphics320x200x256::with_device(&vga);
+        mode.set_mode();
+        draw_graphics_scene(&mode, &colors);
+        assert_golden(&emulator, 0xF5E44DE5);
+
+        let mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        draw_graphics_scene(&mode, &colors);
+        assert_golden(&emulator, 0x521C2554);
+
+        draw_text_scene(&Text80x25::with_device(&vga));
+        assert_golden(&emulator, 0xB483A501);
+
+        draw_text_scene(&Text40x25::with_device(&vga));
+        assert_golden(&emulator, 0x62C61330);
+
+        draw_text_scene(&Text40x50::with_device(&vga));
+        assert_golden(&emulator, 0xFEA5BD2B);
+    }
+
+    #[test]
+    fn test_diff_ppm() {
+        let planes = [[0u8; 16]; 4];
+        let registers =
+            DisplayRegisters::from(&crate::configurations::MODE_640X480X16_CONFIGURATION);
+        let renderer = Renderer::new(
+            [&planes[0], &planes[1], &planes[2], &planes[3]],
+            registers,
+            &DEFAULT_PALETTE,
+        );
+        let mut ppm = [0; 32];
+        ppm[..15].copy_from_slice(b"P6\n640 480\n255\n");
+        assert_eq!(renderer.diff_ppm(&ppm), None);
+        assert_eq!(renderer.diff_ppm(b"P5\n640 480\n255\n"), None);
+    }
+}
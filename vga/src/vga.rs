@@ -1,56 +1,119 @@
 //! Provides access to the vga graphics card.
 
 use super::{
+    colors::PALETTE_SIZE,
     configurations::{
         VgaConfiguration, MODE_320X200X256_CONFIGURATION, MODE_320X240X256_CONFIGURATION,
         MODE_40X25_CONFIGURATION, MODE_40X50_CONFIGURATION, MODE_640X480X16_CONFIGURATION,
         MODE_80X25_CONFIGURATION,
     },
+    dump::RegisterDump,
     fonts::VgaFont,
+    io::{MemoryIo, PortIo, X86Io},
     registers::{
         AttributeControllerRegisters, ColorPaletteRegisters, CrtcControllerIndex,
         CrtcControllerRegisters, EmulationMode, GeneralRegisters, GraphicsControllerIndex,
-        GraphicsControllerRegisters, PlaneMask, SequencerIndex, SequencerRegisters,
+        GraphicsControllerRegisters, GraphicsMiscellaneous, GraphicsMode, HorizontalBlankingEnd,
+        MemoryMode, PlaneMask, ReadPlane, SequencerIndex, SequencerRegisters, VerticalSyncEnd,
     },
+    renderer::{DisplayRegisters, Renderer},
+    Error,
 };
 use conquer_once::spin::Lazy;
-use spinning_top::Spinlock;
+use core::{convert::TryFrom, fmt};
+use spinning_top::{Spinlock, SpinlockGuard};
+
+const LEGACY_FRAME_BUFFER_ADDRESS: usize = 0xA0000;
+const PLANE_SIZE: usize = 0x10000;
+
+// Every configuration lists every register, so the indices of any
+// of them can be used to go through all of the registers.
+const ALL_REGISTERS: &VgaConfiguration = &MODE_80X25_CONFIGURATION;
 
 /// Provides mutable access to the vga graphics card.
 pub static VGA: Lazy<Spinlock<Vga>> = Lazy::new(|| Spinlock::new(Vga::new()));
 
+/// Provides locked access to a `Vga`, used by the writers in
+/// `vga::writers` to reach the card they draw to.
+pub trait VgaDevice {
+    /// The backend used to access the registers and memory of the `Vga`.
+    type Io: PortIo + MemoryIo;
+
+    /// Locks the `Vga`, releasing it when the returned guard is dropped.
+    fn lock(&self) -> SpinlockGuard<'_, Vga<Self::Io>>;
+}
+
+/// The `VgaDevice` for the global `VGA`, which accesses the real hardware.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GlobalVga;
+
+impl VgaDevice for GlobalVga {
+    type Io = X86Io;
+
+    fn lock(&self) -> SpinlockGuard<'_, Vga> {
+        VGA.lock()
+    }
+}
+
+impl<I: PortIo + MemoryIo> VgaDevice for &Spinlock<Vga<I>> {
+    type Io = I;
+
+    fn lock(&self) -> SpinlockGuard<'_, Vga<I>> {
+        Spinlock::lock(*self)
+    }
+}
+
 /// Represents the starting address of the frame buffer for
-/// various video modes.
-#[derive(Debug, Copy, Clone)]
-#[repr(u32)]
+/// various video modes, as selected by the memory map of the
+/// graphics controller `Miscellaneous` register.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FrameBuffer {
+    /// The starting address of the 128K memory map, which covers
+    /// the whole `0xA0000 -> 0xBFFFF` window.
+    ExtendedGraphicsMode,
     /// The starting address for graphics modes.
-    GraphicsMode = 0xa0000,
+    GraphicsMode,
     /// The starting address for color text modes.
-    CgaMode = 0xb8000,
+    CgaMode,
     /// The starting address for monochrome text modes.
-    MdaMode = 0xb0000,
+    MdaMode,
 }
 
-impl From<u8> for FrameBuffer {
-    fn from(value: u8) -> FrameBuffer {
-        match value {
+impl FrameBuffer {
+    pub(crate) fn from_memory_map(memory_map: u8) -> FrameBuffer {
+        match memory_map & 0x3 {
+            0x0 => FrameBuffer::ExtendedGraphicsMode,
             0x1 => FrameBuffer::GraphicsMode,
             0x2 => FrameBuffer::MdaMode,
-            0x3 => FrameBuffer::CgaMode,
-            _ => panic!("{:X} is not a valid FrameBuffer value", value),
+            _ => FrameBuffer::CgaMode,
+        }
+    }
+}
+
+impl TryFrom<u8> for FrameBuffer {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value <= 0x3 {
+            Ok(FrameBuffer::from_memory_map(value))
+        } else {
+            Err(Error::InvalidMemoryMap(value))
         }
     }
 }
 
 impl From<FrameBuffer> for u32 {
     fn from(value: FrameBuffer) -> u32 {
-        value as u32
+        match value {
+            FrameBuffer::ExtendedGraphicsMode | FrameBuffer::GraphicsMode => 0xa0000,
+            FrameBuffer::CgaMode => 0xb8000,
+            FrameBuffer::MdaMode => 0xb0000,
+        }
     }
 }
 
 /// Represents a specified vga video mode.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VideoMode {
     /// Represents text mode 40x25.
     Mode40x25,
@@ -66,37 +129,191 @@ pub enum VideoMode {
     Mode640x480x16,
 }
 
+impl VideoMode {
+    /// Returns the `VideoMode` described by `info`, if any.
+    fn from_info(info: &VideoModeInfo) -> Option<VideoMode> {
+        let VideoModeInfo {
+            graphics,
+            width,
+            height,
+            colors,
+            chain_4,
+        } = *info;
+        match (graphics, width, height, colors, chain_4) {
+            (false, 40, 25, 16, _) => Some(VideoMode::Mode40x25),
+            (false, 40, 50, 16, _) => Some(VideoMode::Mode40x50),
+            (false, 80, 25, 16, _) => Some(VideoMode::Mode80x25),
+            (true, 320, 200, 256, true) => Some(VideoMode::Mode320x200x256),
+            (true, 320, 240, 256, false) => Some(VideoMode::Mode320x240x256),
+            (true, 640, 480, 16, _) => Some(VideoMode::Mode640x480x16),
+            _ => None,
+        }
+    }
+}
+
+/// Describes the layout of a video mode, as decoded from its registers
+/// by `DisplayRegisters::video_mode_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoModeInfo {
+    /// Whether the mode is a graphics mode rather than a text mode.
+    pub graphics: bool,
+    /// The width of the screen, in pixels for graphics modes
+    /// and in characters for text modes.
+    pub width: usize,
+    /// The height of the screen, in pixels for graphics modes
+    /// and in characters for text modes.
+    pub height: usize,
+    /// The number of colors a pixel or character can have.
+    pub colors: usize,
+    /// Whether the four planes are chained into linear memory,
+    /// as in 256 color mode 13h.
+    pub chain_4: bool,
+}
+
+impl fmt::Display for VideoModeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.graphics { "graphics" } else { "text" };
+        write!(
+            f,
+            "{}x{} {} with {} colors",
+            self.width, self.height, kind, self.colors
+        )
+    }
+}
+
+/// A snapshot of the registers, palette and memory of a vga card,
+/// as taken by `Vga::save_state`.
+///
+/// A `VgaState` holds all 256K of vga memory, so it's usually best kept in
+/// a `static` rather than on the stack.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```no_run
+/// use spinning_top::Spinlock;
+/// use vga::vga::{VgaState, VideoMode, VGA};
+///
+/// static BOOT_STATE: Spinlock<VgaState> = Spinlock::new(VgaState::new());
+///
+/// let mut vga = VGA.lock();
+/// vga.save_state(&mut BOOT_STATE.lock());
+/// vga.set_video_mode(VideoMode::Mode640x480x16);
+/// // ...
+/// vga.restore_state(&BOOT_STATE.lock());
+/// ```
+#[derive(Debug, Clone)]
+pub struct VgaState {
+    registers: DisplayRegisters,
+    feature_control: u8,
+    palette: [u8; PALETTE_SIZE],
+    planes: [[u8; PLANE_SIZE]; 4],
+    video_mode: Option<VideoMode>,
+}
+
+impl VgaState {
+    /// Creates a new, zeroed `VgaState` to be filled in by `Vga::save_state`.
+    pub const fn new() -> VgaState {
+        VgaState {
+            registers: DisplayRegisters {
+                miscellaneous_output: 0,
+                sequencer_registers: [0; 0x08],
+                crtc_controller_registers: [0; 0x19],
+                graphics_controller_registers: [0; 0x09],
+                attribute_controller_registers: [0; 0x15],
+            },
+            feature_control: 0,
+            palette: [0; PALETTE_SIZE],
+            planes: [[0; PLANE_SIZE]; 4],
+            video_mode: None,
+        }
+    }
+
+    /// Returns the saved registers.
+    pub fn registers(&self) -> &DisplayRegisters {
+        &self.registers
+    }
+
+    /// Returns the saved 256 color palette, with every 3 bytes
+    /// representing a color.
+    pub fn palette(&self) -> &[u8; PALETTE_SIZE] {
+        &self.palette
+    }
+
+    /// Returns the saved contents of memory plane `plane`.
+    pub fn plane(&self, plane: ReadPlane) -> &[u8; PLANE_SIZE] {
+        &self.planes[plane as usize]
+    }
+
+    /// Returns a `Renderer` for the saved state, showing what the
+    /// screen looked like when it was saved.
+    pub fn renderer(&self) -> Renderer<'_> {
+        let [plane0, plane1, plane2, plane3] = &self.planes;
+        Renderer::new(
+            [plane0, plane1, plane2, plane3],
+            self.registers,
+            &self.palette,
+        )
+    }
+}
+
+impl Default for VgaState {
+    fn default() -> VgaState {
+        VgaState::new()
+    }
+}
+
 /// Represents a vga graphics card with it's common registers,
 /// as well as the most recent video mode.
-pub struct Vga {
+///
+/// All registers are accessed through the `PortIo` backend `P`, which
+/// defaults to the real hardware.
+pub struct Vga<P: PortIo = X86Io> {
+    io: P,
     /// Represents the general registers on vga hardware.
-    pub general_registers: GeneralRegisters,
+    pub general_registers: GeneralRegisters<P>,
     /// Represents the sequencer registers on vga hardware.
-    pub sequencer_registers: SequencerRegisters,
+    pub sequencer_registers: SequencerRegisters<P>,
     /// Represents the graphics controller registers on vga hardware.
-    pub graphics_controller_registers: GraphicsControllerRegisters,
+    pub graphics_controller_registers: GraphicsControllerRegisters<P>,
     /// Represents the attribute controller registers on vga hardware.
-    pub attribute_controller_registers: AttributeControllerRegisters,
+    pub attribute_controller_registers: AttributeControllerRegisters<P>,
     /// Represents the crtc controller registers on vga hardware.
-    pub crtc_controller_registers: CrtcControllerRegisters,
+    pub crtc_controller_registers: CrtcControllerRegisters<P>,
     /// Represents the color palette registers on vga hardware.
-    pub color_palette_registers: ColorPaletteRegisters,
+    pub color_palette_registers: ColorPaletteRegisters<P>,
     most_recent_video_mode: Option<VideoMode>,
+    frame_buffer_base: usize,
+    virtual_width: Option<usize>,
 }
 
 impl Vga {
     fn new() -> Vga {
+        Vga::with_io(X86Io::new())
+    }
+}
+
+impl<P: PortIo + Clone> Vga<P> {
+    /// Creates a new `Vga` that accesses all of its registers
+    /// through `port_io`.
+    pub fn with_io(port_io: P) -> Vga<P> {
         Vga {
-            general_registers: GeneralRegisters::new(),
-            sequencer_registers: SequencerRegisters::new(),
-            graphics_controller_registers: GraphicsControllerRegisters::new(),
-            attribute_controller_registers: AttributeControllerRegisters::new(),
-            crtc_controller_registers: CrtcControllerRegisters::new(),
-            color_palette_registers: ColorPaletteRegisters::new(),
+            io: port_io.clone(),
+            general_registers: GeneralRegisters::new(port_io.clone()),
+            sequencer_registers: SequencerRegisters::new(port_io.clone()),
+            graphics_controller_registers: GraphicsControllerRegisters::new(port_io.clone()),
+            attribute_controller_registers: AttributeControllerRegisters::new(port_io.clone()),
+            crtc_controller_registers: CrtcControllerRegisters::new(port_io.clone()),
+            color_palette_registers: ColorPaletteRegisters::new(port_io),
             most_recent_video_mode: None,
+            frame_buffer_base: LEGACY_FRAME_BUFFER_ADDRESS,
+            virtual_width: None,
         }
     }
+}
 
+impl<P: PortIo> Vga<P> {
     /// Sets the vga graphics card to the given `VideoMode`.
     pub fn set_video_mode(&mut self, video_mode: VideoMode) {
         match video_mode {
@@ -112,11 +329,24 @@ impl Vga {
     /// Gets the `FrameBuffer` address as specified by the
     /// `Miscellaneous Output Register`.
     pub fn get_frame_buffer(&mut self) -> FrameBuffer {
-        let miscellaneous_graphics = self
+        let memory_map = self
             .graphics_controller_registers
-            .read(GraphicsControllerIndex::Miscellaneous);
-        let memory_map_mode = (miscellaneous_graphics >> 0x2) & 0x3;
-        FrameBuffer::from(memory_map_mode)
+            .read_miscellaneous()
+            .memory_map();
+        FrameBuffer::from_memory_map(memory_map)
+    }
+
+    /// Returns the virtual address at which the `FrameBuffer` returned by
+    /// `get_frame_buffer` is mapped, taking the frame buffer base into account.
+    pub fn get_frame_buffer_address(&mut self) -> usize {
+        let frame_buffer = u32::from(self.get_frame_buffer()) as usize;
+        self.frame_buffer_base + (frame_buffer - LEGACY_FRAME_BUFFER_ADDRESS)
+    }
+
+    /// Returns the virtual address at which the legacy vga memory
+    /// window `0xA0000 -> 0xBFFFF` is mapped.
+    pub fn get_frame_buffer_base(&self) -> usize {
+        self.frame_buffer_base
     }
 
     /// Returns the most recent video mode, or `None` if no
@@ -127,95 +357,78 @@ impl Vga {
 
     /// Returns the current `EmulationMode` as determined by the miscellaneous output register.
     pub fn get_emulation_mode(&mut self) -> EmulationMode {
-        EmulationMode::from(self.general_registers.read_msr() & 0x1)
+        EmulationMode::from_miscellaneous_output(self.general_registers.read_msr())
     }
 
-    /// Loads a vga text mode font as specified by `vga_font`.
-    pub fn load_font(&mut self, vga_font: &VgaFont) {
-        // Save registers
-        let (
-            plane_mask,
-            sequencer_memory_mode,
-            read_plane_select,
-            graphics_mode,
-            miscellaneous_graphics,
-        ) = self.save_font_registers();
-
-        // Switch to flat addressing
-        self.sequencer_registers
-            .write(SequencerIndex::MemoryMode, sequencer_memory_mode | 0x04);
-
-        // Disable Even/Odd addressing
-        self.graphics_controller_registers
-            .write(GraphicsControllerIndex::GraphicsMode, graphics_mode & !0x10);
-        self.graphics_controller_registers.write(
-            GraphicsControllerIndex::Miscellaneous,
-            miscellaneous_graphics & !0x02,
-        );
-
-        // Write font to plane
-        self.sequencer_registers.set_plane_mask(PlaneMask::PLANE2);
-
-        let frame_buffer = u32::from(self.get_frame_buffer()) as *mut u8;
-
-        for character in 0..vga_font.characters {
-            for row in 0..vga_font.character_height {
-                let offset = (character * 32) + row;
-                let font_offset = (character * vga_font.character_height) + row;
-                unsafe {
-                    frame_buffer
-                        .offset(offset as isize)
-                        .write_volatile(vga_font.font_data[font_offset as usize]);
-                }
-            }
+    /// Splits the screen into two windows after `scan_line`, using the
+    /// line compare register.
+    ///
+    /// The upper window shows vga memory from the start address as usual,
+    /// while the lower window, starting on the next scan line, always shows
+    /// vga memory from address 0 and isn't affected by horizontal pixel
+    /// panning. This makes the lower window suited for a status bar that
+    /// stays in place while the upper window is scrolled.
+    ///
+    /// `scan_line` counts physical scan lines, so in modes that scan every
+    /// row twice, such as `VideoMode::Mode320x240x256`, the lower window
+    /// starting at row `y` requires a `scan_line` of `y * 2 - 1`.
+    ///
+    /// Returns `Error::OutOfRange` if `scan_line` doesn't fit in the 10 bit
+    /// line compare register.
+    pub fn set_split_screen(&mut self, scan_line: u16) -> Result<(), Error> {
+        if scan_line > 0x3FF {
+            return Err(Error::OutOfRange(usize::from(scan_line)));
         }
+        let emulation_mode = self.get_emulation_mode();
+        self.crtc_controller_registers
+            .set_line_compare(emulation_mode, scan_line);
+        self.attribute_controller_registers
+            .set_pixel_panning_compatibility(emulation_mode, true);
+        Ok(())
+    }
 
-        self.restore_font_registers(
-            plane_mask,
-            sequencer_memory_mode,
-            read_plane_select,
-            graphics_mode,
-            miscellaneous_graphics,
-        );
+    /// Removes the split created by `set_split_screen`.
+    pub fn disable_split_screen(&mut self) {
+        let emulation_mode = self.get_emulation_mode();
+        self.crtc_controller_registers
+            .set_line_compare(emulation_mode, 0x3FF);
+        self.attribute_controller_registers
+            .set_pixel_panning_compatibility(emulation_mode, false);
     }
 
-    fn restore_font_registers(
-        &mut self,
-        plane_mask: u8,
-        sequencer_memory_mode: u8,
-        read_plane_select: u8,
-        graphics_mode: u8,
-        miscellaneous_graphics: u8,
-    ) {
-        self.sequencer_registers
-            .write(SequencerIndex::PlaneMask, plane_mask);
-        self.sequencer_registers
-            .write(SequencerIndex::MemoryMode, sequencer_memory_mode);
-        self.graphics_controller_registers
-            .write(GraphicsControllerIndex::ReadPlaneSelect, read_plane_select);
-        self.graphics_controller_registers
-            .write(GraphicsControllerIndex::GraphicsMode, graphics_mode);
-        self.graphics_controller_registers.write(
-            GraphicsControllerIndex::Miscellaneous,
-            miscellaneous_graphics,
-        );
+    /// Returns the distance between the start of two text rows in vga
+    /// memory, in characters, as set by `CrtcControllerIndex::Offset`.
+    ///
+    /// The register is only read once after each mode change, so the text
+    /// writers don't read it for every character.
+    pub(crate) fn virtual_width(&mut self) -> usize {
+        if let Some(virtual_width) = self.virtual_width {
+            return virtual_width;
+        }
+        let emulation_mode = self.get_emulation_mode();
+        let offset = self
+            .crtc_controller_registers
+            .read(emulation_mode, CrtcControllerIndex::Offset);
+        let virtual_width = usize::from(offset) * 2;
+        self.virtual_width = Some(virtual_width);
+        virtual_width
     }
 
-    fn save_font_registers(&mut self) -> (u8, u8, u8, u8, u8) {
-        (
-            self.sequencer_registers.read(SequencerIndex::PlaneMask),
-            self.sequencer_registers.read(SequencerIndex::MemoryMode),
-            self.graphics_controller_registers
-                .read(GraphicsControllerIndex::ReadPlaneSelect),
-            self.graphics_controller_registers
-                .read(GraphicsControllerIndex::GraphicsMode),
-            self.graphics_controller_registers
-                .read(GraphicsControllerIndex::Miscellaneous),
-        )
+    /// Sets the distance between the start of two text rows in vga memory
+    /// to `columns` characters, which must be even and at most 510.
+    pub(crate) fn set_virtual_width(&mut self, columns: usize) {
+        let emulation_mode = self.get_emulation_mode();
+        self.crtc_controller_registers.write(
+            emulation_mode,
+            CrtcControllerIndex::Offset,
+            (columns / 2) as u8,
+        );
+        self.virtual_width = Some(columns);
     }
 
     fn set_registers(&mut self, configuration: &VgaConfiguration) {
         let emulation_mode = self.get_emulation_mode();
+        self.virtual_width = None;
 
         // Set miscellaneous output
         self.general_registers
@@ -255,6 +468,117 @@ impl Vga {
             .unblank_screen(emulation_mode);
     }
 
+    /// Reads the current values of the miscellaneous output, sequencer,
+    /// crtc, graphics and attribute registers.
+    pub fn read_display_registers(&mut self) -> DisplayRegisters {
+        let emulation_mode = self.get_emulation_mode();
+        let mut registers = DisplayRegisters {
+            miscellaneous_output: self.general_registers.read_msr(),
+            ..DisplayRegisters::default()
+        };
+        for (index, _) in ALL_REGISTERS.sequencer_registers {
+            registers.sequencer_registers[usize::from(u8::from(*index))] =
+                self.sequencer_registers.read(*index);
+        }
+        for (index, _) in ALL_REGISTERS.crtc_controller_registers {
+            registers.crtc_controller_registers[usize::from(u8::from(*index))] =
+                self.crtc_controller_registers.read(emulation_mode, *index);
+        }
+        for (index, _) in ALL_REGISTERS.graphics_controller_registers {
+            registers.graphics_controller_registers[usize::from(u8::from(*index))] =
+                self.graphics_controller_registers.read(*index);
+        }
+        for (index, _) in ALL_REGISTERS.attribute_controller_registers {
+            registers.attribute_controller_registers[usize::from(u8::from(*index))] = self
+                .attribute_controller_registers
+                .read(emulation_mode, *index);
+        }
+        // Reading the attribute controller clears the palette address source bit.
+        self.attribute_controller_registers
+            .unblank_screen(emulation_mode);
+        registers
+    }
+
+    /// Reads every register, along with the dac palette, into a
+    /// `RegisterDump`, which formats as a decoded report that can be
+    /// written to any `core::fmt::Write` sink, such as a serial port.
+    pub fn dump_registers(&mut self) -> RegisterDump {
+        let registers = self.read_display_registers();
+        let feature_control = self.general_registers.read_fcr();
+        let mut palette = [0; PALETTE_SIZE];
+        self.color_palette_registers.read_palette(&mut palette);
+        RegisterDump::new(registers, feature_control, &palette)
+    }
+
+    /// Detects the current video mode from the live register values, which
+    /// also works for modes set up by the bios before this crate was used.
+    ///
+    /// If the registers match a `VideoMode`, it's returned and becomes the
+    /// most recent video mode. Otherwise, `Error::UnknownVideoMode` is
+    /// returned with a description of the unknown mode.
+    pub fn detect_video_mode(&mut self) -> Result<VideoMode, Error> {
+        let info = self.read_display_registers().video_mode_info();
+        let video_mode = VideoMode::from_info(&info).ok_or(Error::UnknownVideoMode(info))?;
+        self.most_recent_video_mode = Some(video_mode);
+        Ok(video_mode)
+    }
+
+    /// Checks that the live register values match `video_mode`, returning
+    /// `Error::UnexpectedVideoMode` with a description of the actual mode
+    /// otherwise.
+    ///
+    /// Unlike `get_most_recent_video_mode`, this notices modes changed
+    /// behind the back of this `Vga`, such as by the bios, at the cost of
+    /// reading every display register.
+    pub fn check_video_mode(&mut self, video_mode: VideoMode) -> Result<(), Error> {
+        let info = self.read_display_registers().video_mode_info();
+        if VideoMode::from_info(&info) == Some(video_mode) {
+            Ok(())
+        } else {
+            Err(Error::UnexpectedVideoMode(info))
+        }
+    }
+
+    fn set_display_registers(&mut self, registers: &DisplayRegisters) {
+        self.virtual_width = None;
+        let emulation_mode =
+            EmulationMode::from_miscellaneous_output(registers.miscellaneous_output);
+
+        self.general_registers
+            .write_msr(registers.miscellaneous_output);
+        for (index, _) in ALL_REGISTERS.sequencer_registers {
+            self.sequencer_registers.write(
+                *index,
+                registers.sequencer_registers[usize::from(u8::from(*index))],
+            );
+        }
+        self.unlock_crtc_registers(emulation_mode);
+        for (index, _) in ALL_REGISTERS.crtc_controller_registers {
+            self.crtc_controller_registers.write(
+                emulation_mode,
+                *index,
+                registers.crtc_controller_registers[usize::from(u8::from(*index))],
+            );
+        }
+        for (index, _) in ALL_REGISTERS.graphics_controller_registers {
+            self.graphics_controller_registers.write(
+                *index,
+                registers.graphics_controller_registers[usize::from(u8::from(*index))],
+            );
+        }
+        self.attribute_controller_registers
+            .blank_screen(emulation_mode);
+        for (index, _) in ALL_REGISTERS.attribute_controller_registers {
+            self.attribute_controller_registers.write(
+                emulation_mode,
+                *index,
+                registers.attribute_controller_registers[usize::from(u8::from(*index))],
+            );
+        }
+        self.attribute_controller_registers
+            .unblank_screen(emulation_mode);
+    }
+
     /// Sets the video card to Mode 40x25.
     fn set_video_mode_40x25(&mut self) {
         self.set_registers(&MODE_40X25_CONFIGURATION);
@@ -305,22 +629,385 @@ impl Vga {
         // hardware. More information can be found here
         // https://01.org/sites/default/files/documentation/intel-gfx-prm-osrc-hsw-display.pdf
         // under `CR03 - Horizontal Blanking End Register`.
-        let horizontal_blanking_end = self
-            .crtc_controller_registers
-            .read(emulation_mode, CrtcControllerIndex::HorizontalBlankingEnd);
-        self.crtc_controller_registers.write(
-            emulation_mode,
-            CrtcControllerIndex::HorizontalBlankingEnd,
-            horizontal_blanking_end | 0x80,
+        self.crtc_controller_registers
+            .modify_horizontal_blanking_end(emulation_mode, |horizontal_blanking_end| {
+                horizontal_blanking_end | HorizontalBlankingEnd::ENABLE_VERTICAL_RETRACE_ACCESS
+            });
+        self.crtc_controller_registers
+            .modify_vertical_sync_end(emulation_mode, |vertical_sync_end| {
+                vertical_sync_end - VerticalSyncEnd::PROTECT_REGISTERS
+            });
+    }
+}
+
+impl<P: PortIo + MemoryIo> Vga<P> {
+    /// Sets the virtual address at which the legacy vga memory window
+    /// `0xA0000 -> 0xBFFFF` is mapped, for example when physical memory is
+    /// mapped at an offset. Defaults to `0xA0000`, which assumes the window
+    /// is identity mapped. The base is also passed on to the `MemoryIo`
+    /// backend, see `MemoryIo::set_frame_buffer_base`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use vga::emulator::VgaEmulator;
+    /// use vga::vga::{Vga, VideoMode};
+    ///
+    /// let physical_memory_offset = 0xFFFF_8000_0000_0000;
+    /// let emulator = VgaEmulator::new();
+    /// let mut vga = Vga::with_io(&emulator);
+    /// vga.set_frame_buffer_base(physical_memory_offset + 0xA0000);
+    /// vga.set_video_mode(VideoMode::Mode80x25);
+    ///
+    /// assert_eq!(vga.get_frame_buffer_address(), physical_memory_offset + 0xB8000);
+    /// ```
+    pub fn set_frame_buffer_base(&mut self, base: usize) {
+        self.frame_buffer_base = base;
+        self.io.set_frame_buffer_base(base);
+    }
+
+    /// Reads the byte at `address` of vga memory, loading the memory
+    /// read latches. `address` is absolute, so it already includes the
+    /// frame buffer base, as in `get_frame_buffer_address() + offset`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be inside of the vga memory window.
+    pub unsafe fn read_memory(&mut self, address: usize) -> u8 {
+        self.io.read_memory(address)
+    }
+
+    /// Writes `value` to `address` of vga memory. `address` is absolute,
+    /// as in `get_frame_buffer_address() + offset`.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be inside of the vga memory window.
+    pub unsafe fn write_memory(&mut self, address: usize, value: u8) {
+        self.io.write_memory(address, value);
+    }
+
+    /// Writes `value` to `count` consecutive bytes of vga memory,
+    /// starting at `address`. `address` is absolute, as in
+    /// `get_frame_buffer_address() + offset`.
+    ///
+    /// # Safety
+    ///
+    /// Every byte in the range must be inside of the vga memory window.
+    pub unsafe fn fill_memory(&mut self, address: usize, value: u8, count: usize) {
+        self.io.fill_memory(address, value, count);
+    }
+
+    /// Writes the bytes of `data` to consecutive bytes of vga memory,
+    /// starting at `address`. `address` is absolute, as in
+    /// `get_frame_buffer_address() + offset`.
+    ///
+    /// # Safety
+    ///
+    /// Every byte in the range must be inside of the vga memory window.
+    pub unsafe fn copy_to_memory(&mut self, address: usize, data: &[u8]) {
+        self.io.copy_to_memory(address, data);
+    }
+
+    /// Saves every sequencer, crtc, graphics, attribute and general register,
+    /// the dac palette and the contents of all four memory planes into
+    /// `state`, so the card can later be returned to it by `restore_state`.
+    ///
+    /// Reading the planes temporarily reprograms the memory mode, but the
+    /// registers are restored before this returns.
+    pub fn save_state(&mut self, state: &mut VgaState) {
+        state.registers = self.read_display_registers();
+        state.feature_control = self.general_registers.read_fcr();
+        self.color_palette_registers
+            .read_palette(&mut state.palette);
+        state.video_mode = self.most_recent_video_mode;
+
+        self.set_planar_memory_mode();
+        let frame_buffer = self.get_frame_buffer_address();
+        for (plane, data) in state.planes.iter_mut().enumerate() {
+            self.graphics_controller_registers
+                .write_read_plane(ReadPlane::try_from(plane as u8).unwrap());
+            for (offset, byte) in data.iter_mut().enumerate() {
+                *byte = unsafe { self.read_memory(frame_buffer + offset) };
+            }
+        }
+        self.set_display_registers(&state.registers);
+    }
+
+    /// Returns the card to a `state` saved by `save_state`, including the
+    /// contents of vga memory, such as the font in plane 2.
+    pub fn restore_state(&mut self, state: &VgaState) {
+        self.set_planar_memory_mode();
+        self.graphics_controller_registers
+            .write(GraphicsControllerIndex::SetReset, 0x00);
+        self.graphics_controller_registers
+            .write_enable_set_reset(0x00);
+        self.graphics_controller_registers
+            .write(GraphicsControllerIndex::DataRotate, 0x00);
+        self.graphics_controller_registers.set_bit_mask(0xFF);
+        let frame_buffer = self.get_frame_buffer_address();
+        for (plane, data) in state.planes.iter().enumerate() {
+            self.sequencer_registers
+                .set_plane_mask(PlaneMask::from_bits(1 << plane).unwrap());
+            unsafe {
+                self.copy_to_memory(frame_buffer, data);
+            }
+        }
+
+        self.set_display_registers(&state.registers);
+        let emulation_mode = self.get_emulation_mode();
+        self.general_registers
+            .write_fcr(emulation_mode, state.feature_control);
+        self.color_palette_registers.load_palette(&state.palette);
+        self.most_recent_video_mode = state.video_mode;
+    }
+
+    /// Maps the 64K of every plane linearly at 0xA0000, with write mode 0
+    /// and read mode 0, so planes can be read and written one at a time.
+    fn set_planar_memory_mode(&mut self) {
+        self.sequencer_registers
+            .write_memory_mode(MemoryMode::EXTENDED_MEMORY | MemoryMode::ODD_EVEN_DISABLE);
+        self.graphics_controller_registers
+            .write_graphics_mode(GraphicsMode::empty());
+        self.graphics_controller_registers
+            .write_miscellaneous(GraphicsMiscellaneous::GRAPHICS_MODE.with_memory_map(1));
+    }
+
+    /// Loads a vga text mode font as specified by `vga_font`.
+    pub fn load_font(&mut self, vga_font: &VgaFont) {
+        // Save registers
+        let (
+            plane_mask,
+            sequencer_memory_mode,
+            read_plane_select,
+            graphics_mode,
+            miscellaneous_graphics,
+        ) = self.save_font_registers();
+
+        // Switch to flat addressing
+        self.sequencer_registers
+            .modify_memory_mode(|memory_mode| memory_mode | MemoryMode::ODD_EVEN_DISABLE);
+
+        // Disable Even/Odd addressing
+        self.graphics_controller_registers
+            .modify_graphics_mode(|graphics_mode| graphics_mode - GraphicsMode::HOST_ODD_EVEN);
+        self.graphics_controller_registers
+            .modify_miscellaneous(|miscellaneous| {
+                miscellaneous - GraphicsMiscellaneous::CHAIN_ODD_EVEN
+            });
+
+        // Write font to plane
+        self.sequencer_registers.set_plane_mask(PlaneMask::PLANE2);
+
+        let frame_buffer = self.get_frame_buffer_address();
+
+        for character in 0..vga_font.characters {
+            for row in 0..vga_font.character_height {
+                let offset = (character * 32) + row;
+                let font_offset = (character * vga_font.character_height) + row;
+                unsafe {
+                    self.write_memory(
+                        frame_buffer + offset as usize,
+                        vga_font.font_data[font_offset as usize],
+                    );
+                }
+            }
+        }
+
+        self.restore_font_registers(
+            plane_mask,
+            sequencer_memory_mode,
+            read_plane_select,
+            graphics_mode,
+            miscellaneous_graphics,
         );
+    }
 
-        let vertical_sync_end = self
-            .crtc_controller_registers
-            .read(emulation_mode, CrtcControllerIndex::VerticalSyncEnd);
-        self.crtc_controller_registers.write(
+    fn restore_font_registers(
+        &mut self,
+        plane_mask: u8,
+        sequencer_memory_mode: u8,
+        read_plane_select: u8,
+        graphics_mode: u8,
+        miscellaneous_graphics: u8,
+    ) {
+        self.sequencer_registers
+            .write(SequencerIndex::PlaneMask, plane_mask);
+        self.sequencer_registers
+            .write(SequencerIndex::MemoryMode, sequencer_memory_mode);
+        self.graphics_controller_registers
+            .write(GraphicsControllerIndex::ReadPlaneSelect, read_plane_select);
+        self.graphics_controller_registers
+            .write(GraphicsControllerIndex::GraphicsMode, graphics_mode);
+        self.graphics_controller_registers.write(
+            GraphicsControllerIndex::Miscellaneous,
+            miscellaneous_graphics,
+        );
+    }
+
+    fn save_font_registers(&mut self) -> (u8, u8, u8, u8, u8) {
+        (
+            self.sequencer_registers.read(SequencerIndex::PlaneMask),
+            self.sequencer_registers.read(SequencerIndex::MemoryMode),
+            self.graphics_controller_registers
+                .read(GraphicsControllerIndex::ReadPlaneSelect),
+            self.graphics_controller_registers
+                .read(GraphicsControllerIndex::GraphicsMode),
+            self.graphics_controller_registers
+                .read(GraphicsControllerIndex::Miscellaneous),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        colors::{Color16, TextModeColor},
+        emulator::VgaEmulator,
+        writers::{Graphics640x480x16, GraphicsWriter, ScreenCharacter, Text80x25, TextWriter},
+    };
+
+    #[test]
+    fn test_detect_video_mode() {
+        let emulator = VgaEmulator::new();
+        for video_mode in [
+            VideoMode::Mode40x25,
+            VideoMode::Mode40x50,
+            VideoMode::Mode80x25,
+            VideoMode::Mode320x200x256,
+            VideoMode::Mode320x240x256,
+            VideoMode::Mode640x480x16,
+        ]
+        .iter()
+        {
+            Vga::with_io(&emulator).set_video_mode(*video_mode);
+            let mut vga = Vga::with_io(&emulator);
+            assert!(vga.get_most_recent_video_mode().is_none());
+            assert_eq!(vga.detect_video_mode(), Ok(*video_mode));
+            assert_eq!(vga.get_most_recent_video_mode(), Some(*video_mode));
+        }
+
+        // 640x350 with 16 colors, as used by ega mode 10h.
+        let mut vga = Vga::with_io(&emulator);
+        let emulation_mode = vga.get_emulation_mode();
+        vga.crtc_controller_registers.write(
             emulation_mode,
-            CrtcControllerIndex::VerticalSyncEnd,
-            vertical_sync_end & 0x7F,
+            CrtcControllerIndex::VerticalDisplayEnableEnd,
+            0x5D,
+        );
+        assert_eq!(
+            vga.detect_video_mode(),
+            Err(Error::UnknownVideoMode(VideoModeInfo {
+                graphics: true,
+                width: 640,
+                height: 350,
+                colors: 16,
+                chain_4: false,
+            }))
+        );
+        assert_eq!(
+            vga.check_video_mode(VideoMode::Mode640x480x16),
+            Err(Error::UnexpectedVideoMode(VideoModeInfo {
+                graphics: true,
+                width: 640,
+                height: 350,
+                colors: 16,
+                chain_4: false,
+            }))
         );
+        assert!(vga.get_most_recent_video_mode().is_none());
+    }
+
+    #[test]
+    fn test_frame_buffer() {
+        assert_eq!(
+            FrameBuffer::try_from(0x0),
+            Ok(FrameBuffer::ExtendedGraphicsMode)
+        );
+        assert_eq!(
+            FrameBuffer::try_from(0x4),
+            Err(Error::InvalidMemoryMap(0x4))
+        );
+
+        let emulator = VgaEmulator::new();
+        let mut vga = Vga::with_io(&emulator);
+        vga.graphics_controller_registers
+            .write(GraphicsControllerIndex::Miscellaneous, 0x01);
+        assert_eq!(vga.get_frame_buffer(), FrameBuffer::ExtendedGraphicsMode);
+        assert_eq!(vga.get_frame_buffer_address(), 0xA0000);
+    }
+
+    #[test]
+    fn test_split_screen() {
+        let emulator = VgaEmulator::new();
+        let mut vga = Vga::with_io(&emulator);
+        vga.set_video_mode(VideoMode::Mode640x480x16);
+        let emulation_mode = vga.get_emulation_mode();
+
+        assert_eq!(vga.set_split_screen(0x3FF), Ok(()));
+        assert_eq!(
+            vga.crtc_controller_registers
+                .read_line_compare(emulation_mode),
+            0x3FF
+        );
+        assert_eq!(vga.set_split_screen(0x200), Ok(()));
+        assert_eq!(vga.set_split_screen(0x400), Err(Error::OutOfRange(0x400)));
+        assert_eq!(
+            vga.crtc_controller_registers
+                .read_line_compare(emulation_mode),
+            0x200
+        );
+    }
+
+    #[test]
+    fn test_save_and_restore_state() {
+        static STATE: Spinlock<VgaState> = Spinlock::new(VgaState::new());
+
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text80x25::with_device(&vga);
+        text_mode.set_mode();
+        text_mode.clear_screen().unwrap();
+        text_mode
+            .write_character(
+                3,
+                2,
+                ScreenCharacter::new(b'A', TextModeColor::new(Color16::LightGrey, Color16::Blue)),
+            )
+            .unwrap();
+        text_mode.set_cursor_position(4, 2);
+        let registers = emulator.display_registers();
+
+        let mut state = STATE.lock();
+        vga.lock().save_state(&mut state);
+        assert_eq!(emulator.display_registers(), registers);
+        assert_eq!(state.registers(), &registers);
+        assert_eq!(state.plane(ReadPlane::Plane0)[2 * 80 + 3], b'A');
+
+        let graphics_mode = Graphics640x480x16::with_device(&vga);
+        graphics_mode.set_mode();
+        graphics_mode.clear_screen(Color16::Red);
+        vga.lock()
+            .color_palette_registers
+            .load_palette(&[0; PALETTE_SIZE]);
+
+        vga.lock().restore_state(&state);
+        assert_eq!(emulator.display_registers(), registers);
+        assert_eq!(
+            text_mode.read_character(3, 2).unwrap().get_character(),
+            b'A'
+        );
+        emulator.with_renderer(|renderer| {
+            let saved = state.renderer();
+            assert_eq!(renderer.dimensions(), (720, 400));
+            for y in 0..400 {
+                for x in 0..720 {
+                    assert_eq!(renderer.pixel(x, y), saved.pixel(x, y));
+                }
+            }
+        });
     }
 }
@@ -0,0 +1,477 @@
+use super::{GraphicsWriter, Present, Screen};
+use crate::drawing::{Bitmap, Bresenham, FontSource, Point};
+use core::marker::PhantomData;
+use spinning_top::Spinlock;
+
+/// The maximum number of dirty rectangles a `BackBuffer` keeps track of,
+/// after which new rectangles are merged into existing ones.
+pub const MAX_DIRTY_RECTS: usize = 16;
+
+/// A rectangle with an exclusive `right` and `bottom` edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl Rect {
+    const EMPTY: Rect = Rect {
+        left: 0,
+        top: 0,
+        right: 0,
+        bottom: 0,
+    };
+
+    fn union(self, other: Rect) -> Rect {
+        Rect {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    /// Returns whether `self` and `other` overlap or share an edge, in which
+    /// case their union doesn't cover any extra pixels along that edge.
+    fn touches(self, other: Rect) -> bool {
+        self.left <= other.right
+            && other.left <= self.right
+            && self.top <= other.bottom
+            && other.top <= self.bottom
+    }
+
+    fn position_and_size(self) -> (Point<usize>, Point<usize>) {
+        (
+            (self.left, self.top),
+            (self.right - self.left, self.bottom - self.top),
+        )
+    }
+
+    fn area(self) -> usize {
+        (self.right - self.left) * (self.bottom - self.top)
+    }
+}
+
+#[derive(Debug)]
+struct BackBufferState<'a, Color> {
+    pixels: &'a mut [Color],
+    dirty_rects: [Rect; MAX_DIRTY_RECTS],
+    dirty_len: usize,
+}
+
+impl<Color> BackBufferState<'_, Color> {
+    fn mark_dirty(&mut self, rect: Rect) {
+        let dirty_rects = &mut self.dirty_rects[..self.dirty_len];
+        if let Some(dirty_rect) = dirty_rects.iter_mut().find(|dirty| dirty.touches(rect)) {
+            *dirty_rect = dirty_rect.union(rect);
+        } else if self.dirty_len < MAX_DIRTY_RECTS {
+            self.dirty_rects[self.dirty_len] = rect;
+            self.dirty_len += 1;
+        } else if let Some(dirty_rect) = dirty_rects
+            .iter_mut()
+            .min_by_key(|dirty| dirty.union(rect).area() - dirty.area())
+        {
+            *dirty_rect = dirty_rect.union(rect);
+        }
+    }
+}
+
+/// An off-screen frame buffer in regular memory, with the dimensions of
+/// the `Screen` of the graphics writer `W`.
+///
+/// A `BackBuffer` implements `GraphicsWriter`, so every drawing function
+/// works on it, including the shapes in `vga::drawing`. Nothing is shown
+/// until `present` copies the rectangles that were drawn to since the last
+/// call to the screen, so a frame can be composed without it being seen
+/// while it's drawn.
+///
+/// The pixels are borrowed, so they can live in a `static` rather than on
+/// the stack, which matters for the 300K needed by `Graphics640x480x16`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```no_run
+/// use vga::writers::{BackBuffer, Graphics320x200x256, GraphicsWriter};
+///
+/// let mode = Graphics320x200x256::new();
+/// mode.set_mode();
+///
+/// let mut pixels = [0u8; 320 * 200];
+/// let back_buffer = BackBuffer::<Graphics320x200x256, _>::new(&mut pixels);
+/// for frame in 0..60 {
+///     back_buffer.clear_screen(0);
+///     back_buffer.draw_line((frame, 20), (frame + 100, 180), 255);
+///     back_buffer.present(&mode);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BackBuffer<'a, W, Color> {
+    state: Spinlock<BackBufferState<'a, Color>>,
+    font: FontSource,
+    writer: PhantomData<W>,
+}
+
+impl<W, Color> Screen for BackBuffer<'_, W, Color>
+where
+    W: Screen,
+{
+    const WIDTH: usize = W::WIDTH;
+    const HEIGHT: usize = W::HEIGHT;
+    const SIZE: usize = W::WIDTH * W::HEIGHT;
+}
+
+impl<'a, W, Color> BackBuffer<'a, W, Color>
+where
+    W: Screen,
+    Color: Copy,
+{
+    /// Creates a new `BackBuffer` that draws to `pixels`, which are stored
+    /// row by row. Nothing is dirty until it's drawn to, so `pixels` should
+    /// be cleared or match the contents of the screen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` holds less than `W::WIDTH * W::HEIGHT` pixels.
+    pub fn new(pixels: &'a mut [Color]) -> BackBuffer<'a, W, Color> {
+        assert!(
+            pixels.len() >= W::WIDTH * W::HEIGHT,
+            "BackBuffer pixels are too short for the screen!"
+        );
+        BackBuffer {
+            state: Spinlock::new(BackBufferState {
+                pixels,
+                dirty_rects: [Rect::EMPTY; MAX_DIRTY_RECTS],
+                dirty_len: 0,
+            }),
+            font: FontSource::Basic,
+            writer: PhantomData,
+        }
+    }
+
+    /// Returns the font `draw_character` draws glyphs from.
+    pub fn font(&self) -> FontSource {
+        self.font
+    }
+
+    /// Selects the font `draw_character` draws glyphs from, which defaults
+    /// to `FontSource::Basic`, like the graphics writers.
+    pub fn set_font(&mut self, font: FontSource) {
+        self.font = font;
+    }
+
+    /// Copies every dirty rectangle to the screen of `writer`, which has
+    /// to have the same dimensions as `W`, and marks it clean.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the screen of `writer` doesn't have the dimensions of `W`.
+    pub fn present<P: Present<Color>>(&self, writer: &P) {
+        assert!(
+            P::WIDTH == W::WIDTH && P::HEIGHT == W::HEIGHT,
+            "BackBuffer dimensions don't match the screen!"
+        );
+        let mut state = self.state.lock();
+        let mut rects = [((0, 0), (0, 0)); MAX_DIRTY_RECTS];
+        for (rect, dirty) in rects.iter_mut().zip(&state.dirty_rects[..state.dirty_len]) {
+            *rect = dirty.position_and_size();
+        }
+        writer.present(state.pixels, &rects[..state.dirty_len]);
+        state.dirty_len = 0;
+    }
+
+    /// Marks the whole screen dirty, so the next `present` copies all of it,
+    /// such as after the screen was changed by drawing to it directly.
+    pub fn invalidate(&self) {
+        self.mark_dirty(0, 0, W::WIDTH, W::HEIGHT);
+    }
+
+    /// Returns the dirty rectangles that the next `present` copies to the
+    /// screen, as their top left corner and `(width, height)`.
+    pub fn dirty_rects(&self) -> impl Iterator<Item = (Point<usize>, Point<usize>)> {
+        let state = self.state.lock();
+        DirtyRects {
+            rects: state.dirty_rects,
+            len: state.dirty_len,
+            next: 0,
+        }
+    }
+
+    /// Fills the rectangle with the corners `start` and `end` (inclusive)
+    /// with the specified `color`, clipped to the screen.
+    pub fn fill_rect(&self, start: Point<isize>, end: Point<isize>, color: Color) {
+        let left = start.0.min(end.0).max(0);
+        let right = start.0.max(end.0).min(W::WIDTH as isize - 1);
+        let top = start.1.min(end.1).max(0);
+        let bottom = start.1.max(end.1).min(W::HEIGHT as isize - 1);
+        if left > right || top > bottom {
+            return;
+        }
+
+        let (left, right) = (left as usize, right as usize + 1);
+        let (top, bottom) = (top as usize, bottom as usize + 1);
+        let mut state = self.state.lock();
+        for y in top..bottom {
+            for pixel in &mut state.pixels[y * W::WIDTH + left..y * W::WIDTH + right] {
+                *pixel = color;
+            }
+        }
+        state.mark_dirty(Rect {
+            left,
+            top,
+            right,
+            bottom,
+        });
+    }
+
+    /// Draws `bitmap` with its top left corner at `position`, clipped to the screen.
+    pub fn blit(&self, bitmap: &Bitmap<'_, Color>, position: Point<isize>)
+    where
+        Color: PartialEq,
+    {
+        let ((source_x, source_y), (x, y), (width, height)) =
+            match bitmap.clip(position, W::WIDTH, W::HEIGHT) {
+                Some(visible) => visible,
+                None => return,
+            };
+        let mut state = self.state.lock();
+        for row in 0..height {
+            let line = (y + row) * W::WIDTH + x;
+            for column in 0..width {
+                if let Some(color) = bitmap.get_pixel(source_x + column, source_y + row) {
+                    state.pixels[line + column] = color;
+                }
+            }
+        }
+        state.mark_dirty(Rect {
+            left: x,
+            top: y,
+            right: x + width,
+            bottom: y + height,
+        });
+    }
+
+    fn mark_dirty(&self, x: usize, y: usize, width: usize, height: usize) {
+        self.state.lock().mark_dirty(Rect {
+            left: x,
+            top: y,
+            right: x + width,
+            bottom: y + height,
+        });
+    }
+}
+
+impl<W, Color> GraphicsWriter<Color> for BackBuffer<'_, W, Color>
+where
+    W: Screen,
+    Color: Copy,
+{
+    fn clear_screen(&self, color: Color) {
+        self.fill_rect(
+            (0, 0),
+            (W::WIDTH as isize - 1, W::HEIGHT as isize - 1),
+            color,
+        );
+    }
+
+    fn draw_line(&self, start: Point<isize>, end: Point<isize>, color: Color) {
+        let max = (W::WIDTH as isize - 1, W::HEIGHT as isize - 1);
+        let mut state = self.state.lock();
+        for (x, y) in Bresenham::clipped(start, end, (0, 0), max) {
+            let (x, y) = (x as usize, y as usize);
+            state.pixels[y * W::WIDTH + x] = color;
+            state.mark_dirty(Rect {
+                left: x,
+                top: y,
+                right: x + 1,
+                bottom: y + 1,
+            });
+        }
+    }
+
+    fn draw_character(&self, x: usize, y: usize, character: char, color: Color) {
+        self.font.for_each_pixel(character, |column, row| {
+            self.set_pixel(x + column, y + row, color)
+        });
+    }
+
+    fn set_pixel(&self, x: usize, y: usize, color: Color) {
+        if x >= W::WIDTH || y >= W::HEIGHT {
+            return;
+        }
+        let mut state = self.state.lock();
+        state.pixels[y * W::WIDTH + x] = color;
+        state.mark_dirty(Rect {
+            left: x,
+            top: y,
+            right: x + 1,
+            bottom: y + 1,
+        });
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Color {
+        self.state.lock().pixels[y * W::WIDTH + x]
+    }
+
+    /// A `BackBuffer` has no video mode, so this does nothing. Set the
+    /// mode of the writer it's presented to instead.
+    fn set_mode(&self) {}
+
+    /// Returns the pixels of this `BackBuffer`.
+    fn get_frame_buffer(&self) -> *mut u8 {
+        self.state.lock().pixels.as_mut_ptr() as *mut u8
+    }
+}
+
+/// A fixed capacity list of dirty rectangles, returned by `BackBuffer::dirty_rects`.
+struct DirtyRects {
+    rects: [Rect; MAX_DIRTY_RECTS],
+    len: usize,
+    next: usize,
+}
+
+impl Iterator for DirtyRects {
+    type Item = (Point<usize>, Point<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next < self.len {
+            let rect = self.rects[self.next];
+            self.next += 1;
+            Some(rect.position_and_size())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        colors::Color16,
+        emulator::VgaEmulator,
+        vga::Vga,
+        writers::{Graphics320x200x256, Graphics320x240x256, Graphics640x480x16},
+    };
+
+    /// Draws to a back buffer of `screen`, and checks that `screen` only
+    /// changes once the back buffer is presented.
+    fn assert_presented<W, Color>(screen: &W, pixels: &mut [Color], colors: [Color; 4])
+    where
+        W: GraphicsWriter<Color> + Present<Color>,
+        Color: Copy + PartialEq + core::fmt::Debug,
+    {
+        let background = colors[0];
+        screen.set_mode();
+        screen.clear_screen(background);
+        for pixel in pixels.iter_mut() {
+            *pixel = background;
+        }
+
+        let back_buffer = BackBuffer::<W, Color>::new(pixels);
+        back_buffer.draw_line((5, 3), (40, 3), colors[1]);
+        back_buffer.draw_line((17, 10), (30, 27), colors[2]);
+        back_buffer.draw_character(101, 50, 'A', colors[3]);
+        back_buffer.set_pixel(W::WIDTH - 1, W::HEIGHT - 1, colors[2]);
+        assert_eq!(screen.get_pixel(5, 3), background);
+
+        back_buffer.present(screen);
+        assert_eq!(back_buffer.dirty_rects().count(), 0);
+        for y in 0..W::HEIGHT {
+            for x in 0..W::WIDTH {
+                assert_eq!(
+                    screen.get_pixel(x, y),
+                    back_buffer.get_pixel(x, y),
+                    "pixel ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_present() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+
+        let mut pixels = [0u8; 320 * 240];
+        let colors = [1, 255, 2, 40];
+        assert_presented(&Graphics320x200x256::with_device(&vga), &mut pixels, colors);
+        assert_presented(&Graphics320x240x256::with_device(&vga), &mut pixels, colors);
+
+        let mut pixels = [Color16::Black; 640 * 480];
+        let colors = [Color16::Blue, Color16::White, Color16::Red, Color16::Yellow];
+        assert_presented(&Graphics640x480x16::with_device(&vga), &mut pixels, colors);
+    }
+
+    /// Presents a short slice and a rectangle past the screen to `screen`,
+    /// which shouldn't panic.
+    fn assert_present_checks_bounds<W, Color>(screen: &W, pixels: &mut [Color], colors: [Color; 2])
+    where
+        W: GraphicsWriter<Color> + Present<Color>,
+        Color: Copy + PartialEq + core::fmt::Debug,
+    {
+        screen.set_mode();
+        screen.clear_screen(colors[0]);
+        for pixel in pixels.iter_mut() {
+            *pixel = colors[1];
+        }
+
+        let (right, bottom) = (W::WIDTH - 1, W::HEIGHT - 1);
+        screen.present(&pixels[..W::WIDTH], &[((0, 0), (W::WIDTH, W::HEIGHT))]);
+        assert_eq!(screen.get_pixel(0, 0), colors[0]);
+
+        let pixels = &pixels[..W::WIDTH * W::HEIGHT];
+        screen.present(pixels, &[((right - 7, bottom), (100, 100))]);
+        assert_eq!(screen.get_pixel(right, bottom), colors[1]);
+        assert_eq!(screen.get_pixel(right - 8, bottom), colors[0]);
+    }
+
+    #[test]
+    fn test_present_checks_bounds() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+
+        let mut pixels = [0u8; 320 * 240];
+        let colors = [1, 255];
+        assert_present_checks_bounds(&Graphics320x200x256::with_device(&vga), &mut pixels, colors);
+        assert_present_checks_bounds(&Graphics320x240x256::with_device(&vga), &mut pixels, colors);
+
+        let mut pixels = [Color16::Black; 640 * 480];
+        let colors = [Color16::Blue, Color16::White];
+        assert_present_checks_bounds(&Graphics640x480x16::with_device(&vga), &mut pixels, colors);
+    }
+
+    #[test]
+    fn test_dirty_rects() {
+        let mut pixels = [0u8; 320 * 200];
+        let back_buffer = BackBuffer::<Graphics320x200x256<()>, u8>::new(&mut pixels);
+        back_buffer.fill_rect((10, 10), (19, 19), 1);
+        back_buffer.fill_rect((20, 12), (29, 15), 1);
+        back_buffer.set_pixel(100, 100, 1);
+        back_buffer.set_pixel(400, 100, 1);
+        let mut dirty_rects = back_buffer.dirty_rects();
+        assert_eq!(dirty_rects.next(), Some(((10, 10), (20, 10))));
+        assert_eq!(dirty_rects.next(), Some(((100, 100), (1, 1))));
+        assert_eq!(dirty_rects.next(), None);
+
+        // Once the list is full, rectangles are merged into the one that
+        // grows the least.
+        for i in 0..MAX_DIRTY_RECTS {
+            back_buffer.set_pixel(i * 10, 150, 1);
+        }
+        assert_eq!(back_buffer.dirty_rects().count(), MAX_DIRTY_RECTS);
+        assert!(back_buffer
+            .dirty_rects()
+            .any(|rect| rect == ((130, 150), (21, 1))));
+
+        back_buffer.invalidate();
+        assert!(back_buffer
+            .dirty_rects()
+            .any(|rect| rect == ((0, 0), (320, 200))));
+    }
+}
@@ -1,10 +1,11 @@
-use super::{GraphicsWriter, Screen};
+use super::{DrawTarget, DrawingSession, GraphicsWriter, Present, Screen};
 use crate::{
     colors::DEFAULT_PALETTE,
-    drawing::{Bresenham, Point},
-    vga::{VideoMode, VGA},
+    drawing::{Bitmap, Bresenham, FontSource, Point, Transparency},
+    registers::RasterOp,
+    vga::{GlobalVga, VgaDevice, VideoMode},
+    Error,
 };
-use font8x8::UnicodeFonts;
 
 const WIDTH: usize = 320;
 const HEIGHT: usize = 200;
@@ -33,60 +34,265 @@ const SIZE: usize = WIDTH * HEIGHT;
 /// }
 /// ```
 #[derive(Debug, Clone, Copy, Default)]
-pub struct Graphics320x200x256;
+pub struct Graphics320x200x256<D = GlobalVga> {
+    device: D,
+    font: FontSource,
+}
 
-impl Screen for Graphics320x200x256 {
+impl<D> Screen for Graphics320x200x256<D> {
     const WIDTH: usize = WIDTH;
     const HEIGHT: usize = HEIGHT;
     const SIZE: usize = SIZE;
 }
 
-impl GraphicsWriter<u8> for Graphics320x200x256 {
+impl<D: VgaDevice> GraphicsWriter<u8> for Graphics320x200x256<D> {
     fn clear_screen(&self, color: u8) {
-        unsafe {
-            self.get_frame_buffer().write_bytes(color, Self::SIZE);
-        }
+        self.session().clear_screen(color);
     }
     fn draw_line(&self, start: Point<isize>, end: Point<isize>, color: u8) {
-        for (x, y) in Bresenham::new(start, end) {
-            self.set_pixel(x as usize, y as usize, color);
-        }
+        self.session().draw_line(start, end, color);
     }
     fn set_pixel(&self, x: usize, y: usize, color: u8) {
-        let offset = (y * WIDTH) + x;
-        unsafe {
-            self.get_frame_buffer().add(offset).write_volatile(color);
-        }
+        self.session().set_pixel(x, y, color);
     }
     fn draw_character(&self, x: usize, y: usize, character: char, color: u8) {
-        let character = match font8x8::BASIC_FONTS.get(character) {
-            Some(character) => character,
-            // Default to a filled block if the character isn't found
-            None => font8x8::unicode::BLOCK_UNICODE[8].byte_array(),
-        };
-
-        for (row, byte) in character.iter().enumerate() {
-            for bit in 0..8 {
-                match *byte & 1 << bit {
-                    0 => (),
-                    _ => self.set_pixel(x + bit, y + row, color),
-                }
-            }
-        }
+        self.session().draw_character(x, y, character, color);
+    }
+    fn get_pixel(&self, x: usize, y: usize) -> u8 {
+        self.session().get_pixel(x, y)
     }
     fn set_mode(&self) {
-        let mut vga = VGA.lock();
+        let mut vga = self.device.lock();
         vga.set_video_mode(VideoMode::Mode320x200x256);
 
         // Some bios mess up the palette when switching modes,
         // so explicitly set it.
         vga.color_palette_registers.load_palette(&DEFAULT_PALETTE);
     }
+    fn get_frame_buffer(&self) -> *mut u8 {
+        self.device.lock().get_frame_buffer_address() as *mut u8
+    }
+
+    fn with_draw_target(&self, f: &mut dyn FnMut(&mut dyn DrawTarget<u8>)) {
+        f(&mut self.session());
+    }
+}
+
+impl<D: VgaDevice> Present<u8> for Graphics320x200x256<D> {
+    fn present(&self, pixels: &[u8], rects: &[(Point<usize>, Point<usize>)]) {
+        if pixels.len() < SIZE {
+            return;
+        }
+        let mut session = self.session();
+        for &(position, size) in rects {
+            session.present_rect(pixels, position, size);
+        }
+    }
+}
+
+impl<D: VgaDevice> DrawTarget<u8> for DrawingSession<'_, Graphics320x200x256<D>, D::Io> {
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        self.set_pixel(x, y, color);
+    }
+
+    fn get_pixel(&mut self, x: usize, y: usize) -> u8 {
+        self.get_pixel(x, y)
+    }
+
+    fn draw_line(&mut self, start: Point<isize>, end: Point<isize>, color: u8) {
+        self.draw_line(start, end, color);
+    }
+
+    fn fill_rect(&mut self, start: Point<isize>, end: Point<isize>, color: u8) {
+        self.fill_rect(start, end, color);
+    }
 }
 
 impl Graphics320x200x256 {
     /// Creates a new `Graphics320x200x256`.
     pub const fn new() -> Graphics320x200x256 {
-        Graphics320x200x256
+        Graphics320x200x256 {
+            device: GlobalVga,
+            font: FontSource::Basic,
+        }
+    }
+}
+
+impl<D> Graphics320x200x256<D> {
+    /// Creates a new `Graphics320x200x256` that draws to `device`.
+    pub const fn with_device(device: D) -> Graphics320x200x256<D> {
+        Graphics320x200x256 {
+            device,
+            font: FontSource::Basic,
+        }
+    }
+
+    /// Returns the font `draw_character` draws glyphs from.
+    pub fn font(&self) -> FontSource {
+        self.font
+    }
+
+    /// Selects the font `draw_character` draws glyphs from, which defaults
+    /// to `FontSource::Basic`. With `FontSource::Vga`, characters look the
+    /// same as in a text mode using that font, including the box drawing
+    /// characters of code page 437, and are as tall as its glyphs.
+    pub fn set_font(&mut self, font: FontSource) {
+        self.font = font;
+    }
+}
+
+impl<D: VgaDevice> Graphics320x200x256<D> {
+    /// Starts a `DrawingSession` on this writer, which holds
+    /// the vga lock until it's dropped.
+    pub fn session(&self) -> DrawingSession<'_, Self, D::Io> {
+        DrawingSession::new(self, self.device.lock())
+    }
+
+    /// Starts a `DrawingSession` like `session`, after checking that the
+    /// card is actually in the video mode of this writer, see
+    /// `Vga::check_video_mode`.
+    pub fn try_session(&self) -> Result<DrawingSession<'_, Self, D::Io>, Error> {
+        let mut vga = self.device.lock();
+        vga.check_video_mode(VideoMode::Mode320x200x256)?;
+        Ok(DrawingSession::new(self, vga))
+    }
+
+    /// Draws `bitmap` with its top left corner at `position`, clipped to the screen.
+    pub fn blit(&self, bitmap: &Bitmap<'_, u8>, position: Point<isize>) {
+        self.session().blit(bitmap, position);
+    }
+
+    /// Fills the rectangle with the corners `start` and `end` (inclusive)
+    /// with the specified `color`, clipped to the screen.
+    pub fn fill_rect(&self, start: Point<isize>, end: Point<isize>, color: u8) {
+        self.session().fill_rect(start, end, color);
+    }
+}
+
+impl<D: VgaDevice> DrawingSession<'_, Graphics320x200x256<D>, D::Io> {
+    /// Clears the screen by setting all pixels to the specified `color`.
+    pub fn clear_screen(&mut self, color: u8) {
+        unsafe {
+            self.fill_memory(0, color, SIZE);
+        }
+    }
+
+    /// Fills the rectangle with the corners `start` and `end` (inclusive)
+    /// with the specified `color`, clipped to the screen.
+    pub fn fill_rect(&mut self, start: Point<isize>, end: Point<isize>, color: u8) {
+        let left = start.0.min(end.0).max(0);
+        let right = start.0.max(end.0).min(WIDTH as isize - 1);
+        let top = start.1.min(end.1).max(0);
+        let bottom = start.1.max(end.1).min(HEIGHT as isize - 1);
+        if left > right || top > bottom {
+            return;
+        }
+
+        let width = (right - left) as usize + 1;
+        for y in top as usize..=bottom as usize {
+            let offset = y * WIDTH + left as usize;
+            unsafe {
+                if self.raster_op() == RasterOp::Replace {
+                    self.fill_memory(offset, color, width);
+                } else {
+                    for column in 0..width {
+                        self.write_pixel(offset + column, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws a line from `start` to `end` with the specified `color`,
+    /// clipped to the screen.
+    pub fn draw_line(&mut self, start: Point<isize>, end: Point<isize>, color: u8) {
+        let max = (WIDTH as isize - 1, HEIGHT as isize - 1);
+        for (x, y) in Bresenham::clipped(start, end, (0, 0), max) {
+            self.set_pixel(x as usize, y as usize, color);
+        }
+    }
+
+    /// Draws a character at the given `(x, y)` coordinant to the specified `color`,
+    /// using the font of the writer, see `set_font`.
+    pub fn draw_character(&mut self, x: usize, y: usize, character: char, color: u8) {
+        let font = self.writer().font;
+        font.for_each_pixel(character, |column, row| {
+            self.set_pixel(x + column, y + row, color)
+        });
+    }
+
+    /// Sets the given pixel at `(x, y)` to the given `color`.
+    #[inline]
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        let offset = (y * WIDTH) + x;
+        unsafe {
+            self.write_pixel(offset, color);
+        }
+    }
+
+    /// Returns the color of the pixel at `(x, y)`.
+    #[inline]
+    pub fn get_pixel(&mut self, x: usize, y: usize) -> u8 {
+        let offset = (y * WIDTH) + x;
+        unsafe { self.read_memory(offset) }
+    }
+
+    /// Draws `bitmap` with its top left corner at `position`, clipped to
+    /// the screen. Rows of opaque bitmaps are copied to vga memory as a whole.
+    pub fn blit(&mut self, bitmap: &Bitmap<'_, u8>, position: Point<isize>) {
+        let ((source_x, source_y), (x, y), (width, height)) =
+            match bitmap.clip(position, WIDTH, HEIGHT) {
+                Some(visible) => visible,
+                None => return,
+            };
+        for row in 0..height {
+            let offset = (y + row) * WIDTH + x;
+            if bitmap.transparency() == Transparency::None && self.raster_op() == RasterOp::Replace
+            {
+                let pixels = &bitmap.row(source_y + row)[source_x..source_x + width];
+                unsafe {
+                    self.copy_to_memory(offset, pixels);
+                }
+                continue;
+            }
+            for column in 0..width {
+                if let Some(color) = bitmap.get_pixel(source_x + column, source_y + row) {
+                    unsafe {
+                        self.write_pixel(offset + column, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies the `(width, height)` pixels at `position` from `pixels`, which
+    /// hold a whole screen, to the same position on the screen, one row at a
+    /// time. The pixels are copied as they are, ignoring the raster op.
+    ///
+    /// The rectangle is clamped to the screen, and nothing is copied if
+    /// `pixels` holds less than a whole screen.
+    pub fn present_rect(&mut self, pixels: &[u8], position: Point<usize>, size: Point<usize>) {
+        if pixels.len() < SIZE {
+            return;
+        }
+        let (x, y) = position;
+        let width = size.0.min(WIDTH.saturating_sub(x));
+        let height = size.1.min(HEIGHT.saturating_sub(y));
+        for row in y..y + height {
+            let offset = row * WIDTH + x;
+            unsafe {
+                self.copy_to_memory(offset, &pixels[offset..offset + width]);
+            }
+        }
+    }
+
+    /// Writes `color` to the pixel at `offset`, combined with the pixel
+    /// already there as specified by the raster op.
+    unsafe fn write_pixel(&mut self, offset: usize, color: u8) {
+        let color = match self.raster_op() {
+            RasterOp::Replace => color,
+            raster_op => raster_op.apply(self.read_memory(offset), color),
+        };
+        self.write_memory(offset, color);
     }
 }
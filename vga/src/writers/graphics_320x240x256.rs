@@ -1,15 +1,20 @@
-use super::{GraphicsWriter, Screen};
+use super::{scroll_on_retrace, DrawTarget, DrawingSession, GraphicsWriter, Present, Screen};
 use crate::{
     colors::DEFAULT_PALETTE,
-    drawing::{Bresenham, Point},
-    registers::PlaneMask,
-    vga::{VideoMode, VGA},
+    drawing::{Bitmap, Bresenham, FontSource, Point},
+    io::{MemoryIo, PortIo},
+    registers::{PlaneMask, RasterOp, ReadPlane, WriteMode},
+    vga::{GlobalVga, Vga, VgaDevice, VideoMode},
+    Error,
 };
-use font8x8::UnicodeFonts;
+use core::convert::TryFrom;
 
 const WIDTH: usize = 320;
 const HEIGHT: usize = 240;
 const SIZE: usize = (WIDTH * HEIGHT) / 4;
+const WIDTH_IN_BYTES: usize = WIDTH / 4;
+const VRAM_HEIGHT: usize = 0x10000 / WIDTH_IN_BYTES;
+const PAGES: usize = 0x10000 / SIZE;
 
 /// A basic interface for interacting with vga graphics mode 320x200x256.
 ///
@@ -33,70 +38,807 @@ const SIZE: usize = (WIDTH * HEIGHT) / 4;
 ///     mode.draw_character(118 + offset * 8, 27, character, 255);
 /// }
 /// ```
+///
+/// Vga memory holds three pages of 320x240 pixels. The writer draws to
+/// the page selected by `set_draw_page`, while `show_page` selects the
+/// page that is displayed, which allows drawing the next frame while the
+/// current one is on screen:
+///
+/// ```no_run
+/// use vga::writers::{Graphics320x240x256, GraphicsWriter};
+///
+/// let mut mode = Graphics320x240x256::new();
+/// mode.set_mode();
+/// for frame in 0..60 {
+///     let page = frame % 2;
+///     mode.set_draw_page(page).unwrap();
+///     mode.clear_screen(0);
+///     mode.draw_line((frame as isize, 0), (frame as isize, 239), 255);
+///     mode.show_page_on_retrace(page).unwrap();
+/// }
+/// ```
 #[derive(Debug, Clone, Copy, Default)]
-pub struct Graphics320x240x256;
+pub struct Graphics320x240x256<D = GlobalVga> {
+    device: D,
+    draw_page: usize,
+    font: FontSource,
+}
 
-impl Screen for Graphics320x240x256 {
+impl<D> Screen for Graphics320x240x256<D> {
     const WIDTH: usize = WIDTH;
     const HEIGHT: usize = HEIGHT;
     const SIZE: usize = SIZE;
 }
 
-impl GraphicsWriter<u8> for Graphics320x240x256 {
+impl<D: VgaDevice> GraphicsWriter<u8> for Graphics320x240x256<D> {
     fn clear_screen(&self, color: u8) {
-        let frame_buffer = self.get_frame_buffer();
-        VGA.lock()
-            .sequencer_registers
-            .set_plane_mask(PlaneMask::ALL_PLANES);
-        unsafe {
-            frame_buffer.write_bytes(color, Self::SIZE);
-        }
+        self.session().clear_screen(color);
     }
     fn draw_line(&self, start: Point<isize>, end: Point<isize>, color: u8) {
-        for (x, y) in Bresenham::new(start, end) {
-            self.set_pixel(x as usize, y as usize, color);
-        }
+        self.session().draw_line(start, end, color);
     }
     fn set_pixel(&self, x: usize, y: usize, color: u8) {
-        let frame_buffer = self.get_frame_buffer();
-        unsafe {
-            let offset = (WIDTH * y + x) / 4;
-            let plane_mask = 0x1 << (x & 3);
-            VGA.lock()
-                .sequencer_registers
-                .set_plane_mask(PlaneMask::from_bits(plane_mask).unwrap());
-            frame_buffer.add(offset).write_volatile(color);
-        }
+        self.session().set_pixel(x, y, color);
     }
     fn draw_character(&self, x: usize, y: usize, character: char, color: u8) {
-        let character = match font8x8::BASIC_FONTS.get(character) {
-            Some(character) => character,
-            // Default to a filled block if the character isn't found
-            None => font8x8::unicode::BLOCK_UNICODE[8].byte_array(),
-        };
-
-        for (row, byte) in character.iter().enumerate() {
-            for bit in 0..8 {
-                match *byte & 1 << bit {
-                    0 => (),
-                    _ => self.set_pixel(x + bit, y + row, color),
-                }
-            }
-        }
+        self.session().draw_character(x, y, character, color);
+    }
+    fn get_pixel(&self, x: usize, y: usize) -> u8 {
+        self.session().get_pixel(x, y)
     }
     fn set_mode(&self) {
-        let mut vga = VGA.lock();
+        let mut vga = self.device.lock();
         vga.set_video_mode(VideoMode::Mode320x240x256);
 
         // Some bios mess up the palette when switching modes,
         // so explicitly set it.
         vga.color_palette_registers.load_palette(&DEFAULT_PALETTE);
     }
+    fn get_frame_buffer(&self) -> *mut u8 {
+        self.device.lock().get_frame_buffer_address() as *mut u8
+    }
+
+    fn with_draw_target(&self, f: &mut dyn FnMut(&mut dyn DrawTarget<u8>)) {
+        f(&mut self.session());
+    }
+}
+
+impl<D: VgaDevice> Present<u8> for Graphics320x240x256<D> {
+    fn present(&self, pixels: &[u8], rects: &[(Point<usize>, Point<usize>)]) {
+        if pixels.len() < WIDTH * HEIGHT {
+            return;
+        }
+        let mut session = self.session();
+        for &(position, size) in rects {
+            session.present_rect(pixels, position, size);
+        }
+    }
+}
+
+impl<D: VgaDevice> DrawTarget<u8> for DrawingSession<'_, Graphics320x240x256<D>, D::Io> {
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        self.set_pixel(x, y, color);
+    }
+
+    fn get_pixel(&mut self, x: usize, y: usize) -> u8 {
+        self.get_pixel(x, y)
+    }
+
+    fn draw_line(&mut self, start: Point<isize>, end: Point<isize>, color: u8) {
+        self.draw_line(start, end, color);
+    }
+
+    fn fill_rect(&mut self, start: Point<isize>, end: Point<isize>, color: u8) {
+        self.fill_rect(start, end, color);
+    }
 }
 
 impl Graphics320x240x256 {
+    /// The number of pages that fit in vga memory.
+    pub const PAGES: usize = PAGES;
+
+    /// The number of rows of the virtual canvas that fit in vga memory,
+    /// see `scroll_to`.
+    pub const CANVAS_HEIGHT: usize = VRAM_HEIGHT;
+
     /// Creates a new `Graphics320x240x256`.
     pub const fn new() -> Graphics320x240x256 {
-        Graphics320x240x256
+        Graphics320x240x256 {
+            device: GlobalVga,
+            draw_page: 0,
+            font: FontSource::Basic,
+        }
+    }
+}
+
+impl<D> Graphics320x240x256<D> {
+    /// Creates a new `Graphics320x240x256` that draws to `device`.
+    pub const fn with_device(device: D) -> Graphics320x240x256<D> {
+        Graphics320x240x256 {
+            device,
+            draw_page: 0,
+            font: FontSource::Basic,
+        }
+    }
+
+    /// Returns the font `draw_character` draws glyphs from.
+    pub fn font(&self) -> FontSource {
+        self.font
+    }
+
+    /// Selects the font `draw_character` draws glyphs from, which defaults
+    /// to `FontSource::Basic`. With `FontSource::Vga`, characters look the
+    /// same as in a text mode using that font, including the box drawing
+    /// characters of code page 437, and are as tall as its glyphs.
+    pub fn set_font(&mut self, font: FontSource) {
+        self.font = font;
+    }
+
+    /// Returns the page this writer draws to.
+    pub fn draw_page(&self) -> usize {
+        self.draw_page
+    }
+
+    /// Selects the page this writer draws to, which doesn't
+    /// have to be the page that is displayed.
+    ///
+    /// Returns `Error::OutOfRange` if `page` isn't less than
+    /// `Graphics320x240x256::PAGES`.
+    pub fn set_draw_page(&mut self, page: usize) -> Result<(), Error> {
+        if page >= PAGES {
+            return Err(Error::OutOfRange(page));
+        }
+        self.draw_page = page;
+        Ok(())
+    }
+}
+
+impl<D: VgaDevice> Graphics320x240x256<D> {
+    /// Starts a `DrawingSession` on this writer, which holds
+    /// the vga lock until it's dropped.
+    pub fn session(&self) -> DrawingSession<'_, Self, D::Io> {
+        DrawingSession::new(self, self.device.lock()).with_offset(self.draw_page * SIZE)
+    }
+
+    /// Starts a `DrawingSession` like `session`, after checking that the
+    /// card is actually in the video mode of this writer, see
+    /// `Vga::check_video_mode`.
+    pub fn try_session(&self) -> Result<DrawingSession<'_, Self, D::Io>, Error> {
+        let mut vga = self.device.lock();
+        vga.check_video_mode(VideoMode::Mode320x240x256)?;
+        Ok(DrawingSession::new(self, vga).with_offset(self.draw_page * SIZE))
+    }
+
+    /// Returns the page that is currently displayed.
+    pub fn displayed_page(&self) -> usize {
+        let mut vga = self.device.lock();
+        let emulation_mode = vga.get_emulation_mode();
+        let start_address = vga
+            .crtc_controller_registers
+            .read_start_address(emulation_mode);
+        usize::from(start_address) / SIZE
+    }
+
+    /// Displays `page` immediately, which may tear the current frame.
+    ///
+    /// Returns `Error::OutOfRange` if `page` isn't less than
+    /// `Graphics320x240x256::PAGES`.
+    pub fn show_page(&self, page: usize) -> Result<(), Error> {
+        if page >= PAGES {
+            return Err(Error::OutOfRange(page));
+        }
+        let mut vga = self.device.lock();
+        set_start_page(&mut vga, page);
+        Ok(())
+    }
+
+    /// Displays `page` from the start of the next frame, and waits for
+    /// vertical retrace so that the previously displayed page can safely
+    /// be drawn to once this returns.
+    ///
+    /// Returns `Error::OutOfRange` if `page` isn't less than
+    /// `Graphics320x240x256::PAGES`.
+    pub fn show_page_on_retrace(&self, page: usize) -> Result<(), Error> {
+        if page >= PAGES {
+            return Err(Error::OutOfRange(page));
+        }
+        let mut vga = self.device.lock();
+        let emulation_mode = vga.get_emulation_mode();
+
+        // The start address is latched at the start of vertical retrace,
+        // so it has to be written outside of it to not be missed.
+        vga.general_registers
+            .wait_for_display_enable(emulation_mode);
+        set_start_page(&mut vga, page);
+        vga.general_registers.wait_for_vsync(emulation_mode);
+        Ok(())
+    }
+
+    /// Scrolls the screen so that it shows row `y` of the virtual canvas
+    /// at its top, starting with the next frame. This waits for up to a
+    /// frame before returning.
+    ///
+    /// The virtual canvas is as wide as the screen and spans all of vga
+    /// memory, so row `y` of page 0 is also row `y - 240` of page 1, and
+    /// the canvas can be drawn to by selecting the matching draw page.
+    ///
+    /// Returns `Error::OutOfRange` if the screen would extend past the end
+    /// of the canvas.
+    pub fn scroll_to(&self, y: usize) -> Result<(), Error> {
+        if y + HEIGHT > VRAM_HEIGHT {
+            return Err(Error::OutOfRange(y));
+        }
+        let mut vga = self.device.lock();
+        scroll_on_retrace(&mut vga, (y * WIDTH_IN_BYTES) as u16, 0, 0);
+        Ok(())
+    }
+
+    /// Splits the screen so that rows from `y` onward show the start of
+    /// vga memory, which stays in place while the rows above it are
+    /// scrolled or flipped. See `Vga::set_split_screen` for details.
+    ///
+    /// The lower window shows the first rows of page 0, so a status bar
+    /// drawn at the top of page 0 appears at `y`. The upper window should
+    /// then be drawn to the other pages, or below the status bar.
+    ///
+    /// Returns `Error::OutOfRange` if `y` is 0 or not less than the screen
+    /// height.
+    pub fn set_split_screen(&self, y: usize) -> Result<(), Error> {
+        if y == 0 || y >= HEIGHT {
+            return Err(Error::OutOfRange(y));
+        }
+        // Every row is scanned twice.
+        self.device.lock().set_split_screen((y * 2 - 1) as u16)
+    }
+
+    /// Removes the split created by `set_split_screen`.
+    pub fn disable_split_screen(&self) {
+        self.device.lock().disable_split_screen();
+    }
+
+    /// Draws `bitmap` with its top left corner at `position`, clipped to the screen.
+    pub fn blit(&self, bitmap: &Bitmap<'_, u8>, position: Point<isize>) {
+        self.session().blit(bitmap, position);
+    }
+
+    /// Fills the rectangle with the corners `start` and `end` (inclusive)
+    /// with the specified `color`, clipped to the screen.
+    ///
+    /// Whole bytes are written to all four planes at once, so only the
+    /// bytes at the left and right edges of each row need to be masked.
+    pub fn fill_rect(&self, start: Point<isize>, end: Point<isize>, color: u8) {
+        self.session().fill_rect(start, end, color);
+    }
+
+    /// Clears the rectangle with the corners `start` and `end` (inclusive)
+    /// by setting all of its pixels to 0.
+    pub fn clear_rect(&self, start: Point<isize>, end: Point<isize>) {
+        self.session().clear_rect(start, end);
+    }
+
+    /// Copies the `(width, height)` pixels at `source` to `destination`
+    /// within vga memory. See `DrawingSession::copy_rect` for details.
+    pub fn copy_rect(&self, source: Point<usize>, destination: Point<usize>, size: Point<usize>) {
+        self.session().copy_rect(source, destination, size);
+    }
+}
+
+fn set_start_page<I: PortIo + MemoryIo>(vga: &mut Vga<I>, page: usize) {
+    let emulation_mode = vga.get_emulation_mode();
+    vga.crtc_controller_registers
+        .set_start_address(emulation_mode, (page * SIZE) as u16);
+}
+
+impl<D: VgaDevice> DrawingSession<'_, Graphics320x240x256<D>, D::Io> {
+    /// Clears the screen by setting all pixels to the specified `color`.
+    pub fn clear_screen(&mut self, color: u8) {
+        self.set_write_mode(WriteMode::Mode0);
+        self.set_plane_mask(PlaneMask::ALL_PLANES);
+        unsafe {
+            self.fill_memory(0, color, SIZE);
+        }
+    }
+
+    /// Fills the horizontal span from `start_x` to `end_x` (inclusive) on
+    /// row `y` with the specified `color`, clipped to the screen.
+    pub fn fill_span(&mut self, start_x: isize, end_x: isize, y: isize, color: u8) {
+        self.fill_rect((start_x, y), (end_x, y), color);
+    }
+
+    /// Fills the rectangle with the corners `start` and `end` (inclusive)
+    /// with the specified `color`, clipped to the screen.
+    ///
+    /// Four pixels are written per byte with every plane enabled, so the
+    /// plane mask only needs to be narrowed for the bytes at the left and
+    /// right edges. With a raster op other than `RasterOp::Replace`, every
+    /// pixel has to be read first, so the planes are filled one at a time.
+    pub fn fill_rect(&mut self, start: Point<isize>, end: Point<isize>, color: u8) {
+        let left = start.0.min(end.0).max(0);
+        let right = start.0.max(end.0).min(WIDTH as isize - 1);
+        let top = start.1.min(end.1).max(0);
+        let bottom = start.1.max(end.1).min(HEIGHT as isize - 1);
+        if left > right || top > bottom {
+            return;
+        }
+
+        let (left, right) = (left as usize, right as usize);
+        let (top, bottom) = (top as usize, bottom as usize);
+        self.set_write_mode(WriteMode::Mode0);
+        if self.raster_op() != RasterOp::Replace {
+            self.fill_planes(left, right, top, bottom, color);
+            return;
+        }
+
+        let (left_byte, right_byte) = (left / 4, right / 4);
+        let left_mask = (0xF << (left & 3)) & 0xF;
+        let right_mask = 0xF >> (3 - (right & 3));
+        if left_byte == right_byte {
+            self.fill_column(left_byte, top, bottom, left_mask & right_mask, color);
+            return;
+        }
+
+        let (mut first_byte, mut last_byte) = (left_byte, right_byte);
+        if left_mask != 0xF {
+            self.fill_column(left_byte, top, bottom, left_mask, color);
+            first_byte += 1;
+        }
+        if right_mask != 0xF {
+            self.fill_column(right_byte, top, bottom, right_mask, color);
+            last_byte -= 1;
+        }
+        if first_byte <= last_byte {
+            self.set_plane_mask(PlaneMask::ALL_PLANES);
+            for y in top..=bottom {
+                unsafe {
+                    self.fill_memory(
+                        y * WIDTH_IN_BYTES + first_byte,
+                        color,
+                        last_byte - first_byte + 1,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Clears the rectangle with the corners `start` and `end` (inclusive)
+    /// by setting all of its pixels to 0.
+    pub fn clear_rect(&mut self, start: Point<isize>, end: Point<isize>) {
+        self.fill_rect(start, end, 0);
+    }
+
+    /// Draws a line from `start` to `end` with the specified `color`,
+    /// clipped to the screen.
+    pub fn draw_line(&mut self, start: Point<isize>, end: Point<isize>, color: u8) {
+        if start.1 == end.1 {
+            self.fill_span(start.0, end.0, start.1, color);
+            return;
+        }
+        let max = (WIDTH as isize - 1, HEIGHT as isize - 1);
+        for (x, y) in Bresenham::clipped(start, end, (0, 0), max) {
+            self.set_pixel(x as usize, y as usize, color);
+        }
+    }
+
+    /// Draws a character at the given `(x, y)` coordinant to the specified `color`,
+    /// using the font of the writer, see `set_font`.
+    pub fn draw_character(&mut self, x: usize, y: usize, character: char, color: u8) {
+        let font = self.writer().font;
+        font.for_each_pixel(character, |column, row| {
+            self.set_pixel(x + column, y + row, color)
+        });
+    }
+
+    /// Sets the given pixel at `(x, y)` to the given `color`.
+    #[inline]
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        let offset = (WIDTH * y + x) / 4;
+        let plane_mask = 0x1 << (x & 3);
+        self.set_write_mode(WriteMode::Mode0);
+        self.set_plane_mask(PlaneMask::from_bits(plane_mask).unwrap());
+        unsafe {
+            self.write_pixel(offset, x & 3, color);
+        }
+    }
+
+    /// Returns the color of the pixel at `(x, y)`.
+    #[inline]
+    pub fn get_pixel(&mut self, x: usize, y: usize) -> u8 {
+        let offset = (WIDTH * y + x) / 4;
+        let read_plane = ReadPlane::try_from((x & 3) as u8).unwrap();
+        self.set_read_plane(read_plane);
+        unsafe { self.read_memory(offset) }
+    }
+
+    /// Draws `bitmap` with its top left corner at `position`, clipped to
+    /// the screen. Every plane is written in a single pass, so the plane
+    /// mask only changes four times.
+    pub fn blit(&mut self, bitmap: &Bitmap<'_, u8>, position: Point<isize>) {
+        let ((source_x, source_y), (x, y), (width, height)) =
+            match bitmap.clip(position, WIDTH, HEIGHT) {
+                Some(visible) => visible,
+                None => return,
+            };
+        self.set_write_mode(WriteMode::Mode0);
+        for plane in 0..4 {
+            // The first visible column that is stored in `plane`.
+            let first_column = (plane + 4 - (x & 3)) & 3;
+            if first_column >= width {
+                continue;
+            }
+            self.set_plane_mask(PlaneMask::from_bits(1 << plane).unwrap());
+            for row in 0..height {
+                let line = (y + row) * WIDTH + x;
+                for column in (first_column..width).step_by(4) {
+                    if let Some(color) = bitmap.get_pixel(source_x + column, source_y + row) {
+                        unsafe {
+                            self.write_pixel((line + column) / 4, plane, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies the `(width, height)` pixels at `source` to `destination`
+    /// within vga memory, handling overlapping rectangles.
+    ///
+    /// Rows past the bottom of the screen address the vga memory after the
+    /// draw page, up to the end of vga memory, which holds the other pages
+    /// and can be used to stash sprites off-screen. Rectangles are clipped
+    /// to that area.
+    ///
+    /// When `source` and `destination` share the same horizontal alignment
+    /// to 4 pixels, four pixels are copied per byte through the latches
+    /// using `WriteMode::Mode1`. Otherwise the pixels are copied one by one.
+    /// Either way, the pixels are copied as they are, ignoring the raster op.
+    pub fn copy_rect(
+        &mut self,
+        source: Point<usize>,
+        destination: Point<usize>,
+        size: Point<usize>,
+    ) {
+        let width = size
+            .0
+            .min(WIDTH.saturating_sub(source.0))
+            .min(WIDTH.saturating_sub(destination.0));
+        let vram_height = VRAM_HEIGHT - self.writer().draw_page * HEIGHT;
+        let height = size
+            .1
+            .min(vram_height.saturating_sub(source.1))
+            .min(vram_height.saturating_sub(destination.1));
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        // Copy backwards when the destination comes after the source,
+        // so overlapping rectangles aren't overwritten before being copied.
+        let source_offset = source.1 * WIDTH + source.0;
+        let destination_offset = destination.1 * WIDTH + destination.0;
+        let backwards = destination_offset > source_offset;
+        let rows = (0..height).map(|row| if backwards { height - 1 - row } else { row });
+
+        if source.0 & 3 != destination.0 & 3 {
+            self.set_write_mode(WriteMode::Mode0);
+            for row in rows {
+                for column in 0..width {
+                    let column = if backwards {
+                        width - 1 - column
+                    } else {
+                        column
+                    };
+                    let color = self.get_pixel(source.0 + column, source.1 + row);
+                    let (x, y) = (destination.0 + column, destination.1 + row);
+                    self.set_plane_mask(PlaneMask::from_bits(1 << (x & 3)).unwrap());
+                    unsafe {
+                        self.write_memory((WIDTH * y + x) / 4, color);
+                    }
+                }
+            }
+            return;
+        }
+
+        let first_byte = source.0 / 4;
+        let last_byte = (source.0 + width - 1) / 4;
+        let destination_byte = destination.0 / 4;
+        let first_mask = (0xF << (source.0 & 3)) & 0xF;
+        let last_mask = 0xF >> (3 - ((source.0 + width - 1) & 3));
+
+        self.set_write_mode(WriteMode::Mode1);
+        for row in rows {
+            let source_line = (source.1 + row) * WIDTH_IN_BYTES;
+            let destination_line = (destination.1 + row) * WIDTH_IN_BYTES;
+            for byte in 0..=last_byte - first_byte {
+                let byte = if backwards {
+                    last_byte - first_byte - byte
+                } else {
+                    byte
+                };
+                let mut plane_mask = 0xF;
+                if byte == 0 {
+                    plane_mask &= first_mask;
+                }
+                if byte == last_byte - first_byte {
+                    plane_mask &= last_mask;
+                }
+                self.set_plane_mask(PlaneMask::from_bits(plane_mask).unwrap());
+                unsafe {
+                    // Reading loads all four pixels into the latches,
+                    // which write mode 1 then writes back out.
+                    self.read_memory(source_line + first_byte + byte);
+                    self.write_memory(destination_line + destination_byte + byte, 0x00);
+                }
+            }
+        }
+        // Writes outside of this session expect the write mode of this video mode.
+        self.set_write_mode(WriteMode::Mode0);
+    }
+
+    /// Copies the `(width, height)` pixels at `position` from `pixels`, which
+    /// hold a whole screen, to the same position on the draw page.
+    ///
+    /// The rectangle is widened to whole bytes of four pixels, and every
+    /// plane is copied row by row in a single pass, so the plane mask only
+    /// changes four times. The pixels are copied as they are, ignoring the
+    /// raster op.
+    ///
+    /// The rectangle is clamped to the screen, and nothing is copied if
+    /// `pixels` holds less than a whole screen.
+    pub fn present_rect(&mut self, pixels: &[u8], position: Point<usize>, size: Point<usize>) {
+        if pixels.len() < WIDTH * HEIGHT {
+            return;
+        }
+        let (x, y) = position;
+        let width = size.0.min(WIDTH.saturating_sub(x));
+        let height = size.1.min(HEIGHT.saturating_sub(y));
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let (first_byte, last_byte) = (x / 4, (x + width - 1) / 4);
+        let bytes = last_byte - first_byte + 1;
+        let mut row_bytes = [0u8; WIDTH_IN_BYTES];
+        self.set_write_mode(WriteMode::Mode0);
+        for plane in 0..4 {
+            self.set_plane_mask(PlaneMask::from_bits(1 << plane).unwrap());
+            for row in y..y + height {
+                let line = row * WIDTH;
+                for (byte, value) in row_bytes[..bytes].iter_mut().enumerate() {
+                    *value = pixels[line + (first_byte + byte) * 4 + plane];
+                }
+                unsafe {
+                    self.copy_to_memory(row * WIDTH_IN_BYTES + first_byte, &row_bytes[..bytes]);
+                }
+            }
+        }
+    }
+
+    /// Writes `color` to the planes selected by `plane_mask` of the byte in
+    /// column `byte` of every row from `top` to `bottom` (inclusive).
+    fn fill_column(&mut self, byte: usize, top: usize, bottom: usize, plane_mask: u8, color: u8) {
+        self.set_plane_mask(PlaneMask::from_bits(plane_mask).unwrap());
+        for y in top..=bottom {
+            unsafe {
+                self.write_memory(y * WIDTH_IN_BYTES + byte, color);
+            }
+        }
+    }
+
+    /// Fills the rectangle from `(left, top)` to `(right, bottom)` (inclusive)
+    /// one plane at a time, combining every pixel with the raster op.
+    fn fill_planes(&mut self, left: usize, right: usize, top: usize, bottom: usize, color: u8) {
+        for plane in 0..4 {
+            // The first column of the rectangle that is stored in `plane`.
+            let first_column = left + ((plane + 4 - (left & 3)) & 3);
+            if first_column > right {
+                continue;
+            }
+            self.set_plane_mask(PlaneMask::from_bits(1 << plane).unwrap());
+            for y in top..=bottom {
+                for x in (first_column..=right).step_by(4) {
+                    unsafe {
+                        self.write_pixel((WIDTH * y + x) / 4, plane, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes `color` to the byte at `offset` of the planes selected by the
+    /// plane mask, combined with the pixel already in `plane` as specified
+    /// by the raster op.
+    unsafe fn write_pixel(&mut self, offset: usize, plane: usize, color: u8) {
+        let color = match self.raster_op() {
+            RasterOp::Replace => color,
+            raster_op => {
+                self.set_read_plane(ReadPlane::try_from(plane as u8).unwrap());
+                raster_op.apply(self.read_memory(offset), color)
+            }
+        };
+        self.write_memory(offset, color);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{emulator::VgaEmulator, registers::GraphicsControllerIndex};
+    use spinning_top::Spinlock;
+
+    fn pattern(x: usize, y: usize) -> u8 {
+        (x * 7 + y * 13) as u8
+    }
+
+    fn assert_copied(source: Point<usize>, destination: Point<usize>, size: Point<usize>) {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        let mut session = mode.session();
+        for y in 0..64 {
+            for x in 0..WIDTH {
+                session.set_pixel(x, y, pattern(x, y));
+            }
+        }
+        session.copy_rect(source, destination, size);
+        drop(session);
+        let graphics_mode =
+            emulator.graphics_controller_register(GraphicsControllerIndex::GraphicsMode);
+        assert_eq!(graphics_mode & 0x3, 0x0);
+
+        let mut session = mode.session();
+        for y in 0..64 {
+            for x in 0..WIDTH {
+                let inside = x >= destination.0
+                    && x < destination.0 + size.0
+                    && y >= destination.1
+                    && y < destination.1 + size.1;
+                let expected = if inside {
+                    pattern(x - destination.0 + source.0, y - destination.1 + source.1)
+                } else {
+                    pattern(x, y)
+                };
+                assert_eq!(session.get_pixel(x, y), expected, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_rect() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(1);
+
+        let rects = [
+            ((1, 1), (2, 2)),
+            ((5, 4), (30, 6)),
+            ((8, 8), (15, 8)),
+            ((20, 9), (20, 11)),
+            ((-10, 12), (9, 14)),
+            ((310, 230), (400, 300)),
+        ];
+        for &(start, end) in &rects {
+            mode.fill_rect(start, end, 0x42);
+        }
+        mode.clear_rect((2, 2), (2, 2));
+        mode.draw_line((50, 20), (57, 20), 0x43);
+
+        let mut session = mode.session();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let inside = |&((left, top), (right, bottom)): &(Point<isize>, Point<isize>)| {
+                    let (x, y) = (x as isize, y as isize);
+                    x >= left && x <= right && y >= top && y <= bottom
+                };
+                let expected = if (x, y) == (2, 2) {
+                    0
+                } else if y == 20 && (50..=57).contains(&x) {
+                    0x43
+                } else if rects.iter().any(inside) {
+                    0x42
+                } else {
+                    1
+                };
+                assert_eq!(session.get_pixel(x, y), expected, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_copy_rect_latched() {
+        assert_copied((8, 0), (100, 30), (16, 10));
+        assert_copied((9, 1), (101, 30), (14, 10));
+        assert_copied((10, 2), (2, 3), (1, 10));
+        // Overlapping copies, in both directions.
+        assert_copied((5, 5), (9, 7), (40, 20));
+        assert_copied((9, 7), (5, 5), (40, 20));
+    }
+
+    #[test]
+    fn test_copy_rect_unaligned() {
+        assert_copied((8, 0), (101, 30), (16, 10));
+        assert_copied((5, 5), (6, 6), (40, 20));
+        assert_copied((6, 6), (5, 5), (40, 20));
+    }
+
+    #[test]
+    fn test_copy_rect_off_screen() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(0);
+        mode.set_pixel(4, 4, 0x42);
+
+        mode.copy_rect((0, 0), (0, 800), (16, 16));
+        mode.clear_screen(0);
+        mode.copy_rect((0, 800), (100, 100), (16, 16));
+        assert_eq!(mode.get_pixel(104, 104), 0x42);
+
+        // Rectangles are clipped to the end of vga memory.
+        mode.copy_rect((0, 100), (0, VRAM_HEIGHT - 1), (WIDTH, 10));
+    }
+
+    #[test]
+    fn test_page_flipping() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mut mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        for page in 0..Graphics320x240x256::PAGES {
+            mode.set_draw_page(page).unwrap();
+            mode.clear_screen(page as u8 + 1);
+        }
+        mode.set_draw_page(1).unwrap();
+        mode.set_pixel(10, 20, 0x42);
+        let pages = Graphics320x240x256::PAGES;
+        assert_eq!(mode.set_draw_page(pages), Err(Error::OutOfRange(pages)));
+        assert_eq!(mode.show_page(pages), Err(Error::OutOfRange(pages)));
+        assert_eq!(
+            mode.show_page_on_retrace(pages),
+            Err(Error::OutOfRange(pages))
+        );
+        assert_eq!(mode.draw_page(), 1);
+
+        assert_eq!(mode.displayed_page(), 0);
+        assert_eq!(emulator.read_pixel(10, 20), 1);
+        mode.show_page_on_retrace(1).unwrap();
+        assert_eq!(mode.displayed_page(), 1);
+        assert_eq!(emulator.read_pixel(10, 20), 0x42);
+        assert_eq!(emulator.read_pixel(319, 239), 2);
+        mode.show_page(2).unwrap();
+        assert_eq!(emulator.read_pixel(10, 20), 3);
+
+        // Each page only sees its own pixels.
+        mode.set_draw_page(0).unwrap();
+        assert_eq!(mode.get_pixel(10, 20), 1);
+        mode.set_draw_page(1).unwrap();
+        assert_eq!(mode.get_pixel(10, 20), 0x42);
+    }
+
+    #[test]
+    fn test_split_screen() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mut mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(1);
+        mode.set_draw_page(1).unwrap();
+        mode.clear_screen(2);
+        mode.show_page(1).unwrap();
+
+        mode.set_split_screen(200).unwrap();
+        emulator.with_renderer(|renderer| {
+            let (blue, green) = ([0x00, 0x00, 0xAA], [0x00, 0xAA, 0x00]);
+            assert_eq!(renderer.pixel(0, 199), green);
+            assert_eq!(renderer.pixel(0, 200), blue);
+            assert_eq!(renderer.pixel(319, 239), blue);
+        });
+
+        mode.disable_split_screen();
+        emulator.with_renderer(|renderer| {
+            assert_eq!(renderer.pixel(0, 200), renderer.pixel(0, 0));
+        });
     }
 }
@@ -1,16 +1,18 @@
-use super::{GraphicsWriter, Screen};
+use super::{scroll_on_retrace, DrawTarget, DrawingSession, GraphicsWriter, Present, Screen};
 use crate::{
     colors::{Color16, DEFAULT_PALETTE},
-    drawing::{Bresenham, Point},
-    registers::{PlaneMask, WriteMode},
-    vga::{VideoMode, VGA},
+    drawing::{Bitmap, Bresenham, FontSource, Point},
+    registers::{PlaneMask, RasterOp, ReadPlane, WriteMode},
+    vga::{GlobalVga, VgaDevice, VideoMode},
+    Error,
 };
-use font8x8::UnicodeFonts;
+use core::convert::TryFrom;
 
 const WIDTH: usize = 640;
 const HEIGHT: usize = 480;
 const SIZE: usize = (WIDTH * HEIGHT) / 8;
 const WIDTH_IN_BYTES: usize = WIDTH / 8;
+const CANVAS_HEIGHT: usize = 0x10000 / WIDTH_IN_BYTES;
 
 /// A basic interface for interacting with vga graphics mode 640x480x16
 ///
@@ -21,7 +23,7 @@ const WIDTH_IN_BYTES: usize = WIDTH / 8;
 /// ```no_run
 /// use vga::colors::Color16;
 /// use vga::writers::{Graphics640x480x16, GraphicsWriter};
-
+///
 /// let mode = Graphics640x480x16::new();
 /// mode.set_mode();
 /// mode.clear_screen(Color16::Black);
@@ -35,102 +37,471 @@ const WIDTH_IN_BYTES: usize = WIDTH / 8;
 /// }
 /// ```
 #[derive(Debug, Clone, Copy, Default)]
-pub struct Graphics640x480x16;
+pub struct Graphics640x480x16<D = GlobalVga> {
+    device: D,
+    font: FontSource,
+}
 
-impl Screen for Graphics640x480x16 {
+impl<D> Screen for Graphics640x480x16<D> {
     const WIDTH: usize = WIDTH;
     const HEIGHT: usize = HEIGHT;
     const SIZE: usize = SIZE;
 }
 
-impl GraphicsWriter<Color16> for Graphics640x480x16 {
+impl<D: VgaDevice> GraphicsWriter<Color16> for Graphics640x480x16<D> {
     fn clear_screen(&self, color: Color16) {
-        self.set_write_mode_2();
-        unsafe {
-            self.get_frame_buffer()
-                .write_bytes(u8::from(color), Self::SIZE);
-        }
+        self.session().clear_screen(color);
     }
 
     fn draw_line(&self, start: Point<isize>, end: Point<isize>, color: Color16) {
-        self.set_write_mode_0(color);
-        for (x, y) in Bresenham::new(start, end) {
-            self._set_pixel(x as usize, y as usize, color);
-        }
+        self.session().draw_line(start, end, color);
     }
 
     fn draw_character(&self, x: usize, y: usize, character: char, color: Color16) {
-        self.set_write_mode_2();
-        let character = match font8x8::BASIC_FONTS.get(character) {
-            Some(character) => character,
-            // Default to a filled block if the character isn't found
-            None => font8x8::unicode::BLOCK_UNICODE[8].byte_array(),
-        };
-
-        for (row, byte) in character.iter().enumerate() {
-            for bit in 0..8 {
-                match *byte & 1 << bit {
-                    0 => (),
-                    _ => self._set_pixel(x + bit, y + row, color),
-                }
-            }
-        }
+        self.session().draw_character(x, y, character, color);
     }
 
     /// **Note:** This method is provided for convenience, but has terrible
-    /// performance since it needs to ensure the correct `WriteMode` per pixel
-    /// drawn. If you need to draw more then one pixel, consider using a method
-    /// such as `draw_line`.
+    /// performance since it needs to lock the vga and ensure the correct
+    /// `WriteMode` per pixel drawn. If you need to draw more then one pixel,
+    /// consider using a `DrawingSession` from `session`.
     fn set_pixel(&self, x: usize, y: usize, color: Color16) {
-        self.set_write_mode_2();
-        self._set_pixel(x, y, color);
+        self.session().set_pixel(x, y, color);
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Color16 {
+        self.session().get_pixel(x, y)
     }
 
     fn set_mode(&self) {
-        let mut vga = VGA.lock();
+        let mut vga = self.device.lock();
         vga.set_video_mode(VideoMode::Mode640x480x16);
 
         // Some bios mess up the palette when switching modes,
         // so explicitly set it.
         vga.color_palette_registers.load_palette(&DEFAULT_PALETTE);
     }
+    fn get_frame_buffer(&self) -> *mut u8 {
+        self.device.lock().get_frame_buffer_address() as *mut u8
+    }
+
+    fn with_draw_target(&self, f: &mut dyn FnMut(&mut dyn DrawTarget<Color16>)) {
+        f(&mut self.session());
+    }
+}
+
+impl<D: VgaDevice> Present<Color16> for Graphics640x480x16<D> {
+    fn present(&self, pixels: &[Color16], rects: &[(Point<usize>, Point<usize>)]) {
+        if pixels.len() < WIDTH * HEIGHT {
+            return;
+        }
+        let mut session = self.session();
+        for &(position, size) in rects {
+            session.present_rect(pixels, position, size);
+        }
+    }
+}
+
+impl<D: VgaDevice> DrawTarget<Color16> for DrawingSession<'_, Graphics640x480x16<D>, D::Io> {
+    fn set_pixel(&mut self, x: usize, y: usize, color: Color16) {
+        self.set_pixel(x, y, color);
+    }
+
+    fn get_pixel(&mut self, x: usize, y: usize) -> Color16 {
+        self.get_pixel(x, y)
+    }
+
+    fn draw_line(&mut self, start: Point<isize>, end: Point<isize>, color: Color16) {
+        self.draw_line(start, end, color);
+    }
+
+    fn fill_rect(&mut self, start: Point<isize>, end: Point<isize>, color: Color16) {
+        self.fill_rect(start, end, color);
+    }
 }
 
 impl Graphics640x480x16 {
+    /// The number of rows of the virtual canvas that fit in vga memory,
+    /// see `scroll_to`.
+    pub const CANVAS_HEIGHT: usize = CANVAS_HEIGHT;
+
     /// Creates a new `Graphics640x480x16`.
     pub const fn new() -> Graphics640x480x16 {
-        Graphics640x480x16
+        Graphics640x480x16 {
+            device: GlobalVga,
+            font: FontSource::Basic,
+        }
+    }
+}
+
+impl<D> Graphics640x480x16<D> {
+    /// Creates a new `Graphics640x480x16` that draws to `device`.
+    pub const fn with_device(device: D) -> Graphics640x480x16<D> {
+        Graphics640x480x16 {
+            device,
+            font: FontSource::Basic,
+        }
+    }
+
+    /// Returns the font `draw_character` draws glyphs from.
+    pub fn font(&self) -> FontSource {
+        self.font
+    }
+
+    /// Selects the font `draw_character` draws glyphs from, which defaults
+    /// to `FontSource::Basic`. With `FontSource::Vga`, characters look the
+    /// same as in a text mode using that font, including the box drawing
+    /// characters of code page 437, and are as tall as its glyphs.
+    pub fn set_font(&mut self, font: FontSource) {
+        self.font = font;
+    }
+}
+
+impl<D: VgaDevice> Graphics640x480x16<D> {
+    /// Starts a `DrawingSession` on this writer, which holds
+    /// the vga lock until it's dropped.
+    pub fn session(&self) -> DrawingSession<'_, Self, D::Io> {
+        DrawingSession::new(self, self.device.lock())
+    }
+
+    /// Starts a `DrawingSession` like `session`, after checking that the
+    /// card is actually in the video mode of this writer, see
+    /// `Vga::check_video_mode`.
+    pub fn try_session(&self) -> Result<DrawingSession<'_, Self, D::Io>, Error> {
+        let mut vga = self.device.lock();
+        vga.check_video_mode(VideoMode::Mode640x480x16)?;
+        Ok(DrawingSession::new(self, vga))
+    }
+
+    /// Draws `bitmap` with its top left corner at `position`, clipped to the screen.
+    pub fn blit(&self, bitmap: &Bitmap<'_, Color16>, position: Point<isize>) {
+        self.session().blit(bitmap, position);
+    }
+
+    /// Fills the rectangle with the corners `start` and `end` (inclusive)
+    /// with the specified `color`, clipped to the screen.
+    ///
+    /// Whole bytes are written using set/reset, so only the partial bytes
+    /// at the left and right edges of each row need to be masked.
+    pub fn fill_rect(&self, start: Point<isize>, end: Point<isize>, color: Color16) {
+        self.session().fill_rect(start, end, color);
+    }
+
+    /// Clears the rectangle with the corners `start` and `end` (inclusive)
+    /// by setting all of its pixels to `Color16::Black`.
+    pub fn clear_rect(&self, start: Point<isize>, end: Point<isize>) {
+        self.session().clear_rect(start, end);
+    }
+
+    /// Scrolls the screen so that it shows row `y` of the virtual canvas
+    /// at its top, starting with the next frame. This waits for up to a
+    /// frame before returning.
+    ///
+    /// The virtual canvas is as wide as the screen, and continues past its
+    /// bottom for as many rows as fit in vga memory. Rows below the screen
+    /// can be drawn to with `set_pixel`.
+    ///
+    /// Returns `Error::OutOfRange` if the screen would extend past the end
+    /// of the canvas.
+    pub fn scroll_to(&self, y: usize) -> Result<(), Error> {
+        if y + HEIGHT > CANVAS_HEIGHT {
+            return Err(Error::OutOfRange(y));
+        }
+        let mut vga = self.device.lock();
+        scroll_on_retrace(&mut vga, (y * WIDTH_IN_BYTES) as u16, 0, 0);
+        Ok(())
+    }
+}
+
+impl<D: VgaDevice> DrawingSession<'_, Graphics640x480x16<D>, D::Io> {
+    /// Clears the screen by setting all pixels to the specified `color`.
+    pub fn clear_screen(&mut self, color: Color16) {
+        self.select_function(RasterOp::Replace);
+        self.set_write_mode(WriteMode::Mode2);
+        self.set_plane_mask(PlaneMask::ALL_PLANES);
+        self.set_bit_mask(0xFF);
+        unsafe {
+            self.fill_memory(0, u8::from(color), SIZE);
+        }
     }
 
-    fn set_write_mode_0(self, color: Color16) {
-        let mut vga = VGA.lock();
-        vga.graphics_controller_registers.write_set_reset(color);
-        vga.graphics_controller_registers
-            .write_enable_set_reset(0xF);
-        vga.graphics_controller_registers
-            .set_write_mode(WriteMode::Mode0);
+    /// Draws a line from `start` to `end` with the specified `color`,
+    /// clipped to the screen.
+    pub fn draw_line(&mut self, start: Point<isize>, end: Point<isize>, color: Color16) {
+        if start.1 == end.1 {
+            self.fill_span(start.0, end.0, start.1, color);
+            return;
+        }
+        let max = (WIDTH as isize - 1, HEIGHT as isize - 1);
+        for (x, y) in Bresenham::clipped(start, end, (0, 0), max) {
+            self.set_pixel(x as usize, y as usize, color);
+        }
+    }
+
+    /// Fills the horizontal span from `start_x` to `end_x` (inclusive) on
+    /// row `y` with the specified `color`, clipped to the screen.
+    pub fn fill_span(&mut self, start_x: isize, end_x: isize, y: isize, color: Color16) {
+        self.fill_rect((start_x, y), (end_x, y), color);
     }
 
-    fn set_write_mode_2(self) {
-        let mut vga = VGA.lock();
-        vga.graphics_controller_registers
-            .set_write_mode(WriteMode::Mode2);
-        vga.graphics_controller_registers.set_bit_mask(0xFF);
-        vga.sequencer_registers
-            .set_plane_mask(PlaneMask::ALL_PLANES);
+    /// Fills the rectangle with the corners `start` and `end` (inclusive)
+    /// with the specified `color`, clipped to the screen.
+    pub fn fill_rect(&mut self, start: Point<isize>, end: Point<isize>, color: Color16) {
+        let left = start.0.min(end.0).max(0);
+        let right = start.0.max(end.0).min(WIDTH as isize - 1);
+        let top = start.1.min(end.1).max(0);
+        let bottom = start.1.max(end.1).min(HEIGHT as isize - 1);
+        if left > right || top > bottom {
+            return;
+        }
+
+        self.select_function(self.raster_op());
+        self.set_write_mode(WriteMode::Mode0);
+        self.set_plane_mask(PlaneMask::ALL_PLANES);
+        self.set_set_reset(color);
+        self.set_enable_set_reset(0xF);
+        for y in top as usize..=bottom as usize {
+            self.fill_row(left as usize, right as usize, y);
+        }
     }
 
+    /// Clears the rectangle with the corners `start` and `end` (inclusive)
+    /// by setting all of its pixels to `Color16::Black`.
+    pub fn clear_rect(&mut self, start: Point<isize>, end: Point<isize>) {
+        self.fill_rect(start, end, Color16::Black);
+    }
+
+    /// Draws a character at the given `(x, y)` coordinant to the specified `color`,
+    /// using the font of the writer, see `set_font`.
+    pub fn draw_character(&mut self, x: usize, y: usize, character: char, color: Color16) {
+        let font = self.writer().font;
+        font.for_each_pixel(character, |column, row| {
+            self.set_pixel(x + column, y + row, color)
+        });
+    }
+
+    /// Sets the given pixel at `(x, y)` to the given `color`.
     #[inline]
-    fn _set_pixel(self, x: usize, y: usize, color: Color16) {
-        let frame_buffer = self.get_frame_buffer();
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color16) {
+        self.select_function(self.raster_op());
+        self.set_write_mode(WriteMode::Mode2);
+        self.set_plane_mask(PlaneMask::ALL_PLANES);
         let offset = x / 8 + y * WIDTH_IN_BYTES;
         let pixel_mask = 0x80 >> (x & 0x07);
-        VGA.lock()
-            .graphics_controller_registers
-            .set_bit_mask(pixel_mask);
+        self.set_bit_mask(pixel_mask);
         unsafe {
-            frame_buffer.add(offset).read_volatile();
-            frame_buffer.add(offset).write_volatile(u8::from(color));
+            self.read_memory(offset);
+            self.write_memory(offset, u8::from(color));
+        }
+    }
+
+    /// Returns the color of the pixel at `(x, y)`, combining one bit from each plane.
+    #[inline]
+    pub fn get_pixel(&mut self, x: usize, y: usize) -> Color16 {
+        let offset = x / 8 + y * WIDTH_IN_BYTES;
+        let bit = 7 - (x & 0x07);
+        let mut color = 0;
+        for plane in 0..4u8 {
+            self.set_read_plane(ReadPlane::try_from(plane).unwrap());
+            let byte = unsafe { self.read_memory(offset) };
+            color |= ((byte >> bit) & 0x1) << plane;
+        }
+        Color16::try_from(color).unwrap()
+    }
+
+    /// Draws `bitmap` with its top left corner at `position`, clipped to
+    /// the screen. Every plane is written in a single pass, with the bits of
+    /// eight pixels packed into each byte.
+    pub fn blit(&mut self, bitmap: &Bitmap<'_, Color16>, position: Point<isize>) {
+        let ((source_x, source_y), (x, y), (width, height)) =
+            match bitmap.clip(position, WIDTH, HEIGHT) {
+                Some(visible) => visible,
+                None => return,
+            };
+        self.select_function(self.raster_op());
+        self.set_write_mode(WriteMode::Mode0);
+        self.set_enable_set_reset(0x0);
+        for plane in 0..4 {
+            self.set_plane_mask(PlaneMask::from_bits(1 << plane).unwrap());
+            for row in 0..height {
+                let line = (y + row) * WIDTH_IN_BYTES;
+                let mut column = 0;
+                while column < width {
+                    let byte = (x + column) / 8;
+                    let (mut bits, mut mask) = (0u8, 0u8);
+                    while column < width && (x + column) / 8 == byte {
+                        if let Some(color) = bitmap.get_pixel(source_x + column, source_y + row) {
+                            let bit = 0x80 >> ((x + column) & 0x07);
+                            mask |= bit;
+                            if u8::from(color) & (1 << plane) != 0 {
+                                bits |= bit;
+                            }
+                        }
+                        column += 1;
+                    }
+                    if mask != 0 {
+                        unsafe {
+                            self.write_masked(line + byte, bits, mask);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies the `(width, height)` pixels at `position` from `pixels`, which
+    /// hold a whole screen, to the same position on the screen.
+    ///
+    /// The rectangle is widened to whole bytes of eight pixels, which are
+    /// converted to planar memory by packing one bit of each pixel into the
+    /// byte of every plane, so each plane is copied row by row without
+    /// reading vga memory. The pixels are copied as they are, ignoring the
+    /// raster op.
+    ///
+    /// The rectangle is clamped to the screen, and nothing is copied if
+    /// `pixels` holds less than a whole screen.
+    pub fn present_rect(&mut self, pixels: &[Color16], position: Point<usize>, size: Point<usize>) {
+        if pixels.len() < WIDTH * HEIGHT {
+            return;
+        }
+        let (x, y) = position;
+        let width = size.0.min(WIDTH.saturating_sub(x));
+        let height = size.1.min(HEIGHT.saturating_sub(y));
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let (first_byte, last_byte) = (x / 8, (x + width - 1) / 8);
+        let bytes = last_byte - first_byte + 1;
+        let mut row_bytes = [0u8; WIDTH_IN_BYTES];
+        self.select_function(RasterOp::Replace);
+        self.set_write_mode(WriteMode::Mode0);
+        self.set_enable_set_reset(0x0);
+        self.set_bit_mask(0xFF);
+        for plane in 0..4 {
+            self.set_plane_mask(PlaneMask::from_bits(1 << plane).unwrap());
+            for row in y..y + height {
+                let line = row * WIDTH;
+                for (byte, value) in row_bytes[..bytes].iter_mut().enumerate() {
+                    let start = line + (first_byte + byte) * 8;
+                    *value = pixels[start..start + 8].iter().fold(0, |bits, &color| {
+                        bits << 1 | (u8::from(color) >> plane) & 0x1
+                    });
+                }
+                unsafe {
+                    self.copy_to_memory(row * WIDTH_IN_BYTES + first_byte, &row_bytes[..bytes]);
+                }
+            }
+        }
+    }
+
+    /// Fills the pixels from `left` to `right` (inclusive) on row `y` with the
+    /// set/reset color, assuming write mode 0 with set/reset enabled.
+    fn fill_row(&mut self, left: usize, right: usize, y: usize) {
+        let row = y * WIDTH_IN_BYTES;
+        let (left_byte, right_byte) = (left / 8, right / 8);
+        let left_mask = 0xFF >> (left & 0x07);
+        let right_mask = 0xFF << (7 - (right & 0x07));
+
+        unsafe {
+            if left_byte == right_byte {
+                self.write_masked(row + left_byte, 0xFF, left_mask & right_mask);
+                return;
+            }
+            self.write_masked(row + left_byte, 0xFF, left_mask);
+            if right_byte > left_byte + 1 && self.raster_op() == RasterOp::Replace {
+                self.set_bit_mask(0xFF);
+                self.fill_memory(row + left_byte + 1, 0xFF, right_byte - left_byte - 1);
+            } else {
+                for byte in left_byte + 1..right_byte {
+                    self.write_masked(row + byte, 0xFF, 0xFF);
+                }
+            }
+            self.write_masked(row + right_byte, 0xFF, right_mask);
+        }
+    }
+
+    /// Writes `value` to the pixels selected by `bit_mask` of the byte at `offset`.
+    unsafe fn write_masked(&mut self, offset: usize, value: u8, bit_mask: u8) {
+        self.set_bit_mask(bit_mask);
+        if bit_mask != 0xFF || self.raster_op() != RasterOp::Replace {
+            // Load the latches, so the pixels outside of the mask are preserved
+            // and the raster op combines the value with the pixels inside it.
+            self.read_memory(offset);
+        }
+        self.write_memory(offset, value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{emulator::VgaEmulator, fonts::TEXT_8X16_FONT, vga::Vga};
+    use spinning_top::Spinlock;
+
+    #[test]
+    fn test_fill_rect() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics640x480x16::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(Color16::Blue);
+
+        let rects = [
+            ((3, 1), (5, 2)),
+            ((6, 4), (30, 6)),
+            ((16, 8), (31, 8)),
+            ((-10, 10), (9, 12)),
+            ((630, 470), (700, 500)),
+        ];
+        for &(start, end) in &rects {
+            mode.fill_rect(start, end, Color16::Yellow);
+        }
+        mode.clear_rect((4, 1), (4, 1));
+
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let inside = |&((left, top), (right, bottom)): &(Point<isize>, Point<isize>)| {
+                    let (x, y) = (x as isize, y as isize);
+                    x >= left && x <= right && y >= top && y <= bottom
+                };
+                let expected = if (x, y) == (4, 1) {
+                    Color16::Black
+                } else if rects.iter().any(inside) {
+                    Color16::Yellow
+                } else {
+                    Color16::Blue
+                };
+                assert_eq!(emulator.read_pixel(x, y), expected as u8, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_draw_character_with_vga_font() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mut mode = Graphics640x480x16::with_device(&vga);
+        mode.set_font(FontSource::Vga(&TEXT_8X16_FONT));
+        mode.set_mode();
+        mode.clear_screen(Color16::Black);
+
+        for (offset, character) in "╔═A".chars().enumerate() {
+            mode.draw_character(20 + offset * 8, 30, character, Color16::White);
+        }
+        for (offset, &index) in [0xC9, 0xCD, b'A'].iter().enumerate() {
+            let glyph = TEXT_8X16_FONT.glyph(index).unwrap();
+            for (y, bits) in glyph.iter().enumerate() {
+                for x in 0..8 {
+                    let expected = if bits & (0x80 >> x) != 0 {
+                        Color16::White
+                    } else {
+                        Color16::Black
+                    };
+                    let (x, y) = (20 + offset * 8 + x, 30 + y);
+                    assert_eq!(emulator.read_pixel(x, y), expected as u8, "({}, {})", x, y);
+                }
+            }
         }
     }
 }
@@ -1,7 +1,9 @@
 //! Writers for common vga modes.
+mod back_buffer;
 mod graphics_320x200x256;
 mod graphics_320x240x256;
 mod graphics_640x480x16;
+mod session;
 mod text_40x25;
 mod text_40x50;
 mod text_80x25;
@@ -9,14 +11,19 @@ mod text_80x25;
 use super::{
     colors::{Color16, TextModeColor},
     drawing::Point,
-    registers::CrtcControllerIndex,
-    vga::{Vga, VGA},
+    io::{MemoryIo, PortIo},
+    registers::{ClockingMode, CrtcControllerIndex},
+    vga::{Vga, VgaDevice},
+    Error,
 };
+use core::convert::TryFrom;
 use spinning_top::SpinlockGuard;
 
+pub use back_buffer::{BackBuffer, MAX_DIRTY_RECTS};
 pub use graphics_320x200x256::Graphics320x200x256;
 pub use graphics_320x240x256::Graphics320x240x256;
 pub use graphics_640x480x16::Graphics640x480x16;
+pub use session::DrawingSession;
 pub use text_40x25::Text40x25;
 pub use text_40x50::Text40x50;
 pub use text_80x25::Text80x25;
@@ -46,6 +53,9 @@ impl ScreenCharacter {
     }
 }
 
+/// The number of `ScreenCharacter`s that fit in the 32K text mode memory window.
+const TEXT_WINDOW_SIZE: usize = 0x8000 / core::mem::size_of::<ScreenCharacter>();
+
 static BLANK_CHARACTER: ScreenCharacter = ScreenCharacter {
     character: b' ',
     color: TextModeColor::new(Color16::Yellow, Color16::Black),
@@ -61,8 +71,20 @@ pub trait Screen {
     const SIZE: usize;
 }
 
+/// A locked `Vga` together with the text mode frame buffer it guards.
+type LockedFrameBuffer<'a, D> = (
+    SpinlockGuard<'a, Vga<<D as VgaDevice>::Io>>,
+    *mut ScreenCharacter,
+);
+
 /// A helper trait used to interact with various vga text modes.
 pub trait TextWriter: Screen {
+    /// The `VgaDevice` this `TextWriter` writes to.
+    type Device: VgaDevice;
+
+    /// Returns the `VgaDevice` this `TextWriter` writes to.
+    fn get_device(&self) -> &Self::Device;
+
     /// Sets the graphics device to a video mode as determined by
     /// the `TextWriter` implementation.
     fn set_mode(&self);
@@ -70,27 +92,56 @@ pub trait TextWriter: Screen {
     /// Returns the start of the `FrameBuffer` as `*mut ScreenCharacter`
     /// as well as a lock to the vga driver. This ensures the vga
     /// driver stays locked while the frame buffer is in use.
-    fn get_frame_buffer(&self) -> (SpinlockGuard<Vga>, *mut ScreenCharacter) {
-        let mut vga = VGA.lock();
-        let frame_buffer = vga.get_frame_buffer();
-        (vga, u32::from(frame_buffer) as *mut ScreenCharacter)
+    fn get_frame_buffer(
+        &self,
+    ) -> (
+        SpinlockGuard<'_, Vga<<Self::Device as VgaDevice>::Io>>,
+        *mut ScreenCharacter,
+    ) {
+        let mut vga = self.get_device().lock();
+        let frame_buffer = vga.get_frame_buffer_address();
+        (vga, frame_buffer as *mut ScreenCharacter)
+    }
+
+    /// Returns the frame buffer like `get_frame_buffer`, after checking that
+    /// the card is in a text mode with the dimensions of this `TextWriter`,
+    /// returning `Error::UnexpectedVideoMode` otherwise.
+    ///
+    /// The check reads every display register, see `Vga::check_video_mode`.
+    fn try_get_frame_buffer(&self) -> Result<LockedFrameBuffer<'_, Self::Device>, Error> {
+        let (mut vga, frame_buffer) = self.get_frame_buffer();
+        let info = vga.read_display_registers().video_mode_info();
+        if info.graphics || info.width != Self::WIDTH || info.height != Self::HEIGHT {
+            return Err(Error::UnexpectedVideoMode(info));
+        }
+        Ok((vga, frame_buffer))
     }
 
     /// Clears the screen by setting all cells to `b' '` with
     /// a background color of `Color16::Black` and a foreground
     /// color of `Color16::Yellow`.
-    fn clear_screen(&self) {
-        self.fill_screen(BLANK_CHARACTER);
+    ///
+    /// Returns `Error::OutOfRange` like `fill_screen`.
+    fn clear_screen(&self) -> Result<(), Error> {
+        self.fill_screen(BLANK_CHARACTER)
     }
 
     /// Fills the screen by setting all cells to the given screen character.
-    fn fill_screen(&self, character: ScreenCharacter) {
-        let (_vga, frame_buffer) = self.get_frame_buffer();
-        for i in 0..Self::SIZE {
+    ///
+    /// Returns `Error::OutOfRange` with the number of characters to fill
+    /// if the screen doesn't fit in the 32K text mode memory window.
+    fn fill_screen(&self, character: ScreenCharacter) -> Result<(), Error> {
+        let (mut vga, frame_buffer) = self.get_frame_buffer();
+        let size = vga.virtual_width() * Self::HEIGHT;
+        if size > TEXT_WINDOW_SIZE {
+            return Err(Error::OutOfRange(size));
+        }
+        for i in 0..size {
             unsafe {
-                frame_buffer.add(i).write_volatile(character);
+                write_screen_character(&mut vga, frame_buffer.add(i), character);
             }
         }
+        Ok(())
     }
 
     /// Disables the cursor in vga text modes.
@@ -122,10 +173,22 @@ pub trait TextWriter: Screen {
     }
 
     /// Returns the `ScreenCharacter` at the given `(x, y)` position.
-    fn read_character(&self, x: usize, y: usize) -> ScreenCharacter {
-        let (_vga, frame_buffer) = self.get_frame_buffer();
-        let offset = Self::WIDTH * y + x;
-        unsafe { frame_buffer.add(offset).read_volatile() }
+    ///
+    /// Returns `Error::OutOfRange` with the offset of the character if it's
+    /// outside of the 32K text mode memory window.
+    fn read_character(&self, x: usize, y: usize) -> Result<ScreenCharacter, Error> {
+        let (mut vga, frame_buffer) = self.get_frame_buffer();
+        let offset = character_offset(&mut vga, x, y)?;
+        Ok(unsafe { read_screen_character(&mut vga, frame_buffer.add(offset)) })
+    }
+
+    /// Returns the `ScreenCharacter` at the given `(x, y)` position like
+    /// `read_character`, after checking the video mode as described in
+    /// `try_get_frame_buffer`.
+    fn try_read_character(&self, x: usize, y: usize) -> Result<ScreenCharacter, Error> {
+        let (mut vga, frame_buffer) = self.try_get_frame_buffer()?;
+        let offset = character_offset(&mut vga, x, y)?;
+        Ok(unsafe { read_screen_character(&mut vga, frame_buffer.add(offset)) })
     }
 
     /// Sets the size of the cursor, as specified by `scan_line_start` and `scan_line_end`.
@@ -160,8 +223,8 @@ pub trait TextWriter: Screen {
     /// Sets the current text cursor to the position specified by
     /// `x` and `y`.
     fn set_cursor_position(&self, x: usize, y: usize) {
-        let offset = Self::WIDTH * y + x;
         let (mut vga, _frame_buffer) = self.get_frame_buffer();
+        let offset = vga.virtual_width() * y + x;
         let emulation_mode = vga.get_emulation_mode();
         let cursor_start = offset & 0xFF;
         let cursor_end = (offset >> 8) & 0xFF;
@@ -178,13 +241,203 @@ pub trait TextWriter: Screen {
     }
 
     /// Prints the given `character` and `color` at `(x, y)`.
-    fn write_character(&self, x: usize, y: usize, screen_character: ScreenCharacter) {
-        let (_vga, frame_buffer) = self.get_frame_buffer();
-        let offset = Self::WIDTH * y + x;
+    ///
+    /// Returns `Error::OutOfRange` with the offset of the character if it's
+    /// outside of the 32K text mode memory window.
+    fn write_character(
+        &self,
+        x: usize,
+        y: usize,
+        screen_character: ScreenCharacter,
+    ) -> Result<(), Error> {
+        let (mut vga, frame_buffer) = self.get_frame_buffer();
+        let offset = character_offset(&mut vga, x, y)?;
         unsafe {
-            frame_buffer.add(offset).write_volatile(screen_character);
+            write_screen_character(&mut vga, frame_buffer.add(offset), screen_character);
+        }
+        Ok(())
+    }
+
+    /// Prints the given `character` and `color` at `(x, y)` like
+    /// `write_character`, after checking the video mode as described in
+    /// `try_get_frame_buffer`.
+    fn try_write_character(
+        &self,
+        x: usize,
+        y: usize,
+        screen_character: ScreenCharacter,
+    ) -> Result<(), Error> {
+        let (mut vga, frame_buffer) = self.try_get_frame_buffer()?;
+        let offset = character_offset(&mut vga, x, y)?;
+        unsafe {
+            write_screen_character(&mut vga, frame_buffer.add(offset), screen_character);
+        }
+        Ok(())
+    }
+
+    /// Returns the width of the virtual screen in characters.
+    ///
+    /// This is `Self::WIDTH` unless it was changed with `set_virtual_width`.
+    fn get_virtual_width(&self) -> usize {
+        self.get_device().lock().virtual_width()
+    }
+
+    /// Sets the width of the virtual screen to `columns` characters,
+    /// which is the distance between the start of two rows in vga memory.
+    ///
+    /// The screen only shows `Self::WIDTH` columns of the virtual screen,
+    /// starting from the position given to `scroll_to`. The positions of
+    /// `read_character`, `write_character` and `set_cursor_position` are
+    /// relative to the virtual screen, while `fill_screen` fills its first
+    /// `Self::HEIGHT` rows.
+    ///
+    /// Returns `Error::OutOfRange` if `columns` is odd, less than
+    /// `Self::WIDTH`, more than 510, or too wide for `Self::HEIGHT` rows
+    /// to fit in the 32K text mode memory window.
+    fn set_virtual_width(&self, columns: usize) -> Result<(), Error> {
+        if columns & 1 != 0
+            || columns < Self::WIDTH
+            || columns > 510
+            || columns * Self::HEIGHT > TEXT_WINDOW_SIZE
+        {
+            return Err(Error::OutOfRange(columns));
+        }
+        self.get_device().lock().set_virtual_width(columns);
+        Ok(())
+    }
+
+    /// Scrolls the screen so that its top left corner shows the pixel at
+    /// `(x, y)` of the virtual screen, which is measured in dots and scan
+    /// lines rather than characters.
+    ///
+    /// The start address is changed while the display is enabled, and the
+    /// pixel panning once vertical retrace has started, so the scroll takes
+    /// effect on the next frame without tearing. As a result, this waits
+    /// for up to a frame before returning.
+    ///
+    /// Returns `Error::OutOfRange` with the start address of the screen if
+    /// it's past the last address of the 16 bit start address register.
+    fn scroll_to(&self, x: usize, y: usize) -> Result<(), Error> {
+        let (mut vga, _frame_buffer) = self.get_frame_buffer();
+        let emulation_mode = vga.get_emulation_mode();
+        let nine_dot_characters = !vga
+            .sequencer_registers
+            .read_clocking_mode()
+            .contains(ClockingMode::EIGHT_DOT_CHARACTERS);
+        let character_width = if nine_dot_characters { 9 } else { 8 };
+        let character_height = usize::from(
+            vga.crtc_controller_registers
+                .read_maximum_scan_line(emulation_mode)
+                .character_height(),
+        );
+
+        let (column, dot) = (x / character_width, x % character_width);
+        let (row, scan_line) = (y / character_height, y % character_height);
+        let start_address = row * vga.virtual_width() + column;
+        let start_address =
+            u16::try_from(start_address).map_err(|_| Error::OutOfRange(start_address))?;
+        // With 9 dot characters, a panning of 8 shows the first dot of
+        // a character and 0 shows its second one.
+        let pixel_panning = if nine_dot_characters {
+            (dot + 8) % 9
+        } else {
+            dot
+        };
+        scroll_on_retrace(
+            &mut vga,
+            start_address,
+            scan_line as u8,
+            pixel_panning as u8,
+        );
+        Ok(())
+    }
+
+    /// Splits the screen so that rows from `row` onward show the start of
+    /// vga memory, which stays in place while the rows above it are
+    /// scrolled with `scroll_to`. See `Vga::set_split_screen` for details.
+    ///
+    /// The lower window shows the first rows of the virtual screen, so
+    /// a status bar written to row 0 appears at `row`.
+    ///
+    /// Returns `Error::OutOfRange` if `row` is 0 or not less than
+    /// `Self::HEIGHT`.
+    fn set_split_screen(&self, row: usize) -> Result<(), Error> {
+        if row == 0 || row >= Self::HEIGHT {
+            return Err(Error::OutOfRange(row));
         }
+        let (mut vga, _frame_buffer) = self.get_frame_buffer();
+        let emulation_mode = vga.get_emulation_mode();
+        let character_height = usize::from(
+            vga.crtc_controller_registers
+                .read_maximum_scan_line(emulation_mode)
+                .character_height(),
+        );
+        vga.set_split_screen((row * character_height - 1) as u16)
+    }
+
+    /// Removes the split created by `set_split_screen`.
+    fn disable_split_screen(&self) {
+        let (mut vga, _frame_buffer) = self.get_frame_buffer();
+        vga.disable_split_screen();
+    }
+}
+
+/// Returns the offset of the character at `(x, y)` of the virtual text
+/// screen, or `Error::OutOfRange` if it's outside of the memory window.
+fn character_offset<I: PortIo + MemoryIo>(
+    vga: &mut Vga<I>,
+    x: usize,
+    y: usize,
+) -> Result<usize, Error> {
+    let offset = vga.virtual_width() * y + x;
+    if offset >= TEXT_WINDOW_SIZE {
+        return Err(Error::OutOfRange(offset));
     }
+    Ok(offset)
+}
+
+/// Displays vga memory from `start_address`, shifted up by `preset_row_scan`
+/// scan lines and left by `pixel_panning` pixels, starting with the next frame.
+fn scroll_on_retrace<I: PortIo + MemoryIo>(
+    vga: &mut Vga<I>,
+    start_address: u16,
+    preset_row_scan: u8,
+    pixel_panning: u8,
+) {
+    let emulation_mode = vga.get_emulation_mode();
+    // The start address is latched at the start of vertical retrace, while
+    // the panning registers take effect immediately, so they're changed
+    // on either side of it.
+    vga.general_registers
+        .wait_for_display_enable(emulation_mode);
+    vga.crtc_controller_registers
+        .set_start_address(emulation_mode, start_address);
+    vga.general_registers.wait_for_vsync(emulation_mode);
+    vga.crtc_controller_registers
+        .set_preset_row_scan(emulation_mode, preset_row_scan);
+    vga.attribute_controller_registers
+        .set_horizontal_pixel_panning(emulation_mode, pixel_panning);
+}
+
+unsafe fn read_screen_character<I: PortIo + MemoryIo>(
+    vga: &mut Vga<I>,
+    screen_character: *mut ScreenCharacter,
+) -> ScreenCharacter {
+    let address = screen_character as usize;
+    ScreenCharacter::new(
+        vga.read_memory(address),
+        TextModeColor::from(vga.read_memory(address + 1)),
+    )
+}
+
+unsafe fn write_screen_character<I: PortIo + MemoryIo>(
+    vga: &mut Vga<I>,
+    screen_character: *mut ScreenCharacter,
+    value: ScreenCharacter,
+) {
+    let address = screen_character as usize;
+    vga.write_memory(address, value.character);
+    vga.write_memory(address + 1, u8::from(value.color));
 }
 
 /// A helper trait used to interact with various vga graphics modes.
@@ -192,15 +445,475 @@ pub trait GraphicsWriter<Color> {
     /// Clears the screen by setting all pixels to the specified `color`.
     fn clear_screen(&self, color: Color);
     /// Draws a line from `start` to `end` with the specified `color`.
+    ///
+    /// The line is clipped to the screen, so `start` and `end` may be negative
+    /// or lie outside of it.
     fn draw_line(&self, start: Point<isize>, end: Point<isize>, color: Color);
     /// Draws a character at the given `(x, y)` coordinant to the specified `color`.
     fn draw_character(&self, x: usize, y: usize, character: char, color: Color);
     /// Sets the given pixel at `(x, y)` to the given `color`.
     fn set_pixel(&self, x: usize, y: usize, color: Color);
+    /// Returns the color of the pixel at `(x, y)`.
+    fn get_pixel(&self, x: usize, y: usize) -> Color;
     /// Sets the graphics device to a `VideoMode`.
     fn set_mode(&self);
     /// Returns the frame buffer for this vga mode.
-    fn get_frame_buffer(&self) -> *mut u8 {
-        u32::from(VGA.lock().get_frame_buffer()) as *mut u8
+    fn get_frame_buffer(&self) -> *mut u8;
+    /// Calls `f` with a `DrawTarget` for this screen, so that shapes made
+    /// of many pixels and spans, such as those in `vga::drawing`, can be
+    /// drawn without locking the vga for each one of them.
+    ///
+    /// The graphics writers pass a `DrawingSession`, while the default
+    /// implementation draws through the other methods of `GraphicsWriter`.
+    fn with_draw_target(&self, f: &mut dyn FnMut(&mut dyn DrawTarget<Color>))
+    where
+        Self: Screen + Sized,
+        Color: Copy,
+    {
+        f(&mut WriterDrawTarget(self));
+    }
+}
+
+/// A helper trait used to copy pixels from regular memory to the screen,
+/// such as from a `BackBuffer`.
+pub trait Present<Color>: Screen {
+    /// Copies every rectangle in `rects`, given as its top left corner and
+    /// `(width, height)`, from `pixels` to the same position on the screen.
+    /// `pixels` holds a whole screen, stored row by row.
+    ///
+    /// Rectangles may be widened to the byte boundaries of vga memory,
+    /// which is why `pixels` has to hold the whole screen.
+    /// Nothing is copied if it holds fewer pixels, and rectangles that extend
+    /// past the screen are clamped to it.
+    fn present(&self, pixels: &[Color], rects: &[(Point<usize>, Point<usize>)]);
+}
+
+/// A helper trait used to draw pixels, lines and fills to a screen through
+/// `&mut self`, such as to a `DrawingSession`. See
+/// `GraphicsWriter::with_draw_target`.
+pub trait DrawTarget<Color> {
+    /// Sets the given pixel at `(x, y)` to the given `color`.
+    fn set_pixel(&mut self, x: usize, y: usize, color: Color);
+    /// Returns the color of the pixel at `(x, y)`.
+    fn get_pixel(&mut self, x: usize, y: usize) -> Color;
+    /// Draws a line from `start` to `end` with the specified `color`,
+    /// clipped to the screen.
+    fn draw_line(&mut self, start: Point<isize>, end: Point<isize>, color: Color);
+    /// Fills the rectangle with the corners `start` and `end` (inclusive)
+    /// with the specified `color`, clipped to the screen.
+    fn fill_rect(&mut self, start: Point<isize>, end: Point<isize>, color: Color);
+}
+
+/// The `DrawTarget` of `GraphicsWriter::with_draw_target` for writers
+/// without a `DrawingSession`.
+struct WriterDrawTarget<'a, W>(&'a W);
+
+impl<W, Color> DrawTarget<Color> for WriterDrawTarget<'_, W>
+where
+    W: GraphicsWriter<Color> + Screen,
+    Color: Copy,
+{
+    fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.0.set_pixel(x, y, color);
+    }
+
+    fn get_pixel(&mut self, x: usize, y: usize) -> Color {
+        self.0.get_pixel(x, y)
+    }
+
+    fn draw_line(&mut self, start: Point<isize>, end: Point<isize>, color: Color) {
+        self.0.draw_line(start, end, color);
+    }
+
+    fn fill_rect(&mut self, start: Point<isize>, end: Point<isize>, color: Color) {
+        let top = start.1.min(end.1).max(0);
+        let bottom = start.1.max(end.1).min(W::HEIGHT as isize - 1);
+        for y in top..=bottom {
+            self.0.draw_line((start.0, y), (end.0, y), color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::emulator::VgaEmulator;
+    use spinning_top::Spinlock;
+
+    #[test]
+    fn test_get_pixel() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+
+        let mode = Graphics640x480x16::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(Color16::Black);
+        mode.set_pixel(9, 3, Color16::Pink);
+        assert_eq!(mode.get_pixel(9, 3), Color16::Pink);
+        assert_eq!(mode.get_pixel(8, 3), Color16::Black);
+
+        let mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(0);
+        mode.set_pixel(7, 3, 0xAB);
+        assert_eq!(mode.get_pixel(7, 3), 0xAB);
+        assert_eq!(mode.get_pixel(6, 3), 0);
+
+        let mode = Graphics320x200x256::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(0);
+        mode.set_pixel(5, 3, 0xCD);
+        assert_eq!(mode.get_pixel(5, 3), 0xCD);
+        assert_eq!(mode.get_pixel(4, 3), 0);
+    }
+
+    #[test]
+    fn test_get_frame_buffer() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        vga.lock().set_frame_buffer_base(0x1000_0000);
+
+        // The frame buffer is looked up on the device of the writer,
+        // rather than on the global `VGA`, and the emulator decodes
+        // addresses relative to the same base, so drawing still reaches
+        // vga memory.
+        let mode = Graphics640x480x16::with_device(&vga);
+        mode.set_mode();
+        assert_eq!(mode.get_frame_buffer() as usize, 0x1000_0000);
+        mode.clear_screen(Color16::Black);
+        mode.set_pixel(9, 4, Color16::Cyan);
+        assert_eq!(emulator.read_pixel(9, 4), Color16::Cyan as u8);
+        let mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        assert_eq!(mode.get_frame_buffer() as usize, 0x1000_0000);
+        mode.clear_screen(0);
+        mode.set_pixel(9, 4, 0x42);
+        assert_eq!(emulator.read_pixel(9, 4), 0x42);
+        let mode = Graphics320x200x256::with_device(&vga);
+        mode.set_mode();
+        assert_eq!(mode.get_frame_buffer() as usize, 0x1000_0000);
+        mode.clear_screen(0);
+        mode.set_pixel(9, 4, 0x24);
+        assert_eq!(emulator.read_pixel(9, 4), 0x24);
+
+        let text_mode = Text80x25::with_device(&vga);
+        let character =
+            ScreenCharacter::new(b'A', TextModeColor::new(Color16::White, Color16::Blue));
+        text_mode.set_mode();
+        text_mode.clear_screen().unwrap();
+        text_mode.write_character(3, 2, character).unwrap();
+        assert_eq!(text_mode.read_character(3, 2), Ok(character));
+        emulator.with_renderer(|renderer| {
+            assert_eq!(renderer.pixel(3 * 9, 2 * 16), [0x00, 0x00, 0xAA]);
+        });
+    }
+
+    #[test]
+    fn test_blit() {
+        use crate::drawing::{Bitmap, Transparency};
+
+        const O: Color16 = Color16::Black;
+        const X: Color16 = Color16::LightRed;
+        const Y: Color16 = Color16::Cyan;
+        static COLORS: [Color16; 30] = [
+            X, Y, O, X, Y, O, X, Y, O, X, //
+            O, X, Y, O, X, Y, O, X, Y, O, //
+            Y, O, X, Y, O, X, Y, O, X, Y, //
+        ];
+        static MASK: [u8; 6] = [0xFF, 0xC0, 0x00, 0x00, 0x81, 0x40];
+        let bytes = COLORS.map(u8::from);
+
+        // The pixels around a bitmap drawn at `position`, clipped to the screen.
+        fn around(
+            position: Point<isize>,
+            width: usize,
+            height: usize,
+        ) -> impl Iterator<Item = (usize, usize)> {
+            let columns =
+                (position.0 - 2).max(0) as usize..((position.0 + 12).max(0) as usize).min(width);
+            let rows =
+                (position.1 - 1).max(0) as usize..((position.1 + 4).max(0) as usize).min(height);
+            rows.flat_map(move |y| columns.clone().map(move |x| (x, y)))
+        }
+
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let planar = Graphics640x480x16::with_device(&vga);
+        let mode_x = Graphics320x240x256::with_device(&vga);
+        let linear = Graphics320x200x256::with_device(&vga);
+
+        for &transparency in &[
+            Transparency::None,
+            Transparency::ColorKey(O),
+            Transparency::Mask(&MASK),
+        ] {
+            let (bitmap, byte_bitmap) = match transparency {
+                Transparency::None => (Bitmap::new(10, 3, &COLORS), Bitmap::new(10, 3, &bytes)),
+                Transparency::ColorKey(key) => (
+                    Bitmap::new(10, 3, &COLORS).with_color_key(key),
+                    Bitmap::new(10, 3, &bytes).with_color_key(u8::from(key)),
+                ),
+                Transparency::Mask(mask) => (
+                    Bitmap::new(10, 3, &COLORS).with_mask(mask),
+                    Bitmap::new(10, 3, &bytes).with_mask(mask),
+                ),
+            };
+            for &position in &[(-3, -1), (5, 7), (315, 198), (637, 478)] {
+                let expected = |x: usize, y: usize| {
+                    let (x, y) = (x as isize - position.0, y as isize - position.1);
+                    if x < 0 || y < 0 || x >= 10 || y >= 3 {
+                        None
+                    } else {
+                        bitmap.get_pixel(x as usize, y as usize)
+                    }
+                };
+
+                planar.set_mode();
+                planar.clear_screen(Color16::Blue);
+                planar.blit(&bitmap, position);
+                for (x, y) in around(position, 640, 480) {
+                    assert_eq!(
+                        planar.get_pixel(x, y),
+                        expected(x, y).unwrap_or(Color16::Blue)
+                    );
+                }
+
+                mode_x.set_mode();
+                mode_x.clear_screen(1);
+                mode_x.blit(&byte_bitmap, position);
+                for (x, y) in around(position, 320, 240) {
+                    assert_eq!(mode_x.get_pixel(x, y), expected(x, y).map_or(1, u8::from));
+                }
+
+                linear.set_mode();
+                linear.clear_screen(1);
+                linear.blit(&byte_bitmap, position);
+                for (x, y) in around(position, 320, 200) {
+                    assert_eq!(linear.get_pixel(x, y), expected(x, y).map_or(1, u8::from));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_scroll_to() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text80x25::with_device(&vga);
+        let block =
+            ScreenCharacter::new(0xDB, TextModeColor::new(Color16::LightGrey, Color16::Black));
+        text_mode.set_mode();
+        assert_eq!(text_mode.get_virtual_width(), 80);
+        assert_eq!(
+            text_mode.set_virtual_width(161),
+            Err(Error::OutOfRange(161))
+        );
+        assert_eq!(text_mode.set_virtual_width(78), Err(Error::OutOfRange(78)));
+        assert_eq!(text_mode.get_virtual_width(), 80);
+        text_mode.set_virtual_width(160).unwrap();
+        text_mode.clear_screen().unwrap();
+        text_mode.write_character(100, 20, block).unwrap();
+        assert_eq!(text_mode.read_character(100, 20), Ok(block));
+
+        // Scroll to the fourth dot and sixth scan line of the block.
+        text_mode.scroll_to(100 * 9 + 3, 20 * 16 + 5).unwrap();
+        emulator.with_renderer(|renderer| {
+            let light_grey = [0xAA, 0xAA, 0xAA];
+            assert_eq!(renderer.pixel(0, 0), light_grey);
+            assert_eq!(renderer.pixel(5, 10), light_grey);
+            assert_ne!(renderer.pixel(6, 0), light_grey);
+            assert_ne!(renderer.pixel(0, 11), light_grey);
+        });
+
+        // Row 410 starts at character 410 * 160, past the 16 bit start address.
+        assert_eq!(
+            text_mode.scroll_to(0, 410 * 16),
+            Err(Error::OutOfRange(410 * 160))
+        );
+    }
+
+    #[test]
+    fn test_text_memory_window() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text40x50::with_device(&vga);
+        let block =
+            ScreenCharacter::new(0xDB, TextModeColor::new(Color16::LightGrey, Color16::Black));
+        text_mode.set_mode();
+
+        // 50 rows of 330 characters don't fit in the 16K characters at 0xB8000.
+        assert_eq!(
+            text_mode.set_virtual_width(330),
+            Err(Error::OutOfRange(330))
+        );
+        text_mode.set_virtual_width(320).unwrap();
+        assert_eq!(text_mode.get_virtual_width(), 320);
+        text_mode.clear_screen().unwrap();
+        text_mode.write_character(63, 51, block).unwrap();
+        assert_eq!(text_mode.read_character(63, 51), Ok(block));
+        assert_eq!(
+            text_mode.write_character(64, 51, block),
+            Err(Error::OutOfRange(0x4000))
+        );
+        assert_eq!(
+            text_mode.read_character(0, 52),
+            Err(Error::OutOfRange(52 * 320))
+        );
+
+        // Setting the mode again resets the cached width.
+        text_mode.set_mode();
+        assert_eq!(text_mode.get_virtual_width(), 40);
+    }
+
+    #[test]
+    fn test_text_split_screen() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text80x25::with_device(&vga);
+        let block =
+            ScreenCharacter::new(0xDB, TextModeColor::new(Color16::LightGrey, Color16::Black));
+        text_mode.set_mode();
+        text_mode.clear_screen().unwrap();
+        text_mode.disable_cursor();
+        text_mode.write_character(0, 0, block).unwrap();
+
+        assert_eq!(text_mode.set_split_screen(0), Err(Error::OutOfRange(0)));
+        assert_eq!(text_mode.set_split_screen(25), Err(Error::OutOfRange(25)));
+
+        // The status bar in row 0 stays at row 24 while the screen scrolls.
+        text_mode.set_split_screen(24).unwrap();
+        text_mode.scroll_to(4, 16 * 10 + 3).unwrap();
+        emulator.with_renderer(|renderer| {
+            let light_grey = [0xAA, 0xAA, 0xAA];
+            assert_ne!(renderer.pixel(0, 0), light_grey);
+            assert_ne!(renderer.pixel(0, 24 * 16 - 1), light_grey);
+            assert_eq!(renderer.pixel(0, 24 * 16), light_grey);
+            assert_eq!(renderer.pixel(8, 24 * 16 + 15), light_grey);
+            assert_ne!(renderer.pixel(9, 24 * 16), light_grey);
+        });
+    }
+
+    #[test]
+    fn test_unexpected_video_mode() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let text_mode = Text80x25::with_device(&vga);
+        let graphics_mode = Graphics640x480x16::with_device(&vga);
+        let character =
+            ScreenCharacter::new(b'A', TextModeColor::new(Color16::White, Color16::Black));
+
+        text_mode.set_mode();
+        assert!(text_mode.try_write_character(0, 0, character).is_ok());
+        assert_eq!(text_mode.try_read_character(0, 0), Ok(character));
+        assert!(matches!(
+            graphics_mode.try_session(),
+            Err(Error::UnexpectedVideoMode(info)) if !info.graphics
+        ));
+        assert!(Text40x25::with_device(&vga)
+            .try_write_character(0, 0, character)
+            .is_err());
+
+        graphics_mode.set_mode();
+        assert!(graphics_mode.try_session().is_ok());
+        assert!(Graphics320x240x256::with_device(&vga)
+            .try_session()
+            .is_err());
+        assert!(matches!(
+            text_mode.try_read_character(0, 0),
+            Err(Error::UnexpectedVideoMode(info)) if info.width == 640
+        ));
+    }
+
+    #[test]
+    fn test_graphics_scroll_to() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+
+        let mode = Graphics640x480x16::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(Color16::Black);
+        mode.set_pixel(3, 500, Color16::Blue);
+        mode.scroll_to(30).unwrap();
+        assert_eq!(emulator.read_pixel(3, 470), Color16::Blue as u8);
+        let y = Graphics640x480x16::CANVAS_HEIGHT - 479;
+        assert_eq!(mode.scroll_to(y), Err(Error::OutOfRange(y)));
+
+        let mut mode = Graphics320x240x256::with_device(&vga);
+        mode.set_mode();
+        mode.set_draw_page(1).unwrap();
+        mode.set_pixel(5, 10, 0x42);
+        mode.scroll_to(250).unwrap();
+        assert_eq!(emulator.read_pixel(5, 0), 0x42);
+        let y = Graphics320x240x256::CANVAS_HEIGHT - 239;
+        assert_eq!(mode.scroll_to(y), Err(Error::OutOfRange(y)));
+        assert_eq!(mode.set_split_screen(240), Err(Error::OutOfRange(240)));
+    }
+
+    #[test]
+    fn test_raster_ops() {
+        use crate::{drawing::Bitmap, registers::RasterOp};
+
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+
+        let planar = Graphics640x480x16::with_device(&vga);
+        planar.set_mode();
+        planar.clear_screen(Color16::Blue);
+        {
+            let mut session = planar.session();
+            session.set_raster_op(RasterOp::Or);
+            session.fill_rect((3, 0), (20, 2), Color16::Green);
+            session.set_raster_op(RasterOp::Xor);
+            session.draw_line((0, 5), (30, 9), Color16::White);
+            session.draw_line((0, 5), (30, 9), Color16::White);
+            session.draw_line((0, 10), (30, 10), Color16::White);
+            session.set_raster_op(RasterOp::And);
+            session.blit(&Bitmap::new(2, 1, &[Color16::Red; 2]), (0, 20));
+        }
+        assert_eq!(planar.get_pixel(3, 0), Color16::Cyan);
+        assert_eq!(planar.get_pixel(20, 2), Color16::Cyan);
+        assert_eq!(planar.get_pixel(2, 0), Color16::Blue);
+        assert_eq!(planar.get_pixel(15, 7), Color16::Blue);
+        assert_eq!(planar.get_pixel(15, 10), Color16::Yellow);
+        assert_eq!(planar.get_pixel(1, 20), Color16::Black);
+        // Dropping the session restores plain writes.
+        planar.set_pixel(15, 10, Color16::Red);
+        assert_eq!(planar.get_pixel(15, 10), Color16::Red);
+
+        let mode_x = Graphics320x240x256::with_device(&vga);
+        mode_x.set_mode();
+        mode_x.clear_screen(0x0F);
+        {
+            let mut session = mode_x.session();
+            session.set_raster_op(RasterOp::Xor);
+            session.fill_rect((1, 1), (6, 2), 0xFF);
+            session.draw_line((0, 5), (9, 5), 0x11);
+            session.set_raster_op(RasterOp::And);
+            session.blit(&Bitmap::new(2, 1, &[0x3C; 2]), (3, 8));
+        }
+        assert_eq!(mode_x.get_pixel(1, 1), 0xF0);
+        assert_eq!(mode_x.get_pixel(6, 2), 0xF0);
+        assert_eq!(mode_x.get_pixel(7, 2), 0x0F);
+        assert_eq!(mode_x.get_pixel(9, 5), 0x1E);
+        assert_eq!(mode_x.get_pixel(4, 8), 0x0C);
+        assert_eq!(mode_x.get_pixel(5, 8), 0x0F);
+
+        let linear = Graphics320x200x256::with_device(&vga);
+        linear.set_mode();
+        linear.clear_screen(0x0F);
+        {
+            let mut session = linear.session();
+            session.set_raster_op(RasterOp::Xor);
+            session.fill_rect((1, 1), (6, 2), 0xFF);
+            session.draw_line((0, 5), (9, 5), 0x11);
+            session.set_raster_op(RasterOp::Or);
+            session.blit(&Bitmap::new(2, 1, &[0x30; 2]), (3, 8));
+        }
+        assert_eq!(linear.get_pixel(1, 1), 0xF0);
+        assert_eq!(linear.get_pixel(6, 2), 0xF0);
+        assert_eq!(linear.get_pixel(7, 2), 0x0F);
+        assert_eq!(linear.get_pixel(9, 5), 0x1E);
+        assert_eq!(linear.get_pixel(4, 8), 0x3F);
+        assert_eq!(linear.get_pixel(5, 8), 0x0F);
     }
 }
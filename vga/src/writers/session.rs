@@ -0,0 +1,374 @@
+use crate::{
+    colors::Color16,
+    io::{MemoryIo, PortIo, X86Io},
+    registers::{PlaneMask, RasterOp, ReadPlane, WriteMode},
+    vga::Vga,
+};
+use spinning_top::SpinlockGuard;
+
+/// A scoped drawing session on a graphics writer `W`.
+///
+/// A `DrawingSession` holds the vga lock for as long as it's alive, and
+/// caches the write mode, plane mask, bit mask, set/reset and read plane
+/// registers, so that ports are only written when their value actually
+/// changes. Every `GraphicsWriter` primitive is implemented on top of a
+/// session, and drawing many primitives in a single session avoids locking
+/// the vga and reprogramming the registers for each one of them.
+///
+/// The cached register values are only valid for the lifetime of the
+/// session, so other code changing the registers while a session is alive
+/// isn't noticed.
+///
+/// Lines, fills and blits combine their colors with the pixels already on
+/// screen as specified by the session's `RasterOp`, see `set_raster_op`.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```no_run
+/// use vga::colors::Color16;
+/// use vga::writers::{Graphics640x480x16, GraphicsWriter};
+///
+/// let mode = Graphics640x480x16::new();
+/// mode.set_mode();
+///
+/// let mut session = mode.session();
+/// session.clear_screen(Color16::Black);
+/// for y in (0..480).step_by(10) {
+///     session.draw_line((0, y), (639, y), Color16::Cyan);
+/// }
+/// ```
+pub struct DrawingSession<'a, W, I: PortIo + MemoryIo = X86Io> {
+    writer: &'a W,
+    vga: SpinlockGuard<'a, Vga<I>>,
+    frame_buffer: usize,
+    raster_op: RasterOp,
+    write_mode: Option<WriteMode>,
+    plane_mask: Option<PlaneMask>,
+    bit_mask: Option<u8>,
+    set_reset: Option<Color16>,
+    enable_set_reset: Option<u8>,
+    read_plane: Option<ReadPlane>,
+    function_select: Option<RasterOp>,
+    reset_function_select: bool,
+}
+
+impl<'a, W, I: PortIo + MemoryIo> DrawingSession<'a, W, I> {
+    pub(crate) fn new(writer: &'a W, mut vga: SpinlockGuard<'a, Vga<I>>) -> Self {
+        let frame_buffer = vga.get_frame_buffer_address();
+        DrawingSession {
+            writer,
+            vga,
+            frame_buffer,
+            raster_op: RasterOp::Replace,
+            write_mode: None,
+            plane_mask: None,
+            bit_mask: None,
+            set_reset: None,
+            enable_set_reset: None,
+            read_plane: None,
+            function_select: None,
+            reset_function_select: false,
+        }
+    }
+
+    /// Moves the frame buffer of this session `offset` bytes forward,
+    /// for writers that draw to more than one page of vga memory.
+    pub(crate) fn with_offset(mut self, offset: usize) -> Self {
+        self.frame_buffer += offset;
+        self
+    }
+
+    /// Returns the writer this session draws to.
+    pub fn writer(&self) -> &'a W {
+        self.writer
+    }
+
+    /// Returns the `Vga` this session draws to.
+    ///
+    /// Since the returned `Vga` can be used to change any register,
+    /// the cached register values of this session are discarded, and the
+    /// function select is reset to `RasterOp::Replace` when the session
+    /// is dropped.
+    pub fn vga(&mut self) -> &mut Vga<I> {
+        self.reset_function_select = true;
+        self.write_mode = None;
+        self.plane_mask = None;
+        self.bit_mask = None;
+        self.set_reset = None;
+        self.enable_set_reset = None;
+        self.read_plane = None;
+        self.function_select = None;
+        &mut self.vga
+    }
+
+    /// Returns the virtual address of the frame buffer this session draws to.
+    pub fn frame_buffer(&self) -> usize {
+        self.frame_buffer
+    }
+
+    /// Returns how lines, fills and blits are combined with the
+    /// pixels already on screen.
+    pub fn raster_op(&self) -> RasterOp {
+        self.raster_op
+    }
+
+    /// Makes the lines, fills and blits drawn from now on in this session
+    /// combine their colors with the pixels already on screen as specified
+    /// by `raster_op`. Clearing the screen always replaces every pixel.
+    ///
+    /// Drawing the same shapes twice with `RasterOp::Xor` restores the
+    /// pixels under them, which is handy for rubber-band selections and
+    /// mouse cursors.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use vga::colors::Color16;
+    /// use vga::registers::RasterOp;
+    /// use vga::writers::Graphics640x480x16;
+    ///
+    /// let mode = Graphics640x480x16::new();
+    /// let mut session = mode.session();
+    /// session.set_raster_op(RasterOp::Xor);
+    /// session.fill_rect((10, 10), (20, 20), Color16::White);
+    /// // ...
+    /// session.fill_rect((10, 10), (20, 20), Color16::White);
+    /// ```
+    pub fn set_raster_op(&mut self, raster_op: RasterOp) {
+        self.raster_op = raster_op;
+    }
+
+    /// Sets the write mode, unless it's already set to `write_mode`.
+    pub fn set_write_mode(&mut self, write_mode: WriteMode) {
+        if self.write_mode != Some(write_mode) {
+            self.vga
+                .graphics_controller_registers
+                .set_write_mode(write_mode);
+            self.write_mode = Some(write_mode);
+        }
+    }
+
+    /// Sets the plane mask, unless it's already set to `plane_mask`.
+    pub fn set_plane_mask(&mut self, plane_mask: PlaneMask) {
+        if self.plane_mask != Some(plane_mask) {
+            self.vga.sequencer_registers.set_plane_mask(plane_mask);
+            self.plane_mask = Some(plane_mask);
+        }
+    }
+
+    /// Sets the bit mask, unless it's already set to `bit_mask`.
+    pub fn set_bit_mask(&mut self, bit_mask: u8) {
+        if self.bit_mask != Some(bit_mask) {
+            self.vga
+                .graphics_controller_registers
+                .set_bit_mask(bit_mask);
+            self.bit_mask = Some(bit_mask);
+        }
+    }
+
+    /// Sets the set/reset color, unless it's already set to `color`.
+    pub fn set_set_reset(&mut self, color: Color16) {
+        if self.set_reset != Some(color) {
+            self.vga
+                .graphics_controller_registers
+                .write_set_reset(color);
+            self.set_reset = Some(color);
+        }
+    }
+
+    /// Sets which planes are written from the set/reset color,
+    /// unless it's already set to `bit_mask`.
+    pub fn set_enable_set_reset(&mut self, bit_mask: u8) {
+        if self.enable_set_reset != Some(bit_mask) {
+            self.vga
+                .graphics_controller_registers
+                .write_enable_set_reset(bit_mask);
+            self.enable_set_reset = Some(bit_mask);
+        }
+    }
+
+    /// Sets the plane read in read mode 0, unless it's already set to `read_plane`.
+    pub fn set_read_plane(&mut self, read_plane: ReadPlane) {
+        if self.read_plane != Some(read_plane) {
+            self.vga
+                .graphics_controller_registers
+                .write_read_plane(read_plane);
+            self.read_plane = Some(read_plane);
+        }
+    }
+
+    /// Sets the function select of the data rotate register, unless it's
+    /// already set to `raster_op`.
+    ///
+    /// The function select is reset to `RasterOp::Replace` when the
+    /// session is dropped.
+    pub fn set_function_select(&mut self, raster_op: RasterOp) {
+        if self.function_select != Some(raster_op) {
+            self.vga
+                .graphics_controller_registers
+                .set_function_select(raster_op);
+            self.function_select = Some(raster_op);
+            self.reset_function_select |= raster_op != RasterOp::Replace;
+        }
+    }
+
+    /// Sets the function select to `raster_op` for drawing. Until it's first
+    /// changed or `vga` is called, the function select is known to be
+    /// `RasterOp::Replace`, since every session resets it when dropped.
+    pub(crate) fn select_function(&mut self, raster_op: RasterOp) {
+        if self.function_select.is_some()
+            || self.reset_function_select
+            || raster_op != RasterOp::Replace
+        {
+            self.set_function_select(raster_op);
+        }
+    }
+
+    /// Reads the byte at `offset` from the start of the frame buffer,
+    /// loading the memory read latches.
+    ///
+    /// # Safety
+    ///
+    /// `offset` must be inside of the frame buffer.
+    pub unsafe fn read_memory(&mut self, offset: usize) -> u8 {
+        let address = self.frame_buffer + offset;
+        self.vga.read_memory(address)
+    }
+
+    /// Writes `value` to the byte at `offset` from the start of the frame buffer.
+    ///
+    /// # Safety
+    ///
+    /// `offset` must be inside of the frame buffer.
+    pub unsafe fn write_memory(&mut self, offset: usize, value: u8) {
+        let address = self.frame_buffer + offset;
+        self.vga.write_memory(address, value);
+    }
+
+    /// Writes `value` to `count` consecutive bytes, starting at `offset`
+    /// from the start of the frame buffer.
+    ///
+    /// # Safety
+    ///
+    /// Every byte in the range must be inside of the frame buffer.
+    pub unsafe fn fill_memory(&mut self, offset: usize, value: u8, count: usize) {
+        let address = self.frame_buffer + offset;
+        self.vga.fill_memory(address, value, count);
+    }
+
+    /// Writes the bytes of `data` to consecutive bytes, starting at `offset`
+    /// from the start of the frame buffer.
+    ///
+    /// # Safety
+    ///
+    /// Every byte in the range must be inside of the frame buffer.
+    pub unsafe fn copy_to_memory(&mut self, offset: usize, data: &[u8]) {
+        let address = self.frame_buffer + offset;
+        self.vga.copy_to_memory(address, data);
+    }
+}
+
+impl<W, I: PortIo + MemoryIo> Drop for DrawingSession<'_, W, I> {
+    fn drop(&mut self) {
+        // Everything outside of a session expects written data to
+        // replace the data in memory.
+        if self.reset_function_select && self.function_select != Some(RasterOp::Replace) {
+            self.set_function_select(RasterOp::Replace);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        colors::Color16,
+        emulator::VgaEmulator,
+        io::{MemoryIo, PortIo},
+        registers::RasterOp,
+        vga::Vga,
+        writers::{Graphics640x480x16, GraphicsWriter},
+    };
+    use core::cell::Cell;
+    use spinning_top::Spinlock;
+
+    #[derive(Clone, Copy)]
+    struct CountingIo<'a> {
+        emulator: &'a VgaEmulator,
+        port_writes: &'a Cell<usize>,
+    }
+
+    impl PortIo for CountingIo<'_> {
+        unsafe fn read_port(&mut self, port: u16) -> u8 {
+            self.emulator.read_port(port)
+        }
+
+        unsafe fn write_port(&mut self, port: u16, value: u8) {
+            self.port_writes.set(self.port_writes.get() + 1);
+            self.emulator.write_port(port, value);
+        }
+    }
+
+    impl MemoryIo for CountingIo<'_> {
+        unsafe fn read_memory(&mut self, address: usize) -> u8 {
+            self.emulator.read_memory(address)
+        }
+
+        unsafe fn write_memory(&mut self, address: usize, value: u8) {
+            self.emulator.write_memory(address, value);
+        }
+    }
+
+    #[test]
+    fn test_session_caches_registers() {
+        let emulator = VgaEmulator::new();
+        let port_writes = Cell::new(0);
+        let vga = Spinlock::new(Vga::with_io(CountingIo {
+            emulator: &emulator,
+            port_writes: &port_writes,
+        }));
+        let mode = Graphics640x480x16::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(Color16::Black);
+
+        port_writes.set(0);
+        let mut session = mode.session();
+        session.draw_line((0, 0), (7, 7), Color16::Green);
+        session.draw_line((0, 8), (7, 15), Color16::Green);
+        drop(session);
+
+        // Looking up the frame buffer selects a single index, the write mode and
+        // plane mask are each set once with a read-modify-write, while the bit
+        // mask is only written when it changes between pixels.
+        assert_eq!(port_writes.get(), 1 + 3 + 3 + 2 * 16);
+        for x in 0..8 {
+            assert_eq!(emulator.read_pixel(x, x), Color16::Green as u8);
+            assert_eq!(emulator.read_pixel(x, x + 8), Color16::Green as u8);
+        }
+        assert_eq!(emulator.read_pixel(1, 0), Color16::Black as u8);
+    }
+
+    #[test]
+    fn test_function_select_reset_after_vga() {
+        let emulator = VgaEmulator::new();
+        let vga = Spinlock::new(Vga::with_io(&emulator));
+        let mode = Graphics640x480x16::with_device(&vga);
+        mode.set_mode();
+        mode.clear_screen(Color16::Black);
+
+        let mut session = mode.session();
+        session.set_raster_op(RasterOp::Xor);
+        session.fill_rect((0, 0), (15, 0), Color16::Red);
+        session.vga();
+        drop(session);
+
+        // The next session still draws with the function select replacing pixels.
+        mode.fill_rect((0, 0), (15, 0), Color16::Blue);
+        for x in 0..16 {
+            assert_eq!(emulator.read_pixel(x, 0), Color16::Blue as u8);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use super::{Screen, TextWriter};
 use crate::{
     colors::DEFAULT_PALETTE,
     fonts::TEXT_8X16_FONT,
-    vga::{VideoMode, VGA},
+    vga::{GlobalVga, VgaDevice, VideoMode},
 };
 
 const WIDTH: usize = 40;
@@ -24,22 +24,30 @@ const SIZE: usize = WIDTH * HEIGHT;
 /// let screen_character = ScreenCharacter::new(b'T', color);
 ///
 /// text_mode.set_mode();
-/// text_mode.clear_screen();
-/// text_mode.write_character(0, 0, screen_character);
+/// text_mode.clear_screen().unwrap();
+/// text_mode.write_character(0, 0, screen_character).unwrap();
 /// ```
 #[derive(Debug, Clone, Copy, Default)]
-pub struct Text40x25;
+pub struct Text40x25<D = GlobalVga> {
+    device: D,
+}
 
-impl Screen for Text40x25 {
+impl<D> Screen for Text40x25<D> {
     const WIDTH: usize = WIDTH;
     const HEIGHT: usize = HEIGHT;
     const SIZE: usize = SIZE;
 }
 
-impl TextWriter for Text40x25 {
+impl<D: VgaDevice> TextWriter for Text40x25<D> {
+    type Device = D;
+
+    fn get_device(&self) -> &D {
+        &self.device
+    }
+
     /// Sets the graphics device to `VideoMode::Mode40x25`.
     fn set_mode(&self) {
-        let mut vga = VGA.lock();
+        let mut vga = self.device.lock();
         vga.set_video_mode(VideoMode::Mode40x25);
 
         // Some bios mess up the palette when switching modes,
@@ -52,6 +60,13 @@ impl TextWriter for Text40x25 {
 impl Text40x25 {
     /// Creates a new `Text40x25`.
     pub const fn new() -> Text40x25 {
-        Text40x25
+        Text40x25 { device: GlobalVga }
+    }
+}
+
+impl<D> Text40x25<D> {
+    /// Creates a new `Text40x25` that writes to `device`.
+    pub const fn with_device(device: D) -> Text40x25<D> {
+        Text40x25 { device }
     }
 }
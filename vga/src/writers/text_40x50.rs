@@ -2,7 +2,7 @@ use super::{Screen, TextWriter};
 use crate::{
     colors::DEFAULT_PALETTE,
     fonts::TEXT_8X8_FONT,
-    vga::{VideoMode, VGA},
+    vga::{GlobalVga, VgaDevice, VideoMode},
 };
 
 const WIDTH: usize = 40;
@@ -24,22 +24,30 @@ const SIZE: usize = WIDTH * HEIGHT;
 /// let screen_character = ScreenCharacter::new(b'T', color);
 ///
 /// text_mode.set_mode();
-/// text_mode.clear_screen();
-/// text_mode.write_character(0, 0, screen_character);
+/// text_mode.clear_screen().unwrap();
+/// text_mode.write_character(0, 0, screen_character).unwrap();
 /// ```
 #[derive(Debug, Clone, Copy, Default)]
-pub struct Text40x50;
+pub struct Text40x50<D = GlobalVga> {
+    device: D,
+}
 
-impl Screen for Text40x50 {
+impl<D> Screen for Text40x50<D> {
     const WIDTH: usize = WIDTH;
     const HEIGHT: usize = HEIGHT;
     const SIZE: usize = SIZE;
 }
 
-impl TextWriter for Text40x50 {
+impl<D: VgaDevice> TextWriter for Text40x50<D> {
+    type Device = D;
+
+    fn get_device(&self) -> &D {
+        &self.device
+    }
+
     /// Sets the graphics device to `VideoMode::Mode40x50`.
     fn set_mode(&self) {
-        let mut vga = VGA.lock();
+        let mut vga = self.device.lock();
         vga.set_video_mode(VideoMode::Mode40x50);
 
         // Some bios mess up the palette when switching modes,
@@ -52,6 +60,13 @@ impl TextWriter for Text40x50 {
 impl Text40x50 {
     /// Creates a new `Text40x50`.
     pub const fn new() -> Text40x50 {
-        Text40x50
+        Text40x50 { device: GlobalVga }
+    }
+}
+
+impl<D> Text40x50<D> {
+    /// Creates a new `Text40x50` that writes to `device`.
+    pub const fn with_device(device: D) -> Text40x50<D> {
+        Text40x50 { device }
     }
 }